@@ -0,0 +1,139 @@
+//! Abstract syntax tree produced by the parser.
+use crate::token::Span;
+
+/// A parsed `.fork` program: the top-level statements in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub stmts: Vec<Stmt>,
+}
+
+/// A statement together with the span of its first token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    /// `name = value;`
+    Assign { name: String, value: Expr },
+    /// `let name: type = value;` with the annotation optional, or a typed
+    /// `for` loop initializer such as `int i = 0`.
+    Let { name: String, ty: Option<TypeName>, value: Expr },
+    /// `name++;` or `name--;`, with `amount` being `1.0` or `-1.0`.
+    Increment { name: String, amount: f64 },
+    /// `print(a, b, ...);`
+    Print(Vec<Expr>),
+    /// `if (cond) { ... } else { ... }`. An `else if` chain is an `If`
+    /// nested as the only statement of `else_block`.
+    If { cond: Expr, then_block: Vec<Stmt>, else_block: Option<Vec<Stmt>> },
+    /// `while (cond) { ... }`
+    While { cond: Expr, body: Vec<Stmt> },
+    /// `for (init; cond; update) { ... }`. Every header part may be empty.
+    For {
+        init: Option<Box<Stmt>>,
+        cond: Option<Expr>,
+        update: Option<Box<Stmt>>,
+        body: Vec<Stmt>,
+    },
+    /// `def name(params) -> type { ... }`, only allowed at the top level. The
+    /// return annotation is optional.
+    Def { name: String, params: Vec<Param>, ret: Option<TypeName>, body: Vec<Stmt> },
+    /// `return;` or `return value;`, only allowed inside a function.
+    Return(Option<Expr>),
+    /// An expression evaluated for its side effects, such as a call.
+    Expr(Expr),
+}
+
+/// A function parameter: `name` or `name: type`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub ty: Option<TypeName>,
+    /// Span of the name.
+    pub span: Span,
+}
+
+/// A type written in an annotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeName {
+    Int,
+    Float,
+    Str,
+}
+
+impl TypeName {
+    pub fn from_name(name: &str) -> Option<TypeName> {
+        match name {
+            "int" => Some(TypeName::Int),
+            "float" => Some(TypeName::Float),
+            "str" => Some(TypeName::Str),
+            _ => None,
+        }
+    }
+
+    /// The type as written in source code.
+    pub fn name(self) -> &'static str {
+        match self {
+            TypeName::Int => "int",
+            TypeName::Float => "float",
+            TypeName::Str => "str",
+        }
+    }
+}
+
+/// An expression together with the span of its first token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Number(f64),
+    Str(String),
+    Var(String),
+    Unary { op: UnaryOp, expr: Box<Expr> },
+    Binary { op: BinOp, lhs: Box<Expr>, rhs: Box<Expr> },
+    /// `name(args)`, a call to a user-defined function.
+    Call { name: String, args: Vec<Expr> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl BinOp {
+    /// The operator as written in source code.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+        }
+    }
+}
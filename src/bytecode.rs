@@ -0,0 +1,262 @@
+//! Bytecode produced by the code generator, and its `.forkc` file encoding.
+use std::fmt;
+
+/// Magic bytes at the start of every `.forkc` file.
+const MAGIC: &[u8; 5] = b"FORKC";
+/// Bumped whenever the encoding or the instruction set changes.
+const FORMAT_VERSION: u8 = 2;
+
+/// Instruction opcodes. Operands follow the opcode byte in little-endian order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Op {
+    /// `CONST idx:u16` pushes a constant.
+    Const = 0,
+    /// `LOAD slot:u16` pushes a local variable.
+    Load,
+    /// `STORE slot:u16` pops into a local variable.
+    Store,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    /// `JUMP target:u32` continues at an absolute offset.
+    Jump,
+    /// `JUMP_IF_FALSE target:u32` pops a value and jumps if it is falsy.
+    JumpIfFalse,
+    /// `PRINT argc:u8` pops `argc` values and prints them space-separated.
+    Print,
+    /// `RETURN` pops the return value and resumes the caller.
+    Return,
+    /// `CALL function:u16 argc:u8` pops `argc` arguments, calls the function at
+    /// index `function` in the module and pushes its return value.
+    Call,
+    /// `POP` discards the top of the stack.
+    Pop,
+}
+
+impl Op {
+    const ALL: [Op; 20] = [
+        Op::Const, Op::Load, Op::Store, Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Neg, Op::Lt,
+        Op::Gt, Op::Le, Op::Ge, Op::Eq, Op::Ne, Op::Jump, Op::JumpIfFalse, Op::Print, Op::Return,
+        Op::Call, Op::Pop,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<Op> {
+        Op::ALL.get(byte as usize).copied()
+    }
+
+    /// Number of operand bytes following the opcode.
+    pub fn operand_width(self) -> usize {
+        match self {
+            Op::Const | Op::Load | Op::Store => 2,
+            Op::Jump | Op::JumpIfFalse => 4,
+            Op::Call => 3,
+            Op::Print => 1,
+            _ => 0,
+        }
+    }
+
+    /// Mnemonic used by the disassembler.
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Const => "CONST",
+            Op::Load => "LOAD",
+            Op::Store => "STORE",
+            Op::Add => "ADD",
+            Op::Sub => "SUB",
+            Op::Mul => "MUL",
+            Op::Div => "DIV",
+            Op::Neg => "NEG",
+            Op::Lt => "LT",
+            Op::Gt => "GT",
+            Op::Le => "LE",
+            Op::Ge => "GE",
+            Op::Eq => "EQ",
+            Op::Ne => "NE",
+            Op::Jump => "JUMP",
+            Op::JumpIfFalse => "JUMP_IF_FALSE",
+            Op::Print => "PRINT",
+            Op::Return => "RETURN",
+            Op::Call => "CALL",
+            Op::Pop => "POP",
+        }
+    }
+}
+
+/// A value stored in a function's constant pool.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(f64),
+    Str(String),
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Number(n) => write!(f, "{}", n),
+            Constant::Str(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// A compiled function: its code, constant pool, local names and line table.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Function {
+    pub name: String,
+    pub arity: u8,
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// Names of the local variable slots, indexed by slot number.
+    pub locals: Vec<String>,
+    /// `(offset, line)` pairs, sorted by offset: every instruction from `offset`
+    /// up to the next entry was generated from source line `line`.
+    pub lines: Vec<(usize, usize)>,
+}
+
+impl Function {
+    /// Source line of the instruction at `offset`, if known.
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        let idx = self.lines.partition_point(|&(start, _)| start <= offset);
+        idx.checked_sub(1).map(|i| self.lines[i].1)
+    }
+}
+
+/// A compiled program. The first function is the top-level `<main>` code.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub functions: Vec<Function>,
+}
+
+/// Error raised when a `.forkc` file cannot be decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError(pub String);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BytecodeError: {}", self.0)
+    }
+}
+
+impl Module {
+    /// Encodes the module in the `.forkc` file format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(FORMAT_VERSION);
+        write_u32(&mut out, self.functions.len());
+        for function in &self.functions {
+            write_str(&mut out, &function.name);
+            out.push(function.arity);
+            write_u32(&mut out, function.constants.len());
+            for constant in &function.constants {
+                match constant {
+                    Constant::Number(n) => {
+                        out.push(0);
+                        out.extend_from_slice(&n.to_le_bytes());
+                    }
+                    Constant::Str(s) => {
+                        out.push(1);
+                        write_str(&mut out, s);
+                    }
+                }
+            }
+            write_u32(&mut out, function.locals.len());
+            for local in &function.locals {
+                write_str(&mut out, local);
+            }
+            write_u32(&mut out, function.code.len());
+            out.extend_from_slice(&function.code);
+            write_u32(&mut out, function.lines.len());
+            for &(offset, line) in &function.lines {
+                write_u32(&mut out, offset);
+                write_u32(&mut out, line);
+            }
+        }
+        out
+    }
+
+    /// Decodes a module previously written by [`Module::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Module, DecodeError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(DecodeError("not a .forkc file".to_string()));
+        }
+        let version = reader.u8()?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError(format!("unsupported format version {}", version)));
+        }
+        let mut functions = Vec::new();
+        for _ in 0..reader.u32()? {
+            let name = reader.string()?;
+            let arity = reader.u8()?;
+            let mut constants = Vec::new();
+            for _ in 0..reader.u32()? {
+                constants.push(match reader.u8()? {
+                    0 => Constant::Number(f64::from_le_bytes(reader.take(8)?.try_into().unwrap())),
+                    1 => Constant::Str(reader.string()?),
+                    tag => return Err(DecodeError(format!("unknown constant tag {}", tag))),
+                });
+            }
+            let mut locals = Vec::new();
+            for _ in 0..reader.u32()? {
+                locals.push(reader.string()?);
+            }
+            let code_len = reader.u32()?;
+            let code = reader.take(code_len)?.to_vec();
+            let mut lines = Vec::new();
+            for _ in 0..reader.u32()? {
+                lines.push((reader.u32()?, reader.u32()?));
+            }
+            functions.push(Function { name, arity, code, constants, locals, lines });
+        }
+        if reader.pos != bytes.len() {
+            return Err(DecodeError("trailing bytes after last function".to_string()));
+        }
+        Ok(Module { functions })
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_u32(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| DecodeError("unexpected end of file".to_string()))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u32()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| DecodeError("invalid UTF-8 in string".to_string()))
+    }
+}
@@ -0,0 +1,196 @@
+//! Passes over calls between functions: inlining small functions into loops,
+//! and turning self-recursive tail calls into jumps.
+use std::collections::HashMap;
+use crate::ir::{Instr, InstrKind, IrFunction, IrProgram, Label, Operand, Var, PRINT};
+
+/// Largest callee, in instructions, whose body is copied into a call site.
+const MAX_INLINE_SIZE: usize = 16;
+
+/// Replaces calls made inside loops with a copy of the callee's body, when the
+/// callee is small and calls no other function. The loop no longer pays for a
+/// call on every iteration, and the loop passes can see the callee's arithmetic.
+pub fn inline_calls(program: &mut IrProgram) -> bool {
+    let candidates: HashMap<String, IrFunction> = program
+        .functions
+        .iter()
+        .skip(1)
+        .filter(|function| is_inlinable(function))
+        .map(|function| (function.name.clone(), function.clone()))
+        .collect();
+    let mut changed = false;
+    for function in &mut program.functions {
+        let loops = loop_ranges(&function.body);
+        let mut fresh = Fresh::new(function);
+        let mut body = Vec::with_capacity(function.body.len());
+        for (idx, instr) in std::mem::take(&mut function.body).into_iter().enumerate() {
+            let in_loop = loops.iter().any(|&(start, end)| start < idx && idx < end);
+            match instr.kind {
+                InstrKind::Call { dest, func, args } if in_loop && candidates.contains_key(&func) => {
+                    expand(&candidates[&func], dest, args, instr.line, &mut fresh, &mut body);
+                    changed = true;
+                }
+                kind => body.push(Instr { kind, line: instr.line }),
+            }
+        }
+        function.body = body;
+    }
+    changed
+}
+
+/// A function is inlined if it is small and makes no calls besides `print`.
+fn is_inlinable(function: &IrFunction) -> bool {
+    let leaf = function.body.iter().all(|instr| match &instr.kind {
+        InstrKind::Call { func, .. } => func == PRINT,
+        _ => true,
+    });
+    let size = function.body.iter().filter(|instr| !matches!(instr.kind, InstrKind::Label(_))).count();
+    leaf && size <= MAX_INLINE_SIZE
+}
+
+/// Instruction ranges `(label, jump)` between a label and a later jump back to
+/// it. The lowering emits every loop this way, so these are the loop bodies.
+fn loop_ranges(body: &[Instr]) -> Vec<(usize, usize)> {
+    let mut labels = HashMap::new();
+    let mut ranges = Vec::new();
+    for (idx, instr) in body.iter().enumerate() {
+        match &instr.kind {
+            InstrKind::Label(label) => {
+                labels.insert(*label, idx);
+            }
+            InstrKind::Jump(target) | InstrKind::JumpIfFalse { target, .. } => {
+                if let Some(&start) = labels.get(target) {
+                    ranges.push((start, idx));
+                }
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// Appends a copy of `callee` to `out` in place of `dest = call callee(args)`.
+/// Every callee variable becomes a fresh temporary of the caller, and every
+/// `return` stores its value in `dest` and jumps past the copy.
+fn expand(callee: &IrFunction, dest: Option<Var>, args: Vec<Operand>, line: usize, fresh: &mut Fresh, out: &mut Vec<Instr>) {
+    let mut vars: HashMap<Var, Var> = HashMap::new();
+    let mut labels: HashMap<Label, Label> = HashMap::new();
+    for (param, arg) in callee.params.iter().zip(args) {
+        let var = fresh.temp();
+        vars.insert(Var::Named(param.clone()), var.clone());
+        out.push(Instr { kind: InstrKind::Copy { dest: var, src: arg }, line });
+    }
+    let end = fresh.label();
+    for instr in &callee.body {
+        let mut kind = instr.kind.clone();
+        let mut rename = |var: &mut Var| *var = vars.entry(var.clone()).or_insert_with(|| fresh.temp()).clone();
+        if let Some(var) = kind.dest_mut() {
+            rename(var);
+        }
+        for operand in kind.operands_mut() {
+            if let Operand::Var(var) = operand {
+                rename(var);
+            }
+        }
+        match &mut kind {
+            InstrKind::Label(label) | InstrKind::Jump(label) | InstrKind::JumpIfFalse { target: label, .. } => {
+                *label = *labels.entry(*label).or_insert_with(|| fresh.label());
+            }
+            InstrKind::Return(value) => {
+                if let Some(dest) = &dest {
+                    let src = value.take().unwrap_or(Operand::Number(0.0));
+                    out.push(Instr { kind: InstrKind::Copy { dest: dest.clone(), src }, line: instr.line });
+                }
+                kind = InstrKind::Jump(end);
+            }
+            _ => {}
+        }
+        out.push(Instr { kind, line: instr.line });
+    }
+    out.push(Instr { kind: InstrKind::Label(end), line });
+}
+
+/// Turns `t = call f(args); return t` inside `f` itself into assignments to
+/// the parameters and a jump back to the start, so a tail-recursive function
+/// runs in a loop instead of hitting the recursion limit.
+pub fn eliminate_tail_calls(program: &mut IrProgram) -> bool {
+    let mut changed = false;
+    for function in program.functions.iter_mut().skip(1) {
+        changed |= eliminate_self_tail_calls(function);
+    }
+    changed
+}
+
+fn eliminate_self_tail_calls(function: &mut IrFunction) -> bool {
+    let is_tail_call = |call: &Instr, next: Option<&Instr>| match (&call.kind, next.map(|next| &next.kind)) {
+        (InstrKind::Call { dest: Some(dest), func, .. }, Some(InstrKind::Return(Some(Operand::Var(value))))) => {
+            *func == function.name && dest == value
+        }
+        _ => false,
+    };
+    if !function.body.windows(2).any(|pair| is_tail_call(&pair[0], Some(&pair[1]))) {
+        return false;
+    }
+    let mut fresh = Fresh::new(function);
+    let start = fresh.label();
+    let mut body = vec![Instr { kind: InstrKind::Label(start), line: function.body.first().map_or(0, |instr| instr.line) }];
+    let mut instrs = function.body.iter().peekable();
+    while let Some(instr) = instrs.next() {
+        if !is_tail_call(instr, instrs.peek().copied()) {
+            body.push(instr.clone());
+            continue;
+        }
+        instrs.next();
+        let InstrKind::Call { args, .. } = &instr.kind else { unreachable!() };
+        // Arguments may read the parameters, so evaluate them all before assigning any
+        let mut temps = Vec::with_capacity(args.len());
+        for arg in args {
+            let temp = fresh.temp();
+            body.push(Instr { kind: InstrKind::Copy { dest: temp.clone(), src: arg.clone() }, line: instr.line });
+            temps.push(temp);
+        }
+        for (param, temp) in function.params.iter().zip(temps) {
+            let kind = InstrKind::Copy { dest: Var::Named(param.clone()), src: Operand::Var(temp) };
+            body.push(Instr { kind, line: instr.line });
+        }
+        body.push(Instr { kind: InstrKind::Jump(start), line: instr.line });
+    }
+    function.body = body;
+    true
+}
+
+/// Hands out temporaries and labels not yet used in a function.
+struct Fresh {
+    next_temp: u32,
+    next_label: u32,
+}
+
+impl Fresh {
+    fn new(function: &IrFunction) -> Self {
+        let mut fresh = Fresh { next_temp: 0, next_label: 0 };
+        for instr in &function.body {
+            if let InstrKind::Label(Label(n)) = instr.kind {
+                fresh.next_label = fresh.next_label.max(n + 1);
+            }
+            let vars = instr.kind.dest().into_iter().chain(instr.kind.operands().into_iter().filter_map(|operand| match operand {
+                Operand::Var(var) => Some(var),
+                _ => None,
+            }));
+            for var in vars {
+                if let Var::Temp(n) = var {
+                    fresh.next_temp = fresh.next_temp.max(n + 1);
+                }
+            }
+        }
+        fresh
+    }
+
+    fn temp(&mut self) -> Var {
+        self.next_temp += 1;
+        Var::Temp(self.next_temp - 1)
+    }
+
+    fn label(&mut self) -> Label {
+        self.next_label += 1;
+        Label(self.next_label - 1)
+    }
+}
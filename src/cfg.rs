@@ -0,0 +1,567 @@
+//! Basic blocks, control-flow graphs and dominator trees over the IR.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Write};
+use crate::ir::{Instr, InstrKind, IrFunction, Label, Operand, Var};
+
+pub type BlockId = usize;
+
+/// A function's IR split into basic blocks. Block 0 is the entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg {
+    pub name: String,
+    pub params: Vec<String>,
+    pub blocks: Vec<Block>,
+}
+
+/// A straight-line run of instructions ending in a single terminator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// Phi nodes, only present while the graph is in SSA form.
+    pub phis: Vec<Phi>,
+    /// Body instructions. Never contains labels, jumps or returns.
+    pub instrs: Vec<Instr>,
+    pub terminator: Terminator,
+    /// Source line the terminator was lowered from.
+    pub terminator_line: usize,
+    /// Predecessor blocks, without duplicates.
+    pub preds: Vec<BlockId>,
+}
+
+/// `dest = phi(pred: value, ...)`, selecting a value by the edge control arrived on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phi {
+    pub dest: Var,
+    pub args: Vec<(BlockId, Operand)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Continues at `then_block` if `cond` is truthy, else at `else_block`.
+    Branch { cond: Operand, then_block: BlockId, else_block: BlockId },
+    Return(Option<Operand>),
+}
+
+impl Terminator {
+    /// Successor blocks, without duplicates.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then_block, else_block, .. } if then_block == else_block => vec![*then_block],
+            Terminator::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch { then_block, else_block, .. } => vec![then_block, else_block],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    /// The value the terminator reads: a branch condition or a returned value.
+    pub fn operand(&self) -> Option<&Operand> {
+        match self {
+            Terminator::Branch { cond, .. } | Terminator::Return(Some(cond)) => Some(cond),
+            Terminator::Jump(_) | Terminator::Return(None) => None,
+        }
+    }
+
+    pub fn operand_mut(&mut self) -> Option<&mut Operand> {
+        match self {
+            Terminator::Branch { cond, .. } | Terminator::Return(Some(cond)) => Some(cond),
+            Terminator::Jump(_) | Terminator::Return(None) => None,
+        }
+    }
+}
+
+/// A natural loop: the blocks that can reach a back edge to `header` without
+/// passing through it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub header: BlockId,
+    /// Sources of the back edges into the header.
+    pub latches: Vec<BlockId>,
+    /// Every block in the loop, including the header.
+    pub blocks: BTreeSet<BlockId>,
+}
+
+/// Instructions collected between two block boundaries while splitting a function.
+#[derive(Default)]
+struct RawBlock {
+    labels: Vec<Label>,
+    instrs: Vec<Instr>,
+    end: Option<Instr>,
+}
+
+impl Cfg {
+    /// Splits a function into basic blocks at labels and after every jump or return.
+    pub fn from_function(function: &IrFunction) -> Cfg {
+        let mut raw = vec![RawBlock::default()];
+        for instr in &function.body {
+            let current = raw.last_mut().unwrap();
+            match instr.kind {
+                InstrKind::Label(label) => {
+                    if current.end.is_some() || !current.instrs.is_empty() {
+                        raw.push(RawBlock::default());
+                    }
+                    raw.last_mut().unwrap().labels.push(label);
+                }
+                InstrKind::Jump(_) | InstrKind::JumpIfFalse { .. } | InstrKind::Return(_) => {
+                    if current.end.is_some() {
+                        raw.push(RawBlock::default());
+                    }
+                    raw.last_mut().unwrap().end = Some(instr.clone());
+                }
+                _ => {
+                    if current.end.is_some() {
+                        raw.push(RawBlock::default());
+                    }
+                    raw.last_mut().unwrap().instrs.push(instr.clone());
+                }
+            }
+        }
+        // The entry block must not be a jump target, so a leading label gets a block of its own
+        if !raw[0].labels.is_empty() {
+            raw.insert(0, RawBlock::default());
+        }
+        if raw.len() > 1 && raw.last().is_some_and(|b| b.labels.is_empty() && b.instrs.is_empty() && b.end.is_none()) {
+            raw.pop();
+        }
+        // A conditional jump at the very end needs a block to fall through to
+        if raw.last().is_some_and(|b| matches!(b.end.as_ref().map(|e| &e.kind), Some(InstrKind::JumpIfFalse { .. }))) {
+            raw.push(RawBlock::default());
+        }
+        let label_blocks: HashMap<Label, BlockId> = raw
+            .iter()
+            .enumerate()
+            .flat_map(|(id, block)| block.labels.iter().map(move |&label| (label, id)))
+            .collect();
+        let count = raw.len();
+        let mut blocks: Vec<Block> = raw
+            .into_iter()
+            .enumerate()
+            .map(|(id, block)| {
+                let next = if id + 1 < count { Terminator::Jump(id + 1) } else { Terminator::Return(None) };
+                let line = block.end.as_ref().or(block.instrs.last()).map_or(0, |i| i.line);
+                let terminator = match block.end.map(|e| e.kind) {
+                    Some(InstrKind::Jump(target)) => Terminator::Jump(label_blocks[&target]),
+                    Some(InstrKind::JumpIfFalse { cond, target }) => Terminator::Branch {
+                        cond,
+                        then_block: id + 1,
+                        else_block: label_blocks[&target],
+                    },
+                    Some(InstrKind::Return(value)) => Terminator::Return(value),
+                    Some(_) => unreachable!("block ends in a non-terminator"),
+                    None => next,
+                };
+                Block { phis: Vec::new(), instrs: block.instrs, terminator, terminator_line: line, preds: Vec::new() }
+            })
+            .collect();
+        for id in 0..blocks.len() {
+            for succ in blocks[id].terminator.successors() {
+                blocks[succ].preds.push(id);
+            }
+        }
+        Cfg { name: function.name.clone(), params: function.params.clone(), blocks }
+    }
+
+    /// Lays the blocks out in order, using fallthrough where possible. The graph
+    /// must not contain phi nodes.
+    pub fn to_function(&self) -> IrFunction {
+        debug_assert!(self.blocks.iter().all(|b| b.phis.is_empty()), "linearizing a CFG in SSA form");
+        let mut targets = BTreeSet::new();
+        for (id, block) in self.blocks.iter().enumerate() {
+            match block.terminator {
+                Terminator::Jump(target) if target != id + 1 => {
+                    targets.insert(target);
+                }
+                Terminator::Branch { then_block, else_block, .. } => {
+                    targets.insert(else_block);
+                    if then_block != id + 1 {
+                        targets.insert(then_block);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let label = |id: BlockId| Label(id as u32);
+        let mut body = Vec::new();
+        for (id, block) in self.blocks.iter().enumerate() {
+            let line = block.instrs.first().map_or(block.terminator_line, |i| i.line);
+            if targets.contains(&id) {
+                body.push(Instr { kind: InstrKind::Label(label(id)), line });
+            }
+            body.extend(block.instrs.iter().cloned());
+            let line = block.terminator_line;
+            match &block.terminator {
+                Terminator::Jump(target) => {
+                    if *target != id + 1 {
+                        body.push(Instr { kind: InstrKind::Jump(label(*target)), line });
+                    }
+                }
+                Terminator::Branch { cond, then_block, else_block } => {
+                    body.push(Instr { kind: InstrKind::JumpIfFalse { cond: cond.clone(), target: label(*else_block) }, line });
+                    if *then_block != id + 1 {
+                        body.push(Instr { kind: InstrKind::Jump(label(*then_block)), line });
+                    }
+                }
+                Terminator::Return(value) => body.push(Instr { kind: InstrKind::Return(value.clone()), line }),
+            }
+        }
+        IrFunction { name: self.name.clone(), params: self.params.clone(), body }
+    }
+
+    /// Successors of a block.
+    pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+        self.blocks[id].terminator.successors()
+    }
+
+    /// Reachable blocks in reverse postorder, starting with the entry.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = Vec::new();
+        // Iterative DFS: (block, index of the next successor to visit)
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some(top) = stack.last_mut() {
+            let (block, next) = *top;
+            if let Some(&succ) = self.successors(block).get(next) {
+                top.1 += 1;
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                order.push(block);
+                stack.pop();
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// Deletes blocks that cannot be reached from the entry, renumbering the rest.
+    /// Returns true if anything was removed.
+    pub fn remove_unreachable(&mut self) -> bool {
+        let mut reachable = vec![false; self.blocks.len()];
+        for id in self.reverse_postorder() {
+            reachable[id] = true;
+        }
+        if reachable.iter().all(|&r| r) {
+            return false;
+        }
+        let mut new_ids = vec![usize::MAX; self.blocks.len()];
+        let mut next = 0;
+        for (id, &keep) in reachable.iter().enumerate() {
+            if keep {
+                new_ids[id] = next;
+                next += 1;
+            }
+        }
+        let old_blocks = std::mem::take(&mut self.blocks);
+        for (id, mut block) in old_blocks.into_iter().enumerate() {
+            if !reachable[id] {
+                continue;
+            }
+            for succ in block.terminator.successors_mut() {
+                *succ = new_ids[*succ];
+            }
+            block.preds.retain(|&p| reachable[p]);
+            for pred in &mut block.preds {
+                *pred = new_ids[*pred];
+            }
+            for phi in &mut block.phis {
+                phi.args.retain(|&(p, _)| reachable[p]);
+                for (pred, _) in &mut phi.args {
+                    *pred = new_ids[*pred];
+                }
+            }
+            self.blocks.push(block);
+        }
+        true
+    }
+
+    /// Recomputes every block's predecessor list from the terminators.
+    pub fn recompute_preds(&mut self) {
+        for block in &mut self.blocks {
+            block.preds.clear();
+        }
+        for id in 0..self.blocks.len() {
+            for succ in self.successors(id) {
+                self.blocks[succ].preds.push(id);
+            }
+        }
+    }
+
+    /// Calls `f` on every operand read anywhere in the graph: instruction
+    /// operands, branch conditions and phi arguments.
+    pub fn for_each_use_mut(&mut self, mut f: impl FnMut(&mut Operand)) {
+        for block in &mut self.blocks {
+            for phi in &mut block.phis {
+                for (_, value) in &mut phi.args {
+                    f(value);
+                }
+            }
+            for instr in &mut block.instrs {
+                for operand in instr.kind.operands_mut() {
+                    f(operand);
+                }
+            }
+            if let Some(operand) = block.terminator.operand_mut() {
+                f(operand);
+            }
+        }
+    }
+
+    /// Finds the natural loops of reachable blocks, merging loops that share a
+    /// header. Inner loops come before the loops that contain them.
+    pub fn natural_loops(&self, dominators: &Dominators) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = Vec::new();
+        for latch in 0..self.blocks.len() {
+            for header in self.successors(latch) {
+                if !dominators.dominates(header, latch) {
+                    continue;
+                }
+                let index = match loops.iter().position(|l| l.header == header) {
+                    Some(index) => index,
+                    None => {
+                        loops.push(Loop { header, latches: Vec::new(), blocks: BTreeSet::from([header]) });
+                        loops.len() - 1
+                    }
+                };
+                let lp = &mut loops[index];
+                lp.latches.push(latch);
+                let mut worklist = vec![latch];
+                while let Some(block) = worklist.pop() {
+                    if lp.blocks.insert(block) {
+                        worklist.extend(self.blocks[block].preds.iter().copied());
+                    }
+                }
+            }
+        }
+        loops.sort_by_key(|l| l.blocks.len());
+        loops
+    }
+
+    /// Redirects the edge `from -> to` through a new empty block, returning its id.
+    pub fn split_edge(&mut self, from: BlockId, to: BlockId) -> BlockId {
+        let new = self.blocks.len();
+        let line = self.blocks[from].terminator_line;
+        self.blocks.push(Block {
+            phis: Vec::new(),
+            instrs: Vec::new(),
+            terminator: Terminator::Jump(to),
+            terminator_line: line,
+            preds: vec![from],
+        });
+        for succ in self.blocks[from].terminator.successors_mut() {
+            if *succ == to {
+                *succ = new;
+            }
+        }
+        let target = &mut self.blocks[to];
+        for pred in &mut target.preds {
+            if *pred == from {
+                *pred = new;
+            }
+        }
+        for phi in &mut target.phis {
+            for (pred, _) in &mut phi.args {
+                if *pred == from {
+                    *pred = new;
+                }
+            }
+        }
+        new
+    }
+
+    /// Renders the graph in Graphviz DOT syntax as a cluster named after the function.
+    /// `index` keeps node names unique when several functions share one graph.
+    pub fn to_dot(&self, out: &mut String, index: usize, dominators: &Dominators) {
+        let prefix = format!("f{}", index);
+        let _ = writeln!(out, "  subgraph \"cluster_{}\" {{", escape_dot(&self.name));
+        let _ = writeln!(out, "    label=\"{}\";", escape_dot(&self.name));
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("B{}", id);
+            if let Some(idom) = dominators.idom(id) {
+                let _ = write!(label, "  (idom B{})", idom);
+            }
+            label.push_str("\\l");
+            for line in block_lines(block) {
+                let _ = write!(label, "{}\\l", escape_dot(&line));
+            }
+            let _ = writeln!(out, "    {}_{} [shape=box, fontname=monospace, label=\"{}\"];", prefix, id, label);
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            match block.terminator {
+                Terminator::Jump(target) => {
+                    let _ = writeln!(out, "    {}_{} -> {}_{};", prefix, id, prefix, target);
+                }
+                Terminator::Branch { then_block, else_block, .. } => {
+                    let _ = writeln!(out, "    {}_{} -> {}_{} [label=\"T\"];", prefix, id, prefix, then_block);
+                    let _ = writeln!(out, "    {}_{} -> {}_{} [label=\"F\"];", prefix, id, prefix, else_block);
+                }
+                Terminator::Return(_) => {}
+            }
+        }
+        let _ = writeln!(out, "  }}");
+    }
+}
+
+/// Text of a block's phis, instructions and terminator, one entry per line.
+fn block_lines(block: &Block) -> Vec<String> {
+    let mut lines: Vec<String> = block.phis.iter().map(|phi| phi.to_string()).collect();
+    lines.extend(block.instrs.iter().map(|instr| instr.kind.to_string()));
+    lines.push(block.terminator.to_string());
+    lines
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dominators = Dominators::compute(self);
+        writeln!(f, "function {}({}):", self.name, self.params.join(", "))?;
+        for (id, block) in self.blocks.iter().enumerate() {
+            let preds: Vec<String> = block.preds.iter().map(|p| format!("B{}", p)).collect();
+            let idom = dominators.idom(id).map_or("-".to_string(), |d| format!("B{}", d));
+            writeln!(f, "B{}:    ; preds: [{}], idom: {}", id, preds.join(", "), idom)?;
+            for line in block_lines(block) {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Phi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let args: Vec<String> = self.args.iter().map(|(pred, value)| format!("B{}: {}", pred, value)).collect();
+        write!(f, "{} = phi({})", self.dest, args.join(", "))
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "goto B{}", target),
+            Terminator::Branch { cond, then_block, else_block } => {
+                write!(f, "if {} goto B{} else B{}", cond, then_block, else_block)
+            }
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+        }
+    }
+}
+
+/// Immediate dominators of every reachable block.
+#[derive(Debug, Clone)]
+pub struct Dominators {
+    /// `idom[entry] == Some(entry)`; unreachable blocks have `None`.
+    idom: Vec<Option<BlockId>>,
+}
+
+impl Dominators {
+    /// Computes dominators with the Cooper-Harvey-Kennedy iterative algorithm.
+    pub fn compute(cfg: &Cfg) -> Dominators {
+        let rpo = cfg.reverse_postorder();
+        let mut rpo_index = vec![usize::MAX; cfg.blocks.len()];
+        for (idx, &block) in rpo.iter().enumerate() {
+            rpo_index[block] = idx;
+        }
+        let mut idom: Vec<Option<BlockId>> = vec![None; cfg.blocks.len()];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for &pred in &cfg.blocks[block].preds {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(current) => {
+                            let (mut a, mut b) = (pred, current);
+                            while a != b {
+                                while rpo_index[a] > rpo_index[b] {
+                                    a = idom[a].unwrap();
+                                }
+                                while rpo_index[b] > rpo_index[a] {
+                                    b = idom[b].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if new_idom.is_some() && idom[block] != new_idom {
+                    idom[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        Dominators { idom }
+    }
+
+    /// Immediate dominator of a block; `None` for the entry and unreachable blocks.
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.idom[block].filter(|&d| d != block)
+    }
+
+    /// Returns true if every path from the entry to `b` passes through `a`.
+    /// Unreachable blocks dominate nothing and are dominated by nothing.
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        if self.idom[a].is_none() {
+            return false;
+        }
+        let mut current = b;
+        loop {
+            if current == a {
+                return true;
+            }
+            match self.idom[current] {
+                Some(parent) if parent != current => current = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Children of every block in the dominator tree.
+    pub fn tree(&self) -> Vec<Vec<BlockId>> {
+        let mut children = vec![Vec::new(); self.idom.len()];
+        for block in 0..self.idom.len() {
+            if let Some(parent) = self.idom(block) {
+                children[parent].push(block);
+            }
+        }
+        children
+    }
+
+    /// Dominance frontier of every block: where its dominance ends.
+    pub fn frontiers(&self, cfg: &Cfg) -> Vec<BTreeSet<BlockId>> {
+        let mut frontiers = vec![BTreeSet::new(); cfg.blocks.len()];
+        for (block, data) in cfg.blocks.iter().enumerate() {
+            if data.preds.len() < 2 || self.idom[block].is_none() {
+                continue;
+            }
+            for &pred in &data.preds {
+                let mut runner = pred;
+                while self.idom[runner].is_some() && Some(runner) != self.idom(block) {
+                    frontiers[runner].insert(block);
+                    match self.idom(runner) {
+                        Some(parent) => runner = parent,
+                        None => break,
+                    }
+                }
+            }
+        }
+        frontiers
+    }
+}
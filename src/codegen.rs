@@ -1,5 +1,9 @@
-use crate::token::Token;
+use std::collections::HashMap;
+use crate::ast::{BinOp, UnaryOp};
+use crate::bytecode::{Constant, Function, Module, Op};
+use crate::ir::{InstrKind, IrFunction, IrProgram, Label, Operand, Var, PRINT};
 
+/// Compiles three-address IR into stack-machine bytecode.
 pub struct CodeGenerator;
 
 impl CodeGenerator {
@@ -7,12 +11,215 @@ impl CodeGenerator {
         CodeGenerator
     }
 
-    pub fn generate(&self, tokens: &[Token]) {
-        // For demonstration, just print a stub for real code generation
+    /// Generates one bytecode function per IR function.
+    pub fn generate(&self, program: &IrProgram) -> Module {
+        let indices: HashMap<&str, u16> =
+            program.functions.iter().enumerate().map(|(idx, f)| (f.name.as_str(), idx as u16)).collect();
+        Module { functions: program.functions.iter().map(|f| generate_function(f, &indices)).collect() }
+    }
+}
+
+impl Default for CodeGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn generate_function(ir: &IrFunction, indices: &HashMap<&str, u16>) -> Function {
+    let mut builder = FunctionBuilder::new(&ir.name, indices);
+    for param in &ir.params {
+        builder.local(&Var::Named(param.clone()));
+    }
+    builder.function.arity = ir.params.len() as u8;
+    for instr in &ir.body {
+        for operand in instr.kind.operands() {
+            if let Operand::Var(Var::Temp(n)) = operand {
+                *builder.temp_uses.entry(*n).or_insert(0) += 1;
+            }
+        }
+    }
+    for (idx, instr) in ir.body.iter().enumerate() {
+        builder.line = instr.line;
+        builder.next_first_operand = ir.body.get(idx + 1).and_then(|next| next.kind.operands().first().cloned().cloned());
+        builder.instr(&instr.kind);
+    }
+    for (operand, label) in std::mem::take(&mut builder.pending_jumps) {
+        let target = builder.labels[&label] as u32;
+        builder.function.code[operand..operand + 4].copy_from_slice(&target.to_le_bytes());
+    }
+    builder.function
+}
+
+/// Accumulates the code of one function while walking its instructions.
+struct FunctionBuilder<'a> {
+    function: Function,
+    /// Index of every function in the module, for `CALL`.
+    indices: &'a HashMap<&'a str, u16>,
+    line: usize,
+    /// Number of reads of each temporary in the function.
+    temp_uses: HashMap<u32, usize>,
+    /// First operand pushed by the instruction after the current one.
+    next_first_operand: Option<Operand>,
+    /// A single-use temporary whose value was left on the stack for the next instruction.
+    stacked_temp: Option<u32>,
+    /// Offset of every label seen so far.
+    labels: HashMap<Label, usize>,
+    /// Jump operand offsets waiting for their label's offset.
+    pending_jumps: Vec<(usize, Label)>,
+}
+
+impl<'a> FunctionBuilder<'a> {
+    fn new(name: &str, indices: &'a HashMap<&'a str, u16>) -> Self {
+        FunctionBuilder {
+            function: Function { name: name.to_string(), ..Function::default() },
+            indices,
+            line: 0,
+            temp_uses: HashMap::new(),
+            next_first_operand: None,
+            stacked_temp: None,
+            labels: HashMap::new(),
+            pending_jumps: Vec::new(),
+        }
+    }
+
+    fn instr(&mut self, kind: &InstrKind) {
+        match kind {
+            InstrKind::Copy { dest, src } => {
+                self.operand(src);
+                self.store(dest);
+            }
+            InstrKind::Unary { dest, op: UnaryOp::Neg, src } => {
+                self.operand(src);
+                self.emit(Op::Neg);
+                self.store(dest);
+            }
+            InstrKind::Binary { dest, op, lhs, rhs } => {
+                self.operand(lhs);
+                self.operand(rhs);
+                self.emit(match op {
+                    BinOp::Add => Op::Add,
+                    BinOp::Sub => Op::Sub,
+                    BinOp::Mul => Op::Mul,
+                    BinOp::Div => Op::Div,
+                    BinOp::Lt => Op::Lt,
+                    BinOp::Gt => Op::Gt,
+                    BinOp::Le => Op::Le,
+                    BinOp::Ge => Op::Ge,
+                    BinOp::Eq => Op::Eq,
+                    BinOp::Ne => Op::Ne,
+                });
+                self.store(dest);
+            }
+            InstrKind::Label(label) => {
+                self.labels.insert(*label, self.function.code.len());
+            }
+            InstrKind::Jump(target) => self.emit_jump(Op::Jump, *target),
+            InstrKind::JumpIfFalse { cond, target } => {
+                self.operand(cond);
+                self.emit_jump(Op::JumpIfFalse, *target);
+            }
+            InstrKind::Call { func, args, .. } if func == PRINT => {
+                for arg in args {
+                    self.operand(arg);
+                }
+                self.emit(Op::Print);
+                self.function.code.push(args.len() as u8);
+            }
+            InstrKind::Call { dest, func, args } => {
+                for arg in args {
+                    self.operand(arg);
+                }
+                let idx = *self.indices.get(func.as_str()).unwrap_or_else(|| unreachable!("call to unknown function '{}'", func));
+                self.emit_u16(Op::Call, idx);
+                self.function.code.push(args.len() as u8);
+                match dest {
+                    Some(dest) => self.store(dest),
+                    None => self.emit(Op::Pop),
+                }
+            }
+            InstrKind::Return(value) => {
+                match value {
+                    Some(value) => self.operand(value),
+                    None => self.operand(&Operand::Number(0.0)),
+                }
+                self.emit(Op::Return);
+            }
+        }
+    }
+
+    /// Pushes the value of an operand.
+    fn operand(&mut self, operand: &Operand) {
+        match operand {
+            Operand::Number(n) => {
+                let idx = self.constant(Constant::Number(*n));
+                self.emit_u16(Op::Const, idx);
+            }
+            Operand::Str(s) => {
+                let idx = self.constant(Constant::Str(s.clone()));
+                self.emit_u16(Op::Const, idx);
+            }
+            Operand::Var(Var::Temp(n)) if self.stacked_temp == Some(*n) => {
+                self.stacked_temp = None;
+            }
+            Operand::Var(var) => {
+                let slot = self.local(var);
+                self.emit_u16(Op::Load, slot);
+            }
+        }
+    }
+
+    /// Pops the top of the stack into `dest`, unless `dest` is a temporary read only
+    /// by the next instruction, which can then take the value straight off the stack.
+    fn store(&mut self, dest: &Var) {
+        if let Var::Temp(n) = dest
+            && self.temp_uses.get(n) == Some(&1)
+            && self.next_first_operand == Some(Operand::Var(dest.clone()))
+        {
+            self.stacked_temp = Some(*n);
+            return;
+        }
+        let slot = self.local(dest);
+        self.emit_u16(Op::Store, slot);
+    }
+
+    /// Returns the slot of a variable or temporary, allocating one on first use.
+    fn local(&mut self, var: &Var) -> u16 {
+        let name = var.to_string();
+        let locals = &mut self.function.locals;
+        let slot = locals.iter().position(|l| *l == name).unwrap_or_else(|| {
+            locals.push(name);
+            locals.len() - 1
+        });
+        slot as u16
+    }
 
-        for (_i, _token) in tokens.iter().enumerate() {
+    /// Returns the index of a constant, reusing an identical existing entry.
+    fn constant(&mut self, constant: Constant) -> u16 {
+        let constants = &mut self.function.constants;
+        let idx = constants.iter().position(|c| *c == constant).unwrap_or_else(|| {
+            constants.push(constant);
+            constants.len() - 1
+        });
+        idx as u16
+    }
 
+    fn emit(&mut self, op: Op) {
+        let offset = self.function.code.len();
+        if self.function.lines.last().map(|&(_, line)| line) != Some(self.line) {
+            self.function.lines.push((offset, self.line));
         }
+        self.function.code.push(op as u8);
+    }
+
+    fn emit_u16(&mut self, op: Op, operand: u16) {
+        self.emit(op);
+        self.function.code.extend_from_slice(&operand.to_le_bytes());
+    }
 
+    /// Emits a jump whose target offset is filled in once the whole function is generated.
+    fn emit_jump(&mut self, op: Op, target: Label) {
+        self.emit(op);
+        self.pending_jumps.push((self.function.code.len(), target));
+        self.function.code.extend_from_slice(&[0; 4]);
     }
 }
@@ -0,0 +1,143 @@
+//! Project settings read from a `fork.toml` file.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the settings file, looked up from a source file's directory upwards.
+pub const CONFIG_FILE: &str = "fork.toml";
+
+/// A setting's value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// One `key = value` line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+/// A malformed or invalid setting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub message: String,
+    pub line: usize,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+/// The settings of a `fork.toml`: `[section]` tables of `key = value`
+/// entries. This is the subset of TOML the settings need: values are basic
+/// strings, integers or booleans, and `#` starts a comment.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Config {
+    /// Where the settings were read from, or `None` for the defaults.
+    pub path: Option<PathBuf>,
+    sections: HashMap<String, Vec<Entry>>,
+}
+
+impl Config {
+    /// Loads the `fork.toml` closest to `source`, searching its directory and
+    /// then each parent. Without one, every setting keeps its default.
+    pub fn for_file(source: &Path) -> Result<Config, ConfigError> {
+        let dir = match source.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let dir = dir.canonicalize().unwrap_or(dir);
+        match dir.ancestors().map(|dir| dir.join(CONFIG_FILE)).find(|path| path.is_file()) {
+            Some(path) => Config::load(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError { path: path.to_path_buf(), message: e.to_string(), line: 0 })?;
+        Config::parse(&text).map(|config| Config { path: Some(path.to_path_buf()), ..config }).map_err(|(message, line)| ConfigError {
+            path: path.to_path_buf(),
+            message,
+            line,
+        })
+    }
+
+    fn parse(text: &str) -> Result<Config, (String, usize)> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (idx, line) in text.lines().enumerate() {
+            let number = idx + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name.strip_suffix(']').ok_or(("expected ']' after section name".to_string(), number))?;
+                section = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or(("expected 'key = value'".to_string(), number))?;
+            let key = key.trim().trim_matches('"').to_string();
+            let value = parse_value(value.trim()).ok_or(("expected a string, integer or boolean".to_string(), number))?;
+            let entries = config.sections.entry(section.clone()).or_default();
+            if entries.iter().any(|entry| entry.key == key) {
+                return Err((format!("duplicate key '{}'", key), number));
+            }
+            entries.push(Entry { key, value, line: number });
+        }
+        Ok(config)
+    }
+
+    /// The entries of a `[name]` section, in file order.
+    pub fn section(&self, name: &str) -> &[Entry] {
+        self.sections.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// An error about the entry on `line`.
+    pub fn error(&self, message: String, line: usize) -> ConfigError {
+        ConfigError { path: self.path.clone().unwrap_or_else(|| PathBuf::from(CONFIG_FILE)), message, line }
+    }
+}
+
+/// Drops a `#` comment, unless the `#` is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(inner) = text.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        return Some(Value::Str(inner.to_string()));
+    }
+    match text {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => text.replace('_', "").parse().ok().map(Value::Int),
+    }
+}
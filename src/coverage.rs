@@ -0,0 +1,247 @@
+//! `fork test --coverage`: which lines, functions and branches of each file
+//! ran, written as LCOV and as a standalone HTML report.
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+use crate::ast::{Expr, Program, Stmt, StmtKind};
+use crate::interpreter::debug::{Debugger, Event};
+use crate::interpreter::Interpreter;
+use crate::value::RuntimeError;
+
+/// The condition of an `if` or a loop, with the times it held and did not.
+/// For a loop, holding runs the body and not holding leaves the loop.
+struct Branch {
+    line: usize,
+    taken: [usize; 2],
+}
+
+/// A function, with the line of its definition and its number of calls.
+struct Function {
+    name: String,
+    line: usize,
+    calls: usize,
+}
+
+/// What ran of one file.
+pub struct FileCoverage {
+    pub path: String,
+    source: String,
+    /// Times a statement started on each line that has one.
+    lines: BTreeMap<usize, usize>,
+    functions: Vec<Function>,
+    /// Conditions in source order.
+    branches: Vec<Branch>,
+    /// Index in `branches` by the start of the condition.
+    conditions: HashMap<usize, usize>,
+}
+
+impl FileCoverage {
+    /// Starts recording a program, with every line, function and condition
+    /// in it not run yet.
+    pub fn new(path: &str, source: &str, program: &Program) -> Self {
+        let mut coverage = FileCoverage {
+            path: path.to_string(),
+            source: source.to_string(),
+            lines: BTreeMap::new(),
+            functions: Vec::new(),
+            branches: Vec::new(),
+            conditions: HashMap::new(),
+        };
+        coverage.add_block(&program.stmts);
+        coverage
+    }
+
+    fn add_block(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.lines.insert(stmt.span.line, 0);
+            match &stmt.kind {
+                StmtKind::If { cond, then_block, else_block } => {
+                    self.add_condition(cond);
+                    self.add_block(then_block);
+                    self.add_block(else_block.as_deref().unwrap_or_default());
+                }
+                StmtKind::While { cond, body } => {
+                    self.add_condition(cond);
+                    self.add_block(body);
+                }
+                StmtKind::For { cond, body, .. } => {
+                    if let Some(cond) = cond {
+                        self.add_condition(cond);
+                    }
+                    self.add_block(body);
+                }
+                StmtKind::Def { name, body, .. } => {
+                    self.functions.push(Function { name: name.clone(), line: stmt.span.line, calls: 0 });
+                    self.add_block(body);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn add_condition(&mut self, cond: &Expr) {
+        self.conditions.insert(cond.span.start, self.branches.len());
+        self.branches.push(Branch { line: cond.span.line, taken: [0, 0] });
+    }
+
+    /// Adds the counts of another run of the same file.
+    pub fn merge(&mut self, other: FileCoverage) {
+        for (line, hits) in other.lines {
+            *self.lines.entry(line).or_default() += hits;
+        }
+        for (function, other) in self.functions.iter_mut().zip(other.functions) {
+            function.calls += other.calls;
+        }
+        for (branch, other) in self.branches.iter_mut().zip(other.branches) {
+            branch.taken[0] += other.taken[0];
+            branch.taken[1] += other.taken[1];
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            lines: (self.lines.values().filter(|hits| **hits > 0).count(), self.lines.len()),
+            functions: (self.functions.iter().filter(|function| function.calls > 0).count(), self.functions.len()),
+            branches: (self.branches.iter().flat_map(|branch| branch.taken).filter(|taken| *taken > 0).count(), self.branches.len() * 2),
+        }
+    }
+
+    /// Whether a line has a condition that went only one way, or none.
+    fn partial(&self, line: usize) -> bool {
+        self.branches.iter().any(|branch| branch.line == line && branch.taken.contains(&0))
+    }
+}
+
+impl Debugger for Rc<RefCell<FileCoverage>> {
+    fn on_statement(&mut self, _interpreter: &mut Interpreter, stmt: &Stmt, event: Event) -> Result<(), RuntimeError> {
+        if event == Event::Before {
+            *self.borrow_mut().lines.entry(stmt.span.line).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, name: &str) {
+        if let Some(function) = self.borrow_mut().functions.iter_mut().find(|function| function.name == name) {
+            function.calls += 1;
+        }
+    }
+
+    fn branch(&mut self, cond: &Expr, taken: bool) {
+        let mut coverage = self.borrow_mut();
+        if let Some(&idx) = coverage.conditions.get(&cond.span.start) {
+            coverage.branches[idx].taken[usize::from(!taken)] += 1;
+        }
+    }
+}
+
+/// Lines, functions and branches run, each as (run, total).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Summary {
+    pub lines: (usize, usize),
+    pub functions: (usize, usize),
+    pub branches: (usize, usize),
+}
+
+impl Summary {
+    pub fn total(files: &[FileCoverage]) -> Summary {
+        let add = |a: (usize, usize), b: (usize, usize)| (a.0 + b.0, a.1 + b.1);
+        files.iter().map(FileCoverage::summary).fold(Summary::default(), |total, file| Summary {
+            lines: add(total.lines, file.lines),
+            functions: add(total.functions, file.functions),
+            branches: add(total.branches, file.branches),
+        })
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let part = |(run, total): (usize, usize)| format!("{} ({}/{})", percent(run, total), run, total);
+        write!(f, "lines {}, functions {}, branches {}", part(self.lines), part(self.functions), part(self.branches))
+    }
+}
+
+fn percent(run: usize, total: usize) -> String {
+    if total == 0 {
+        return "n/a".to_string();
+    }
+    format!("{:.1}%", run as f64 * 100.0 / total as f64)
+}
+
+/// The report in the LCOV tracefile format. Branches of a condition that
+/// never ran are written `-`.
+pub fn lcov(files: &[FileCoverage]) -> String {
+    let mut out = String::new();
+    for file in files {
+        let summary = file.summary();
+        let _ = writeln!(out, "TN:\nSF:{}", file.path);
+        for function in &file.functions {
+            let _ = writeln!(out, "FN:{},{}", function.line, function.name);
+        }
+        for function in &file.functions {
+            let _ = writeln!(out, "FNDA:{},{}", function.calls, function.name);
+        }
+        let _ = writeln!(out, "FNF:{}\nFNH:{}", summary.functions.1, summary.functions.0);
+        for (block, branch) in file.branches.iter().enumerate() {
+            for (idx, taken) in branch.taken.iter().enumerate() {
+                let taken = if branch.taken == [0, 0] { "-".to_string() } else { taken.to_string() };
+                let _ = writeln!(out, "BRDA:{},{},{},{}", branch.line, block, idx, taken);
+            }
+        }
+        let _ = writeln!(out, "BRF:{}\nBRH:{}", summary.branches.1, summary.branches.0);
+        for (line, hits) in &file.lines {
+            let _ = writeln!(out, "DA:{},{}", line, hits);
+        }
+        let _ = writeln!(out, "LF:{}\nLH:{}\nend_of_record", summary.lines.1, summary.lines.0);
+    }
+    out
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table.source { border-collapse: collapse; font-family: monospace; width: 100%; }
+table.source td { padding: 0 0.5em; white-space: pre; }
+td.line, td.hits { text-align: right; color: #666; width: 1%; }
+tr.run td.code { background: #dfd; }
+tr.partial td.code { background: #ffd; }
+tr.missed td.code { background: #fdd; }";
+
+/// A standalone HTML page with the summary of each file and its source,
+/// each line marked as run, partly run or missed, with its count.
+pub fn html(files: &[FileCoverage]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Coverage</title>\n<style>\n{}\n</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(out, "<h1>Coverage</h1>\n<p>Total: {}</p>", Summary::total(files));
+    let _ = writeln!(out, "<ul>");
+    for (idx, file) in files.iter().enumerate() {
+        let _ = writeln!(out, "<li><a href=\"#file{}\">{}</a>: {}</li>", idx, escape(&file.path), file.summary());
+    }
+    let _ = writeln!(out, "</ul>");
+    for (idx, file) in files.iter().enumerate() {
+        let _ = writeln!(out, "<h2 id=\"file{}\">{}</h2>\n<p>{}</p>\n<table class=\"source\">", idx, escape(&file.path), file.summary());
+        for (number, text) in file.source.lines().enumerate().map(|(idx, text)| (idx + 1, text)) {
+            let (class, hits) = match file.lines.get(&number) {
+                None => ("", String::new()),
+                Some(0) => ("missed", "0".to_string()),
+                Some(hits) if file.partial(number) => ("partial", hits.to_string()),
+                Some(hits) => ("run", hits.to_string()),
+            };
+            let _ = writeln!(
+                out,
+                "<tr class=\"{}\"><td class=\"line\">{}</td><td class=\"hits\">{}</td><td class=\"code\">{}</td></tr>",
+                class,
+                number,
+                hits,
+                escape(text)
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+/// Escapes text for HTML and XML.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
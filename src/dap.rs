@@ -0,0 +1,368 @@
+//! `fork dap`: the engine of `fork debug` behind the Debug Adapter Protocol,
+//! over stdin and stdout. A session launches one program, which runs on the
+//! adapter's own thread; requests are read while it is paused.
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+use crate::ast::{Program, Stmt, StmtKind};
+use crate::debugger::{self, show, Engine, Frontend, Paused, Points, Resume, StopReason};
+use crate::interpreter::Interpreter;
+use crate::json::Json;
+use crate::lsp::{read_message, write_message};
+
+/// The id of the only thread.
+const THREAD_ID: usize = 1;
+
+type Outcome = Result<Json, String>;
+
+/// Serves one session, until the `disconnect` request or the end of the input.
+pub fn serve<R: BufRead + 'static, W: Write + 'static>(input: R, output: W) -> io::Result<()> {
+    let client = Rc::new(RefCell::new(Client { input, out: output, seq: 0, failed: None, disconnected: false }));
+    let mut session = Session { client, launch: None, breakpoints: BTreeSet::new() };
+    loop {
+        let Some(request) = session.client.borrow_mut().receive()? else {
+            return Ok(());
+        };
+        let command = request.get("command").and_then(Json::as_str).unwrap_or_default().to_string();
+        let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+        let result = session.request(&command, &arguments);
+        let succeeded = result.is_ok();
+        let mut client = session.client.borrow_mut();
+        client.respond(&request, result)?;
+        match command.as_str() {
+            "launch" if succeeded => client.event("initialized", Json::Null)?,
+            "terminate" => client.event("terminated", Json::Null)?,
+            "disconnect" => return Ok(()),
+            "configurationDone" if succeeded => {
+                drop(client);
+                if !session.run()? {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A launched program waiting for `configurationDone`.
+struct Launch {
+    path: String,
+    program: Program,
+    /// Lines a statement starts on, where breakpoints can be set.
+    lines: BTreeSet<usize>,
+    stop_on_entry: bool,
+    no_debug: bool,
+}
+
+/// The session while no program is paused.
+struct Session<R: BufRead, W: Write> {
+    client: Rc<RefCell<Client<R, W>>>,
+    launch: Option<Launch>,
+    /// Breakpoints set before the program starts.
+    breakpoints: BTreeSet<usize>,
+}
+
+impl<R: BufRead + 'static, W: Write + 'static> Session<R, W> {
+    fn request(&mut self, command: &str, arguments: &Json) -> Outcome {
+        match command {
+            "initialize" => Ok(Json::object([
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsEvaluateForHovers", true.into()),
+                ("supportsTerminateRequest", true.into()),
+            ])),
+            "launch" => {
+                if self.launch.is_some() {
+                    return Err("a program is already launched".to_string());
+                }
+                let path = arguments.get("program").and_then(Json::as_str).ok_or("launch needs a 'program' to run")?;
+                let (program, _) = crate::engine::read_checked_program(path)?;
+                let mut lines = BTreeSet::new();
+                statement_lines(&program.stmts, &mut lines);
+                let flag = |name| arguments.get(name).and_then(Json::as_bool).unwrap_or(false);
+                let (stop_on_entry, no_debug) = (flag("stopOnEntry"), flag("noDebug"));
+                self.launch = Some(Launch { path: path.to_string(), program, lines, stop_on_entry, no_debug });
+                Ok(Json::Null)
+            }
+            "setBreakpoints" => {
+                let lines = self.launch.as_ref().map(|launch| &launch.lines).ok_or("no program has been launched")?;
+                set_breakpoints(arguments, lines, &mut self.breakpoints)
+            }
+            "configurationDone" if self.launch.is_none() => Err("no program has been launched".to_string()),
+            "configurationDone" | "disconnect" | "terminate" => Ok(Json::Null),
+            "threads" => Ok(threads()),
+            "stackTrace" | "scopes" | "variables" | "evaluate" | "continue" | "next" | "stepIn" | "stepOut" => {
+                Err("the program is not paused".to_string())
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        }
+    }
+
+    /// Runs the launched program to its end. Returns false if the client
+    /// disconnected meanwhile.
+    fn run(&mut self) -> io::Result<bool> {
+        let Some(launch) = self.launch.take() else {
+            return Ok(true);
+        };
+        let mut interpreter = Interpreter::new();
+        let frontend = Adapter { client: Rc::clone(&self.client), path: launch.path, lines: launch.lines };
+        let mut points = Points::default();
+        if !launch.no_debug {
+            points.breakpoints = std::mem::take(&mut self.breakpoints);
+        }
+        let stop_on_entry = launch.stop_on_entry && !launch.no_debug;
+        interpreter.attach(Box::new(Engine::new(frontend, points, stop_on_entry)));
+        let result = interpreter.interpret(&launch.program);
+        let mut client = self.client.borrow_mut();
+        if let Some(e) = client.failed.take() {
+            return Err(e);
+        }
+        if client.disconnected {
+            return Ok(false);
+        }
+        match result {
+            Ok(()) => client.event("exited", Json::object([("exitCode", 0.into())]))?,
+            Err(e) if e.message == debugger::STOPPED => {}
+            Err(e) => {
+                client.event("output", Json::object([("category", "stderr".into()), ("output", format!("{}\n", e).into())]))?;
+                client.event("exited", Json::object([("exitCode", 1.into())]))?;
+            }
+        }
+        client.event("terminated", Json::Null)?;
+        Ok(true)
+    }
+}
+
+/// The front end of the engine: answers requests while the program is paused.
+struct Adapter<R: BufRead, W: Write> {
+    client: Rc<RefCell<Client<R, W>>>,
+    path: String,
+    lines: BTreeSet<usize>,
+}
+
+impl<R: BufRead, W: Write> Adapter<R, W> {
+    /// Handles a request while paused. Returns the response, and how to
+    /// resume if the request ends the pause.
+    fn request(&self, paused: &mut Paused<'_>, command: &str, arguments: &Json) -> (Outcome, Option<Resume>) {
+        let frame = |name| arguments.get(name).and_then(Json::as_usize).unwrap_or(1);
+        let outcome = match command {
+            "continue" => return (Ok(Json::object([("allThreadsContinued", true.into())])), Some(Resume::Continue)),
+            "next" => return (Ok(Json::Null), Some(Resume::StepOver)),
+            "stepIn" => return (Ok(Json::Null), Some(Resume::StepIn)),
+            "stepOut" => return (Ok(Json::Null), Some(Resume::StepOut)),
+            "disconnect" => {
+                self.client.borrow_mut().disconnected = true;
+                return (Ok(Json::Null), Some(Resume::Stop));
+            }
+            "terminate" => return (Ok(Json::Null), Some(Resume::Stop)),
+            "threads" => Ok(threads()),
+            "setBreakpoints" => set_breakpoints(arguments, &self.lines, &mut paused.points.breakpoints),
+            "stackTrace" => Ok(self.stack_trace(paused, arguments)),
+            "scopes" => {
+                let id = frame("frameId");
+                let count = paused.backtrace().len();
+                if !(1..=count).contains(&id) {
+                    return (Err(format!("no frame {}", id)), None);
+                }
+                let name = if id == count { "Globals" } else { "Locals" };
+                let scope = Json::object([("name", name.into()), ("variablesReference", id.into()), ("expensive", false.into())]);
+                Ok(Json::object([("scopes", vec![scope].into())]))
+            }
+            "variables" => {
+                let id = frame("variablesReference");
+                match paused.backtrace().get(id.wrapping_sub(1)) {
+                    Some(view) => {
+                        let mut names: Vec<&String> = view.variables.keys().collect();
+                        names.sort();
+                        let variables = names.into_iter().map(|name| {
+                            let value = show(view.variables.get(name));
+                            Json::object([("name", name.as_str().into()), ("value", value.into()), ("variablesReference", 0.into())])
+                        });
+                        Ok(Json::object([("variables", variables.collect::<Vec<_>>().into())]))
+                    }
+                    None => Err(format!("no variables with reference {}", id)),
+                }
+            }
+            "evaluate" => {
+                let expression = arguments.get("expression").and_then(Json::as_str).unwrap_or_default();
+                let Some(frame) = frame("frameId").checked_sub(1) else {
+                    return (Err("no frame 0".to_string()), None);
+                };
+                paused
+                    .evaluate(expression, frame)
+                    .map(|value| Json::object([("result", show(Some(&value)).into()), ("variablesReference", 0.into())]))
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+        (outcome, None)
+    }
+
+    fn stack_trace(&self, paused: &Paused<'_>, arguments: &Json) -> Json {
+        let frames = paused.backtrace();
+        let start = arguments.get("startFrame").and_then(Json::as_usize).unwrap_or(0);
+        let levels = arguments.get("levels").and_then(Json::as_usize).filter(|levels| *levels > 0).unwrap_or(frames.len());
+        let name = Path::new(&self.path).file_name().map_or(self.path.clone(), |name| name.to_string_lossy().into_owned());
+        let source = Json::object([("name", name.into()), ("path", self.path.as_str().into())]);
+        let stack_frames = frames.iter().enumerate().skip(start).take(levels).map(|(idx, frame)| {
+            Json::object([
+                ("id", (idx + 1).into()),
+                ("name", frame.function.into()),
+                ("source", source.clone()),
+                ("line", frame.line.into()),
+                ("column", 1.into()),
+            ])
+        });
+        Json::object([("stackFrames", stack_frames.collect::<Vec<_>>().into()), ("totalFrames", frames.len().into())])
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Adapter<R, W> {
+    fn stopped(&mut self, mut paused: Paused<'_>, reason: &StopReason) -> Resume {
+        let client = Rc::clone(&self.client);
+        let mut client = client.borrow_mut();
+        let (reason, description) = match reason {
+            StopReason::Entry => ("entry", None),
+            StopReason::Breakpoint => ("breakpoint", None),
+            StopReason::Step => ("step", None),
+            StopReason::Watch { name, old, new } => ("data breakpoint", Some(format!("'{}' changed from {} to {}", name, show(old.as_ref()), show(new.as_ref())))),
+        };
+        let mut body = vec![("reason".to_string(), reason.into())];
+        if let Some(description) = description {
+            body.push(("description".to_string(), description.into()));
+        }
+        body.push(("threadId".to_string(), THREAD_ID.into()));
+        body.push(("allThreadsStopped".to_string(), true.into()));
+        if let Err(e) = client.event("stopped", Json::Object(body)) {
+            return client.fail(e);
+        }
+        loop {
+            let request = match client.receive() {
+                Ok(Some(request)) => request,
+                Ok(None) => {
+                    client.disconnected = true;
+                    return Resume::Stop;
+                }
+                Err(e) => return client.fail(e),
+            };
+            let command = request.get("command").and_then(Json::as_str).unwrap_or_default();
+            let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+            drop(client);
+            let (outcome, resume) = self.request(&mut paused, command, &arguments);
+            client = self.client.borrow_mut();
+            if let Err(e) = client.respond(&request, outcome) {
+                return client.fail(e);
+            }
+            if let Some(resume) = resume {
+                return resume;
+            }
+        }
+    }
+
+    fn print(&mut self, line: &str) -> bool {
+        let mut client = self.client.borrow_mut();
+        let body = Json::object([("category", "stdout".into()), ("output", format!("{}\n", line).into())]);
+        if let Err(e) = client.event("output", body) {
+            client.fail(e);
+        }
+        true
+    }
+}
+
+/// The connection to the client.
+struct Client<R: BufRead, W: Write> {
+    input: R,
+    out: W,
+    /// Sequence number of the last message sent.
+    seq: usize,
+    /// The first error talking to the client while the program ran.
+    failed: Option<io::Error>,
+    disconnected: bool,
+}
+
+impl<R: BufRead, W: Write> Client<R, W> {
+    /// Reads the next request, skipping any other message; `None` at the end
+    /// of the input.
+    fn receive(&mut self) -> io::Result<Option<Json>> {
+        while let Some(body) = read_message(&mut self.input)? {
+            if let Ok(message) = Json::parse(&body)
+                && message.get("type").and_then(Json::as_str) == Some("request")
+            {
+                return Ok(Some(message));
+            }
+        }
+        Ok(None)
+    }
+
+    fn send(&mut self, kind: &str, mut members: Vec<(String, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        members.insert(0, ("seq".to_string(), self.seq.into()));
+        members.insert(1, ("type".to_string(), kind.into()));
+        members.retain(|(_, value)| *value != Json::Null);
+        write_message(&mut self.out, &Json::Object(members))
+    }
+
+    fn respond(&mut self, request: &Json, outcome: Outcome) -> io::Result<()> {
+        let member = |key: &str, value: Json| (key.to_string(), value);
+        let mut members = vec![
+            member("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            member("success", outcome.is_ok().into()),
+            member("command", request.get("command").cloned().unwrap_or(Json::Null)),
+        ];
+        match outcome {
+            Ok(body) => members.push(member("body", body)),
+            Err(message) => members.push(member("message", message.into())),
+        }
+        self.send("response", members)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send("event", vec![("event".to_string(), event.into()), ("body".to_string(), body)])
+    }
+
+    /// Records an error talking to the client, which ends the program.
+    fn fail(&mut self, e: io::Error) -> Resume {
+        self.failed.get_or_insert(e);
+        Resume::Stop
+    }
+}
+
+fn threads() -> Json {
+    let thread = Json::object([("id", THREAD_ID.into()), ("name", "main".into())]);
+    Json::object([("threads", vec![thread].into())])
+}
+
+/// Replaces the breakpoints with those of a `setBreakpoints` request. Only
+/// lines a statement starts on are accepted.
+fn set_breakpoints(arguments: &Json, lines: &BTreeSet<usize>, breakpoints: &mut BTreeSet<usize>) -> Outcome {
+    breakpoints.clear();
+    let requested = arguments.get("breakpoints").and_then(Json::as_array).unwrap_or_default();
+    let mut results = Vec::new();
+    for breakpoint in requested {
+        let line = breakpoint.get("line").and_then(Json::as_usize).ok_or("a breakpoint needs a 'line'")?;
+        let verified = lines.contains(&line);
+        if verified {
+            breakpoints.insert(line);
+        }
+        let mut result = vec![("verified".to_string(), verified.into()), ("line".to_string(), line.into())];
+        if !verified {
+            result.push(("message".to_string(), "no statement starts on this line".into()));
+        }
+        results.push(Json::Object(result));
+    }
+    Ok(Json::object([("breakpoints", results.into())]))
+}
+
+fn statement_lines(stmts: &[Stmt], lines: &mut BTreeSet<usize>) {
+    for stmt in stmts {
+        lines.insert(stmt.span.line);
+        match &stmt.kind {
+            StmtKind::If { then_block, else_block, .. } => {
+                statement_lines(then_block, lines);
+                statement_lines(else_block.as_deref().unwrap_or_default(), lines);
+            }
+            StmtKind::While { body, .. } | StmtKind::For { body, .. } | StmtKind::Def { body, .. } => statement_lines(body, lines),
+            _ => {}
+        }
+    }
+}
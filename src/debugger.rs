@@ -0,0 +1,363 @@
+//! `fork debug`: breakpoints, stepping and watchpoints built on the
+//! interpreter's statement hook. The `Engine` decides where to pause; a
+//! `Frontend` talks to the user while the program is paused.
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use crate::ast::{Stmt, StmtKind};
+use crate::interpreter::debug::{Debugger, Event, FrameView};
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::value::{RuntimeError, Value};
+
+/// Message of the error that ends a program stopped from the debugger.
+pub const STOPPED: &str = "Stopped by the debugger";
+
+/// Why the program paused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// Before the first statement.
+    Entry,
+    Breakpoint,
+    /// A step finished.
+    Step,
+    /// A watched variable changed; `None` means not assigned.
+    Watch { name: String, old: Option<Value>, new: Option<Value> },
+}
+
+/// How to go on from a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run to the next breakpoint or watchpoint.
+    Continue,
+    /// Pause at the next statement, entering calls.
+    StepIn,
+    /// Pause at the next statement of this function or its callers.
+    StepOver,
+    /// Pause at the next statement of a caller.
+    StepOut,
+    /// End the program.
+    Stop,
+}
+
+/// A variable watched in the frame it was set in, with its last value.
+struct Watch {
+    name: String,
+    depth: usize,
+    value: Option<Value>,
+}
+
+/// Breakpoint lines and watched variables.
+#[derive(Default)]
+pub struct Points {
+    pub breakpoints: BTreeSet<usize>,
+    watches: Vec<Watch>,
+}
+
+/// A paused program, as a front end sees it.
+pub struct Paused<'a> {
+    pub interpreter: &'a mut Interpreter,
+    pub points: &'a mut Points,
+    /// Line of the statement about to run, or of the one that changed a
+    /// watched variable.
+    pub line: usize,
+}
+
+impl Paused<'_> {
+    /// Frames innermost first, as in `Interpreter::backtrace`.
+    pub fn backtrace(&self) -> Vec<FrameView<'_>> {
+        self.interpreter.backtrace(self.line)
+    }
+
+    /// Evaluates an expression typed by the user with the variables of a frame.
+    pub fn evaluate(&mut self, source: &str, frame: usize) -> Result<Value, String> {
+        let tokens = Lexer::new().tokenize_with_spans(source);
+        let expr = Parser::new(tokens).parse_expression().map_err(|e| e.to_string())?;
+        self.interpreter.evaluate(&expr, frame).map_err(|e| e.to_string())
+    }
+
+    /// Watches a variable of the running frame. Returns its current value.
+    pub fn watch(&mut self, name: &str) -> Option<Value> {
+        let value = self.interpreter.variables().get(name).cloned();
+        self.points.watches.retain(|watch| watch.name != name);
+        let watch = Watch { name: name.to_string(), depth: self.interpreter.call_depth(), value: value.clone() };
+        self.points.watches.push(watch);
+        value
+    }
+
+    /// Stops watching a variable. Returns false if it was not watched.
+    pub fn unwatch(&mut self, name: &str) -> bool {
+        let before = self.points.watches.len();
+        self.points.watches.retain(|watch| watch.name != name);
+        self.points.watches.len() != before
+    }
+
+    /// Names of the watched variables.
+    pub fn watches(&self) -> impl Iterator<Item = &str> {
+        self.points.watches.iter().map(|watch| watch.name.as_str())
+    }
+}
+
+/// Talks to the user while the program is paused.
+pub trait Frontend {
+    /// Called at each pause; returns once the user chooses how to go on.
+    fn stopped(&mut self, paused: Paused<'_>, reason: &StopReason) -> Resume;
+
+    /// Takes a line printed by the program. Returns false to leave it to stdout.
+    fn print(&mut self, _line: &str) -> bool {
+        false
+    }
+}
+
+/// Where the engine pauses next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Entry,
+    Run,
+    StepIn,
+    /// Stepping over from the given call depth.
+    StepOver(usize),
+    /// Stepping out from the given call depth.
+    StepOut(usize),
+}
+
+/// Decides where to pause a program, and hands each pause to a front end.
+pub struct Engine<F: Frontend> {
+    points: Points,
+    frontend: F,
+    mode: Mode,
+    /// Line and depth of an `if` or loop just entered. A block that starts on
+    /// the line of its statement does not pause again there.
+    entered: Option<(usize, usize)>,
+}
+
+impl<F: Frontend> Engine<F> {
+    pub fn new(frontend: F, points: Points, stop_on_entry: bool) -> Self {
+        let mode = if stop_on_entry { Mode::Entry } else { Mode::Run };
+        Engine { points, frontend, mode, entered: None }
+    }
+
+    fn pause(&mut self, interpreter: &mut Interpreter, line: usize, reason: StopReason) -> Result<(), RuntimeError> {
+        let depth = interpreter.call_depth();
+        let paused = Paused { interpreter, points: &mut self.points, line };
+        self.mode = match self.frontend.stopped(paused, &reason) {
+            Resume::Continue => Mode::Run,
+            Resume::StepIn => Mode::StepIn,
+            Resume::StepOver => Mode::StepOver(depth),
+            Resume::StepOut => Mode::StepOut(depth),
+            Resume::Stop => return Err(RuntimeError { message: STOPPED.to_string(), line: None }),
+        };
+        Ok(())
+    }
+
+    fn before(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) -> Result<(), RuntimeError> {
+        let (line, depth) = (stmt.span.line, interpreter.call_depth());
+        let same_stop = self.entered.take() == Some((line, depth));
+        if matches!(stmt.kind, StmtKind::If { .. } | StmtKind::While { .. } | StmtKind::For { .. }) {
+            self.entered = Some((line, depth));
+        }
+        if same_stop {
+            return Ok(());
+        }
+        let reason = match self.mode {
+            Mode::Entry => StopReason::Entry,
+            Mode::StepIn => StopReason::Step,
+            Mode::StepOver(from) if depth <= from => StopReason::Step,
+            Mode::StepOut(from) if depth < from => StopReason::Step,
+            _ if self.points.breakpoints.contains(&line) => StopReason::Breakpoint,
+            _ => return Ok(()),
+        };
+        self.pause(interpreter, line, reason)
+    }
+
+    /// Pauses if the statement changed a variable watched in this frame.
+    fn after(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) -> Result<(), RuntimeError> {
+        let depth = interpreter.call_depth();
+        let mut changed = None;
+        for watch in self.points.watches.iter_mut().filter(|watch| watch.depth == depth) {
+            let value = interpreter.variables().get(&watch.name).cloned();
+            if value != watch.value {
+                let old = std::mem::replace(&mut watch.value, value.clone());
+                changed = changed.or(Some(StopReason::Watch { name: watch.name.clone(), old, new: value }));
+            }
+        }
+        match changed {
+            Some(reason) => self.pause(interpreter, stmt.span.line, reason),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<F: Frontend> Debugger for Engine<F> {
+    fn on_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt, event: Event) -> Result<(), RuntimeError> {
+        match event {
+            Event::Before => self.before(interpreter, stmt),
+            Event::After => self.after(interpreter, stmt),
+        }
+    }
+
+    fn print(&mut self, line: &str) -> bool {
+        self.frontend.print(line)
+    }
+}
+
+/// A value as the debugger shows it, with strings quoted.
+pub fn show(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::Str(s)) => format!("\"{}\"", s),
+        Some(value) => value.to_string(),
+        None => "<unassigned>".to_string(),
+    }
+}
+
+const HELP: &str = "\
+Commands:
+  s, step            run to the next statement, entering calls
+  n, next            run to the next statement of this function
+  finish             run until the current function returns
+  c, continue        run to the next breakpoint or watchpoint
+  b, break [LINE]    set a breakpoint, or list the breakpoints
+  clear LINE         remove a breakpoint
+  watch [NAME]       watch a variable of this frame, or list the watchpoints
+  unwatch NAME       remove a watchpoint
+  p, print EXPR      evaluate an expression in the current frame
+  locals             show the variables of the current frame
+  bt, backtrace      show the active calls
+  l, list            show the source around the current line
+  q, quit            stop the program
+An empty line repeats the last command.";
+
+/// The interactive front end of `fork debug`: a prompt on a terminal.
+pub struct Terminal<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    source: Vec<String>,
+    last_command: String,
+}
+
+impl<R: BufRead, W: Write> Terminal<R, W> {
+    pub fn new(input: R, output: W, source: &str) -> Self {
+        Terminal { input, output, source: source.lines().map(str::to_string).collect(), last_command: String::new() }
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        self.source.get(line.wrapping_sub(1)).map_or("", |text| text.trim())
+    }
+
+    /// Runs one command. Returns how to resume, or `None` to keep prompting.
+    fn command(&mut self, paused: &mut Paused<'_>, input: &str) -> Option<Resume> {
+        let (command, arg) = input.split_once(' ').map_or((input, ""), |(command, arg)| (command, arg.trim()));
+        let out = &mut self.output;
+        let result = match command {
+            "s" | "step" => return Some(Resume::StepIn),
+            "n" | "next" => return Some(Resume::StepOver),
+            "finish" => return Some(Resume::StepOut),
+            "c" | "continue" => return Some(Resume::Continue),
+            "q" | "quit" => return Some(Resume::Stop),
+            "b" | "break" if arg.is_empty() => {
+                if paused.points.breakpoints.is_empty() {
+                    writeln!(out, "No breakpoints.")
+                } else {
+                    let lines: Vec<String> = paused.points.breakpoints.iter().map(usize::to_string).collect();
+                    writeln!(out, "Breakpoints at lines {}", lines.join(", "))
+                }
+            }
+            "b" | "break" => match arg.parse::<usize>() {
+                Ok(line) if (1..=self.source.len()).contains(&line) => {
+                    paused.points.breakpoints.insert(line);
+                    writeln!(out, "Breakpoint set at line {}", line)
+                }
+                _ => writeln!(out, "Expected a line number between 1 and {}", self.source.len()),
+            },
+            "clear" => match arg.parse::<usize>() {
+                Ok(line) if paused.points.breakpoints.remove(&line) => writeln!(out, "Breakpoint at line {} removed", line),
+                _ => writeln!(out, "No breakpoint at line '{}'", arg),
+            },
+            "watch" if arg.is_empty() => {
+                let names: Vec<&str> = paused.watches().collect();
+                if names.is_empty() {
+                    writeln!(out, "No watchpoints.")
+                } else {
+                    writeln!(out, "Watching {}", names.join(", "))
+                }
+            }
+            "watch" => {
+                let value = paused.watch(arg);
+                writeln!(out, "Watching '{}' (currently {})", arg, show(value.as_ref()))
+            }
+            "unwatch" if paused.unwatch(arg) => writeln!(out, "Stopped watching '{}'", arg),
+            "unwatch" => writeln!(out, "'{}' is not watched", arg),
+            "p" | "print" if !arg.is_empty() => match paused.evaluate(arg, 0) {
+                Ok(value) => writeln!(out, "{}", show(Some(&value))),
+                Err(e) => writeln!(out, "{}", e),
+            },
+            "locals" => {
+                let frames = paused.backtrace();
+                let mut names: Vec<&String> = frames[0].variables.keys().collect();
+                names.sort();
+                if names.is_empty() {
+                    writeln!(out, "No variables.")
+                } else {
+                    names.iter().try_for_each(|name| writeln!(out, "{} = {}", name, show(frames[0].variables.get(*name))))
+                }
+            }
+            "bt" | "backtrace" => paused
+                .backtrace()
+                .iter()
+                .enumerate()
+                .try_for_each(|(idx, frame)| writeln!(out, "#{} {} at line {}", idx, frame.function, frame.line)),
+            "l" | "list" => {
+                let first = paused.line.saturating_sub(3).max(1);
+                let last = (paused.line + 3).min(self.source.len());
+                (first..=last).try_for_each(|line| {
+                    let marker = if line == paused.line { "->" } else { "  " };
+                    writeln!(out, "{} {:>4} | {}", marker, line, self.source[line - 1])
+                })
+            }
+            "h" | "help" => writeln!(out, "{}", HELP),
+            _ => writeln!(out, "Unknown command '{}'. Type 'help' for a list of commands.", input),
+        };
+        // A terminal that cannot be written to cannot be debugged from
+        result.is_err().then_some(Resume::Stop)
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Terminal<R, W> {
+    fn stopped(&mut self, mut paused: Paused<'_>, reason: &StopReason) -> Resume {
+        let function = paused.backtrace()[0].function.to_string();
+        let heading = match reason {
+            StopReason::Entry => format!("Stopped at line {} in {}", paused.line, function),
+            StopReason::Breakpoint => format!("Breakpoint at line {} in {}", paused.line, function),
+            StopReason::Step => format!("Line {} in {}", paused.line, function),
+            StopReason::Watch { name, old, new } => {
+                format!("Watchpoint '{}': {} -> {} at line {} in {}", name, show(old.as_ref()), show(new.as_ref()), paused.line, function)
+            }
+        };
+        let text = self.source_line(paused.line).to_string();
+        if writeln!(self.output, "{}\n{:>4} | {}", heading, paused.line, text).is_err() {
+            return Resume::Stop;
+        }
+        loop {
+            if write!(self.output, "(fork-dbg) ").and_then(|()| self.output.flush()).is_err() {
+                return Resume::Stop;
+            }
+            let mut input = String::new();
+            match self.input.read_line(&mut input) {
+                Ok(0) | Err(_) => return Resume::Stop,
+                Ok(_) => {}
+            }
+            let mut input = input.trim().to_string();
+            if input.is_empty() {
+                input = self.last_command.clone();
+            } else {
+                self.last_command = input.clone();
+            }
+            if input.is_empty() {
+                continue;
+            }
+            if let Some(resume) = self.command(&mut paused, &input) {
+                return resume;
+            }
+        }
+    }
+}
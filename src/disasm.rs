@@ -0,0 +1,115 @@
+//! Human-readable listing of compiled bytecode, for debugging the code generator.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use crate::bytecode::{Function, Module, Op};
+
+/// Renders every function in `module`. When the original `source` is available,
+/// each new source line is printed above the instructions generated from it.
+pub fn disassemble(module: &Module, source: Option<&str>) -> String {
+    let source_lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
+    let mut out = String::new();
+    for (idx, function) in module.functions.iter().enumerate() {
+        if idx > 0 {
+            out.push('\n');
+        }
+        disassemble_function(&mut out, module, function, &source_lines);
+    }
+    out
+}
+
+fn disassemble_function(out: &mut String, module: &Module, function: &Function, source_lines: &[&str]) {
+    let _ = writeln!(
+        out,
+        "== {} == arity {}, {} locals, {} constants",
+        function.name,
+        function.arity,
+        function.locals.len(),
+        function.constants.len()
+    );
+    let labels = jump_labels(function);
+    let mut offset = 0;
+    let mut last_line = None;
+    while offset < function.code.len() {
+        if let Some(label) = labels.get(&offset) {
+            let _ = writeln!(out, "{}:", label);
+        }
+        let line = function.line_at(offset);
+        let line_column = if line == last_line {
+            "   |".to_string()
+        } else {
+            if let Some(text) = line.and_then(|l| source_lines.get(l.wrapping_sub(1))) {
+                let _ = writeln!(out, "            ; {}", text.trim());
+            }
+            line.map_or("   ?".to_string(), |l| format!("{:4}", l))
+        };
+        last_line = line;
+        let Some(op) = Op::from_byte(function.code[offset]) else {
+            let _ = writeln!(out, "{:04} {}  <invalid opcode {}>", offset, line_column, function.code[offset]);
+            offset += 1;
+            continue;
+        };
+        let operand_bytes = function.code.get(offset + 1..offset + 1 + op.operand_width());
+        let Some(operand_bytes) = operand_bytes else {
+            let _ = writeln!(out, "{:04} {}  {} <truncated operand>", offset, line_column, op.name());
+            break;
+        };
+        let operand = read_operand(operand_bytes);
+        let detail = match op {
+            Op::Const => format!(
+                "{:<6} ; {}",
+                operand,
+                function.constants.get(operand).map_or("<bad constant>".to_string(), |c| c.to_string())
+            ),
+            Op::Load | Op::Store => format!(
+                "{:<6} ; {}",
+                operand,
+                function.locals.get(operand).map_or("<bad slot>", |l| l.as_str())
+            ),
+            Op::Jump | Op::JumpIfFalse => format!(
+                "{:<6} ; -> {:04}",
+                labels.get(&operand).map_or("?", |l| l.as_str()),
+                operand
+            ),
+            Op::Call => {
+                let callee = read_operand(&operand_bytes[..2]);
+                format!(
+                    "{:<6} ; {}, {} args",
+                    callee,
+                    module.functions.get(callee).map_or("<bad function>", |f| f.name.as_str()),
+                    operand_bytes[2]
+                )
+            }
+            Op::Print => operand.to_string(),
+            _ => String::new(),
+        };
+        let text = format!("{:04} {}  {:<14}{}", offset, line_column, op.name(), detail);
+        let _ = writeln!(out, "{}", text.trim_end());
+        offset += 1 + op.operand_width();
+    }
+    if let Some(label) = labels.get(&function.code.len()) {
+        let _ = writeln!(out, "{}:", label);
+    }
+}
+
+/// Assigns `L0`, `L1`, ... to jump targets in offset order.
+fn jump_labels(function: &Function) -> BTreeMap<usize, String> {
+    let mut targets = BTreeMap::new();
+    let mut offset = 0;
+    while let Some(op) = function.code.get(offset).and_then(|&b| Op::from_byte(b)) {
+        let Some(operand_bytes) = function.code.get(offset + 1..offset + 1 + op.operand_width()) else {
+            break;
+        };
+        if matches!(op, Op::Jump | Op::JumpIfFalse) {
+            targets.insert(read_operand(operand_bytes), String::new());
+        }
+        offset += 1 + op.operand_width();
+    }
+    for (idx, label) in targets.values_mut().enumerate() {
+        *label = format!("L{}", idx);
+    }
+    targets
+}
+
+fn read_operand(bytes: &[u8]) -> usize {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as usize)
+}
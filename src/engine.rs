@@ -0,0 +1,260 @@
+//! Embedding API: runs Fork source from Rust, keeping variables and
+//! functions between runs.
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, Write};
+use crate::ast::{Program, Stmt, StmtKind};
+use crate::interpreter::builtins::EXIT;
+use crate::interpreter::capabilities::Capabilities;
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::native::NativeFunction;
+use crate::parser::{ParseError, Parser};
+use crate::semantic::{SemanticAnalyzer, SemanticError};
+use crate::value::{RuntimeError, Value};
+
+/// Why source could not be run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForkError {
+    /// The file could not be read, or is not a `.fork` file.
+    File(String),
+    Syntax(ParseError),
+    /// Name and type errors, found before anything runs.
+    Semantic(Vec<SemanticError>),
+    Runtime(RuntimeError),
+    /// The script called `exit` with this status.
+    Exit(i32),
+}
+
+impl fmt::Display for ForkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForkError::File(message) => write!(f, "{}", message),
+            ForkError::Syntax(e) => write!(f, "{}", e),
+            ForkError::Semantic(errors) => write!(f, "{}", semantic_errors(errors)),
+            ForkError::Runtime(e) => write!(f, "{}", e),
+            ForkError::Exit(code) => write!(f, "{}: {}", EXIT, code),
+        }
+    }
+}
+
+impl ForkError {
+    /// A runtime error with `message`, such as one raised by a native
+    /// function. The line of the call is added when it reaches the script.
+    pub fn runtime(message: impl Into<String>) -> Self {
+        ForkError::Runtime(RuntimeError { message: message.into(), line: None })
+    }
+
+    /// The message a script sees: a runtime error without its line.
+    fn message(self) -> String {
+        match self {
+            ForkError::Runtime(e) => e.message,
+            e => e.to_string(),
+        }
+    }
+}
+
+impl Error for ForkError {}
+
+impl From<ParseError> for ForkError {
+    fn from(e: ParseError) -> Self {
+        ForkError::Syntax(e)
+    }
+}
+
+impl From<Vec<SemanticError>> for ForkError {
+    fn from(errors: Vec<SemanticError>) -> Self {
+        ForkError::Semantic(errors)
+    }
+}
+
+impl From<RuntimeError> for ForkError {
+    fn from(e: RuntimeError) -> Self {
+        ForkError::Runtime(e)
+    }
+}
+
+/// Formats semantic errors one per line.
+pub fn semantic_errors(errors: &[SemanticError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+}
+
+/// Reads a `.fork` file.
+pub fn read_source(filename: &str) -> Result<String, ForkError> {
+    if !filename.ends_with(".fork") {
+        return Err(ForkError::File("Error: Only .fork files are supported.".to_string()));
+    }
+    fs::read_to_string(filename).map_err(|e| ForkError::File(format!("Error reading file {}: {}", filename, e)))
+}
+
+/// Reads, parses and analyzes a `.fork` file, returning the program and the source text.
+pub fn read_checked_program(filename: &str) -> Result<(Program, String), String> {
+    let source = read_source(filename).map_err(|e| e.to_string())?;
+    let tokens = Lexer::new().tokenize_with_spans(&source);
+    let program = Parser::new(tokens).parse().map_err(|e| e.to_string())?;
+    SemanticAnalyzer::new().analyze(&program).map_err(|errors| semantic_errors(&errors))?;
+    Ok((program, source))
+}
+
+/// Source that has been parsed and checked once, to be run any number of
+/// times.
+#[derive(Debug, Clone)]
+pub struct Script {
+    program: Program,
+}
+
+impl Script {
+    /// Parses `source` as a program or, failing that, as a single expression.
+    pub fn parse(source: &str) -> Result<Program, ParseError> {
+        let program = Parser::new(Lexer::new().tokenize_with_spans(source)).parse();
+        let Err(error) = program else {
+            return program;
+        };
+        match Parser::new(Lexer::new().tokenize_with_spans(source)).parse_expression() {
+            Ok(expr) => Ok(Program { stmts: vec![Stmt { span: expr.span, kind: StmtKind::Expr(expr) }] }),
+            Err(_) => Err(error),
+        }
+    }
+
+    fn check(source: &str, analyzer: &SemanticAnalyzer) -> Result<Script, ForkError> {
+        let program = Self::parse(source)?;
+        analyzer.analyze(&program)?;
+        Ok(Script { program })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+}
+
+/// An interpreter with the variables and functions of everything run on it.
+///
+/// ```
+/// use fork::{Engine, Value};
+///
+/// let mut engine = Engine::new();
+/// engine.eval("def double(n) { return n * 2; }").unwrap();
+/// engine.set("n", Value::Number(20.0));
+/// let script = engine.compile("double(n + 1)").unwrap();
+/// assert_eq!(engine.run(&script).unwrap(), Value::Number(42.0));
+/// engine.eval("n = n + 1;").unwrap();
+/// assert_eq!(engine.run(&script).unwrap(), Value::Number(44.0));
+/// assert_eq!(engine.get("n"), Some(&Value::Number(21.0)));
+/// ```
+pub struct Engine {
+    interpreter: Interpreter,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine { interpreter: Interpreter::new() }
+    }
+
+    /// Parses and checks `source`. Names are resolved against the variables
+    /// and functions defined so far, which must still be defined when the
+    /// script runs.
+    pub fn compile(&self, source: &str) -> Result<Script, ForkError> {
+        let analyzer = SemanticAnalyzer::new()
+            .with_globals(self.interpreter.global_names())
+            .with_functions(self.interpreter.function_arities());
+        Script::check(source, &analyzer)
+    }
+
+    /// Runs a compiled script, returning the value of its last statement if
+    /// that is an expression, or `0`.
+    pub fn run(&mut self, script: &Script) -> Result<Value, ForkError> {
+        self.interpreter.interpret_value(&script.program).map_err(|e| match self.interpreter.exit_code.take() {
+            Some(code) => ForkError::Exit(code),
+            None => ForkError::Runtime(e),
+        })
+    }
+
+    /// Compiles and runs `source`, which may be a program or a single
+    /// expression.
+    pub fn eval(&mut self, source: &str) -> Result<Value, ForkError> {
+        let script = self.compile(source)?;
+        self.run(&script)
+    }
+
+    /// Reads a `.fork` file and runs it.
+    pub fn run_file(&mut self, filename: &str) -> Result<Value, ForkError> {
+        let source = read_source(filename)?;
+        self.eval(&source)
+    }
+
+    /// The value of a top-level variable.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.interpreter.global(name)
+    }
+
+    /// Assigns a top-level variable, which scripts compiled afterwards may read.
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.interpreter.set_global(name, value);
+    }
+
+    /// Makes a Rust closure callable from scripts as `name`. Its number of
+    /// parameters is the function's arity, and the arguments and result are
+    /// converted from and to values:
+    ///
+    /// ```
+    /// use fork::{Engine, ForkError, Value};
+    ///
+    /// let mut engine = Engine::new();
+    /// engine.register_fn("fetch_rate", |currency: String| match currency.as_str() {
+    ///     "EUR" => Ok(1.25),
+    ///     _ => Err(ForkError::runtime(format!("ValueError: unknown currency {}", currency))),
+    /// });
+    /// assert_eq!(engine.eval("fetch_rate(\"EUR\") * 4").unwrap(), Value::Number(5.0));
+    /// let error = engine.eval("x = 1;\nfetch_rate(\"XYZ\");").unwrap_err();
+    /// assert_eq!(error.to_string(), "ValueError: unknown currency XYZ (line 2)");
+    /// ```
+    ///
+    /// An error returned by the closure ends the script as a runtime error
+    /// at the call. Functions defined by a script take precedence.
+    pub fn register_fn<Args, F: NativeFunction<Args> + 'static>(&mut self, name: &str, function: F) {
+        let owned = name.to_string();
+        self.interpreter.define_native(name, F::ARITY, move |args| function.call(&owned, args).map_err(ForkError::message));
+    }
+
+    /// Like `register_fn`, for a closure that takes the arguments as values.
+    /// Calls with other than `arity` arguments are a `TypeError`.
+    pub fn register_raw_fn(&mut self, name: &str, arity: usize, function: impl Fn(&[Value]) -> Result<Value, ForkError> + 'static) {
+        self.interpreter.define_native(name, arity, move |args| function(args).map_err(ForkError::message));
+    }
+
+    /// Grants scripts access to files, the environment, the clock, random
+    /// numbers or exiting, all of which are denied at first.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.interpreter.set_capabilities(capabilities);
+    }
+
+    /// Makes a script raise `MemoryError` once its variables would hold more
+    /// than `limit` bytes of strings, lists and dicts. See `Interpreter::memory`
+    /// for what they hold.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.interpreter.set_memory_limit(limit);
+    }
+
+    /// Sends what scripts print to `output` instead of stdout: a
+    /// `streams::Capture` to keep it, a `streams::Tee` to also show it, or
+    /// `io::sink()` to discard it.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.interpreter.set_output(output);
+    }
+
+    /// Makes `input()` read from `input` instead of stdin.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.interpreter.set_input(input);
+    }
+
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,289 @@
+//! `fork fmt`: prints a program back in the canonical style. The syntax tree
+//! has no comments, so they are taken from the token stream and put back
+//! between the statements they were found between.
+use std::collections::HashSet;
+use crate::ast::{BinOp, Expr, ExprKind, Program, Stmt, StmtKind};
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, Parser};
+use crate::token::{Span, Token};
+
+/// One level of indentation.
+const INDENT: &str = "    ";
+
+/// Formats a whole source file. The result always ends with a newline, and
+/// formatting it again gives the same text.
+pub fn format_source(source: &str) -> Result<String, ParseError> {
+    let tokens = Lexer::new().tokenize_with_spans(source);
+    let program = Parser::new(tokens.clone()).parse()?;
+    Ok(Printer::new(source, &tokens).program(&program))
+}
+
+/// Prints a syntax tree in the canonical style. The tree has no comments, so
+/// the result has none either.
+pub fn format_program(program: &Program) -> String {
+    Printer::new("", &[]).program(program)
+}
+
+/// A comment and whether code comes before it on its line.
+struct Comment {
+    text: String,
+    span: Span,
+    trailing: bool,
+}
+
+struct Printer<'a> {
+    /// Tokens other than whitespace and comments.
+    tokens: Vec<&'a (Token, Span)>,
+    comments: Vec<Comment>,
+    /// Index of the first comment not printed yet.
+    next_comment: usize,
+    /// Source positions of each `{` and its matching `}`.
+    braces: Vec<(usize, usize)>,
+    /// Source lines holding only whitespace. One blank line is kept wherever
+    /// the source has one or more.
+    blank_lines: HashSet<usize>,
+    lines: Vec<String>,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(source: &str, tokens: &'a [(Token, Span)]) -> Self {
+        let mut comments = Vec::new();
+        let mut code_lines = HashSet::new();
+        let mut braces = Vec::new();
+        let mut open = Vec::new();
+        for token in tokens {
+            let (kind, span) = token;
+            match kind {
+                Token::Whitespace => continue,
+                Token::Comment(text) => {
+                    let text = text.trim_end().to_string();
+                    comments.push(Comment { text, span: *span, trailing: code_lines.contains(&span.line) });
+                    continue;
+                }
+                Token::Symbol('{') => open.push(span.start),
+                Token::Symbol('}') => {
+                    if let Some(start) = open.pop() {
+                        braces.push((start, span.start));
+                    }
+                }
+                _ => {}
+            }
+            code_lines.insert(span.line);
+        }
+        braces.sort_unstable();
+        let blank_lines = source.lines().enumerate().filter(|(_, line)| line.trim().is_empty()).map(|(idx, _)| idx + 1).collect();
+        Printer {
+            tokens: tokens.iter().filter(|(token, _)| !matches!(token, Token::Whitespace | Token::Comment(_))).collect(),
+            comments,
+            next_comment: 0,
+            braces,
+            blank_lines,
+            lines: Vec::new(),
+            indent: 0,
+        }
+    }
+
+    fn program(mut self, program: &Program) -> String {
+        for stmt in &program.stmts {
+            self.stmt(stmt);
+        }
+        self.comments_before(usize::MAX);
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        text
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.comments_before(stmt.span.start);
+        self.blank_line_before(stmt.span.line);
+        match &stmt.kind {
+            StmtKind::If { .. } => self.if_chain(stmt, ""),
+            StmtKind::While { cond, body } => {
+                self.line(format!("while ({}) {{", expr(cond)));
+                self.block(body, stmt.span.start);
+                self.line("}".to_string());
+            }
+            StmtKind::For { init, cond, update, body } => {
+                let mut header = init.as_deref().map(|init| self.for_init(init)).unwrap_or_default();
+                for part in [cond.as_ref().map(expr), update.as_deref().map(simple)] {
+                    header.push(';');
+                    if let Some(part) = part {
+                        header.push(' ');
+                        header.push_str(&part);
+                    }
+                }
+                self.line(format!("for ({}) {{", header));
+                self.block(body, stmt.span.start);
+                self.line("}".to_string());
+            }
+            StmtKind::Def { body, .. } => {
+                self.line(format!("{} {{", signature(stmt)));
+                self.block(body, stmt.span.start);
+                self.line("}".to_string());
+            }
+            _ => self.line(format!("{};", simple(stmt))),
+        }
+    }
+
+    /// Prints an `if` and its `else if` chain, with each `else` on the line
+    /// of the closing brace before it.
+    fn if_chain(&mut self, stmt: &Stmt, prefix: &str) {
+        let StmtKind::If { cond, then_block, else_block } = &stmt.kind else {
+            unreachable!("if_chain is only called on if statements");
+        };
+        self.line(format!("{}if ({}) {{", prefix, expr(cond)));
+        let then_end = self.block(then_block, stmt.span.start);
+        match else_block.as_deref() {
+            // `else if` is written without braces; `else { if ... }` keeps them
+            Some([nested]) if matches!(nested.kind, StmtKind::If { .. }) && !self.else_has_brace(then_end) => {
+                self.if_chain(nested, "} else ");
+            }
+            Some(else_block) => {
+                self.line("} else {".to_string());
+                self.block(else_block, then_end);
+                self.line("}".to_string());
+            }
+            None => self.line("}".to_string()),
+        }
+    }
+
+    /// Prints the statements of the first block opened after `after`, and
+    /// the comments before its closing brace. Returns where the brace is.
+    fn block(&mut self, stmts: &[Stmt], after: usize) -> usize {
+        let close = self.braces.iter().find(|&&(open, _)| open >= after).map_or(usize::MAX, |&(_, close)| close);
+        self.indent += 1;
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.comments_before(close);
+        self.indent -= 1;
+        close
+    }
+
+    /// A `for` initializer, keeping the `int i = 0` form if it was written so.
+    fn for_init(&self, init: &Stmt) -> String {
+        let typed = self.tokens.iter().any(|(token, span)| span.start == init.span.start && matches!(token, Token::Identifier(_)));
+        match &init.kind {
+            StmtKind::Let { name, ty: Some(ty), value } if typed => format!("{} {} = {}", ty.name(), name, expr(value)),
+            _ => simple(init),
+        }
+    }
+
+    /// True if the `else` following the brace at `close` opens a block.
+    fn else_has_brace(&self, close: usize) -> bool {
+        let mut rest = self.tokens.iter().filter(|(_, span)| span.start > close).skip(1);
+        matches!(rest.next(), Some((Token::Symbol('{'), _)))
+    }
+
+    /// Prints the comments that start before `pos`. A comment after code is
+    /// kept at the end of the last printed line.
+    fn comments_before(&mut self, pos: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment).filter(|comment| comment.span.start < pos) {
+            let text = format!("//{}", comment.text);
+            let (trailing, line) = (comment.trailing, comment.span.line);
+            self.next_comment += 1;
+            match self.lines.last_mut() {
+                Some(last) if trailing => {
+                    last.push_str("  ");
+                    last.push_str(&text);
+                }
+                _ => {
+                    self.blank_line_before(line);
+                    self.line(text);
+                }
+            }
+        }
+    }
+
+    /// Keeps a blank line the source has before `line`, except at the start
+    /// of the file or of a block.
+    fn blank_line_before(&mut self, line: usize) {
+        let opens_block = self.lines.last().is_none_or(|last| last.ends_with('{'));
+        if !opens_block && self.blank_lines.contains(&(line - 1)) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn line(&mut self, text: String) {
+        self.lines.push(format!("{}{}", INDENT.repeat(self.indent), text));
+    }
+}
+
+/// The header of a `def` statement, `def name(params) -> type`, in the
+/// canonical style.
+pub fn signature(def: &Stmt) -> String {
+    let StmtKind::Def { name, params, ret, .. } = &def.kind else {
+        unreachable!("signature is only called on def statements");
+    };
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| match param.ty {
+            Some(ty) => format!("{}: {}", param.name, ty.name()),
+            None => param.name.clone(),
+        })
+        .collect();
+    let ret = ret.map(|ty| format!(" -> {}", ty.name())).unwrap_or_default();
+    format!("def {}({}){}", name, params.join(", "), ret)
+}
+
+/// A statement that fits on one line, without its semicolon.
+fn simple(stmt: &Stmt) -> String {
+    match &stmt.kind {
+        StmtKind::Assign { name, value } => format!("{} = {}", name, expr(value)),
+        StmtKind::Let { name, ty: Some(ty), value } => format!("let {}: {} = {}", name, ty.name(), expr(value)),
+        StmtKind::Let { name, ty: None, value } => format!("let {} = {}", name, expr(value)),
+        StmtKind::Increment { name, amount } => format!("{}{}", name, if *amount > 0.0 { "++" } else { "--" }),
+        StmtKind::Print(args) => format!("print({})", list(args)),
+        StmtKind::Return(Some(value)) => format!("return {}", expr(value)),
+        StmtKind::Return(None) => "return".to_string(),
+        StmtKind::Expr(value) => expr(value),
+        StmtKind::If { .. } | StmtKind::While { .. } | StmtKind::For { .. } | StmtKind::Def { .. } => {
+            unreachable!("compound statements are printed by Printer::stmt")
+        }
+    }
+}
+
+fn list(args: &[Expr]) -> String {
+    args.iter().map(expr).collect::<Vec<_>>().join(", ")
+}
+
+/// Prints an expression with a space around each binary operator and only
+/// the parentheses its meaning needs.
+fn expr(e: &Expr) -> String {
+    match &e.kind {
+        ExprKind::Number(n) => n.to_string(),
+        ExprKind::Str(s) => format!("\"{}\"", s),
+        ExprKind::Var(name) => name.clone(),
+        ExprKind::Call { name, args } => format!("{}({})", name, list(args)),
+        ExprKind::Unary { expr: operand, .. } => match operand.kind {
+            ExprKind::Binary { .. } => format!("-({})", expr(operand)),
+            _ => format!("-{}", expr(operand)),
+        },
+        ExprKind::Binary { op, lhs, rhs } => {
+            // Operators group to the left, so a right operand of the same
+            // precedence needs parentheses: `a - (b - c)`
+            let lhs = operand(lhs, precedence(*op));
+            let rhs = operand(rhs, precedence(*op) + 1);
+            format!("{} {} {}", lhs, op.symbol(), rhs)
+        }
+    }
+}
+
+/// Prints `e` in parentheses if it binds less tightly than `min`.
+fn operand(e: &Expr, min: u8) -> String {
+    match &e.kind {
+        ExprKind::Binary { op, .. } if precedence(*op) < min => format!("({})", expr(e)),
+        _ => expr(e),
+    }
+}
+
+/// Binding strength of a binary operator, as in the parser.
+fn precedence(op: BinOp) -> u8 {
+    match op {
+        BinOp::Eq | BinOp::Ne => 1,
+        BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => 2,
+        BinOp::Add | BinOp::Sub => 3,
+        BinOp::Mul | BinOp::Div => 4,
+    }
+}
@@ -1,5 +1,24 @@
-use crate::token::Token;
+use std::collections::HashMap;
+use std::fmt;
+use crate::ast::{BinOp, Expr, ExprKind, Program, Stmt, StmtKind};
+use crate::ir::{Instr, InstrKind, IrFunction, IrProgram, Label, Operand, Var, PRINT};
+use crate::token::Span;
 
+/// A call that can never succeed: an unknown function or the wrong number of arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (line {}, column {})", self.message, self.span.line, self.span.col)
+    }
+}
+
+/// Intermediate code generator: lowers the syntax tree to three-address code.
+#[allow(clippy::upper_case_acronyms)]
 pub struct ICG;
 
 impl ICG {
@@ -7,12 +26,217 @@ impl ICG {
         ICG
     }
 
-    pub fn generate(&self, tokens: &[Token]) {
-        // For demonstration, just print a stub for real intermediate code generation
+    /// Lowers a program into an IR program whose `<main>` function holds the top-level code,
+    /// followed by one function per `def` in declaration order.
+    pub fn generate(&self, program: &Program) -> Result<IrProgram, CompileError> {
+        let mut arities = HashMap::new();
+        for stmt in &program.stmts {
+            if let StmtKind::Def { name, params, .. } = &stmt.kind {
+                arities.insert(name.clone(), params.len());
+            }
+        }
 
-        for (_i, _token) in tokens.iter().enumerate() {
+        let mut lowering = Lowering::new("<main>", Vec::new(), &arities);
+        lowering.block(&program.stmts);
+        let mut functions = vec![lowering.finish()?];
+        for stmt in &program.stmts {
+            if let StmtKind::Def { name, params, body, .. } = &stmt.kind {
+                let params = params.iter().map(|param| param.name.clone()).collect();
+                let mut lowering = Lowering::new(name, params, &arities);
+                lowering.line = stmt.span.line;
+                lowering.block(body);
+                functions.push(lowering.finish()?);
+            }
+        }
+        Ok(IrProgram { functions })
+    }
+}
 
+impl Default for ICG {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lowering state for one function: fresh temporaries and labels.
+struct Lowering<'a> {
+    function: IrFunction,
+    next_temp: u32,
+    next_label: u32,
+    line: usize,
+    /// Parameter count of every user-defined function.
+    arities: &'a HashMap<String, usize>,
+    /// The first bad call found, reported once lowering finishes.
+    error: Option<CompileError>,
+}
+
+impl<'a> Lowering<'a> {
+    fn new(name: &str, params: Vec<String>, arities: &'a HashMap<String, usize>) -> Self {
+        Lowering {
+            function: IrFunction { name: name.to_string(), params, body: Vec::new() },
+            next_temp: 0,
+            next_label: 0,
+            line: 0,
+            arities,
+            error: None,
         }
+    }
+
+    /// Ends the function with an implicit `return` and hands it over.
+    fn finish(mut self) -> Result<IrFunction, CompileError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.emit(InstrKind::Return(None));
+        Ok(self.function)
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.line = stmt.span.line;
+        match &stmt.kind {
+            StmtKind::Assign { name, value } | StmtKind::Let { name, value, .. } => {
+                self.expr_into(Var::Named(name.clone()), value)
+            }
+            StmtKind::Increment { name, amount } => {
+                let var = Var::Named(name.clone());
+                let op = if *amount > 0.0 { BinOp::Add } else { BinOp::Sub };
+                self.emit(InstrKind::Binary { dest: var.clone(), op, lhs: Operand::Var(var), rhs: Operand::Number(1.0) });
+            }
+            StmtKind::Print(args) => {
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                self.emit(InstrKind::Call { dest: None, func: PRINT.to_string(), args });
+            }
+            StmtKind::Expr(expr) => match &expr.kind {
+                ExprKind::Call { name, args } => self.call(None, name, args, expr.span),
+                _ => {
+                    self.expr(expr);
+                }
+            },
+            StmtKind::Return(value) => {
+                let value = value.as_ref().map(|value| self.expr(value));
+                self.emit(InstrKind::Return(value));
+            }
+            // Function bodies are lowered separately by `ICG::generate`
+            StmtKind::Def { .. } => {}
+            StmtKind::If { cond, then_block, else_block } => {
+                let cond = self.expr(cond);
+                let else_label = self.label();
+                self.emit(InstrKind::JumpIfFalse { cond, target: else_label });
+                self.block(then_block);
+                match else_block {
+                    Some(else_block) => {
+                        let end_label = self.label();
+                        self.emit(InstrKind::Jump(end_label));
+                        self.emit(InstrKind::Label(else_label));
+                        self.block(else_block);
+                        self.emit(InstrKind::Label(end_label));
+                    }
+                    None => self.emit(InstrKind::Label(else_label)),
+                }
+            }
+            StmtKind::While { cond, body } => {
+                let top = self.label();
+                let end = self.label();
+                self.emit(InstrKind::Label(top));
+                let cond = self.expr(cond);
+                self.emit(InstrKind::JumpIfFalse { cond, target: end });
+                self.block(body);
+                self.line = stmt.span.line;
+                self.emit(InstrKind::Jump(top));
+                self.emit(InstrKind::Label(end));
+            }
+            StmtKind::For { init, cond, update, body } => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                let top = self.label();
+                let end = self.label();
+                self.line = stmt.span.line;
+                self.emit(InstrKind::Label(top));
+                if let Some(cond) = cond {
+                    let cond = self.expr(cond);
+                    self.emit(InstrKind::JumpIfFalse { cond, target: end });
+                }
+                self.block(body);
+                if let Some(update) = update {
+                    self.stmt(update);
+                }
+                self.line = stmt.span.line;
+                self.emit(InstrKind::Jump(top));
+                self.emit(InstrKind::Label(end));
+            }
+        }
+    }
+
+    /// Lowers an expression to an operand, storing intermediate results in temporaries.
+    fn expr(&mut self, expr: &Expr) -> Operand {
+        match &expr.kind {
+            ExprKind::Number(n) => Operand::Number(*n),
+            ExprKind::Str(s) => Operand::Str(s.clone()),
+            ExprKind::Var(name) => Operand::Var(Var::Named(name.clone())),
+            ExprKind::Unary { .. } | ExprKind::Binary { .. } | ExprKind::Call { .. } => {
+                let dest = self.temp();
+                self.expr_into(dest.clone(), expr);
+                Operand::Var(dest)
+            }
+        }
+    }
+
+    /// Lowers an expression whose result goes straight into `dest`.
+    fn expr_into(&mut self, dest: Var, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Unary { op, expr } => {
+                let src = self.expr(expr);
+                self.emit(InstrKind::Unary { dest, op: *op, src });
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                let lhs = self.expr(lhs);
+                let rhs = self.expr(rhs);
+                self.emit(InstrKind::Binary { dest, op: *op, lhs, rhs });
+            }
+            ExprKind::Call { name, args } => self.call(Some(dest), name, args, expr.span),
+            _ => {
+                let src = self.expr(expr);
+                self.emit(InstrKind::Copy { dest, src });
+            }
+        }
+    }
+
+    /// Lowers a call to a user-defined function, checking that it exists and gets its arguments.
+    fn call(&mut self, dest: Option<Var>, name: &str, args: &[Expr], span: Span) {
+        let message = match self.arities.get(name) {
+            None => Some(format!("NameError: name '{}' is not defined", name)),
+            Some(&arity) if arity != args.len() => {
+                Some(format!("TypeError: {}() takes {} arguments but {} were given", name, arity, args.len()))
+            }
+            Some(_) => None,
+        };
+        if let Some(message) = message
+            && self.error.is_none()
+        {
+            self.error = Some(CompileError { message, span });
+        }
+        let args = args.iter().map(|arg| self.expr(arg)).collect();
+        self.emit(InstrKind::Call { dest, func: name.to_string(), args });
+    }
+
+    fn temp(&mut self) -> Var {
+        self.next_temp += 1;
+        Var::Temp(self.next_temp - 1)
+    }
+
+    fn label(&mut self) -> Label {
+        self.next_label += 1;
+        Label(self.next_label - 1)
+    }
 
+    fn emit(&mut self, kind: InstrKind) {
+        self.function.body.push(Instr { kind, line: self.line });
     }
 }
@@ -0,0 +1,110 @@
+//! Functions every program can call without defining them. They run on the
+//! interpreter, so they can use its input and output, and each is checked
+//! against its capabilities.
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::ast::TypeName;
+use crate::value::{RuntimeError, Value};
+use super::capabilities::Capability;
+use super::Interpreter;
+
+/// A builtin's name, parameter types and result type, and what it needs to
+/// be allowed to do.
+pub struct Builtin {
+    pub name: &'static str,
+    pub params: &'static [Option<TypeName>],
+    pub ret: Option<TypeName>,
+    pub needs: Option<Capability>,
+}
+
+const STR: Option<TypeName> = Some(TypeName::Str);
+
+pub const BUILTINS: &[Builtin] = &[
+    // input(prompt): writes the prompt, then reads a line without its line break
+    Builtin { name: "input", params: &[None], ret: STR, needs: None },
+    // read_file(path): the contents of a file
+    Builtin { name: "read_file", params: &[STR], ret: STR, needs: Some(Capability::Read) },
+    // write_file(path, text): replaces the contents of a file
+    Builtin { name: "write_file", params: &[STR, None], ret: Some(TypeName::Int), needs: Some(Capability::Write) },
+    // env(name): an environment variable, or "" if it is not set
+    Builtin { name: "env", params: &[STR], ret: STR, needs: Some(Capability::Env) },
+    // time(): seconds since the Unix epoch
+    Builtin { name: "time", params: &[], ret: Some(TypeName::Float), needs: Some(Capability::Clock) },
+    // random(): a number from 0 up to but not including 1
+    Builtin { name: "random", params: &[], ret: Some(TypeName::Float), needs: Some(Capability::Random) },
+    // exit(code): ends the program with an exit status
+    Builtin { name: "exit", params: &[Some(TypeName::Int)], ret: Some(TypeName::Int), needs: Some(Capability::Exit) },
+];
+
+/// Message of the error that unwinds the program after `exit`.
+pub const EXIT: &str = "SystemExit";
+
+impl Interpreter {
+    /// Runs the builtin `name`, or returns `None` if there is none.
+    pub(crate) fn call_builtin(&mut self, name: &str, args: &[Value], line: usize) -> Option<Result<Value, RuntimeError>> {
+        let builtin = BUILTINS.iter().find(|builtin| builtin.name == name)?;
+        let fail = |message: String| RuntimeError { message, line: Some(line) };
+        if args.len() != builtin.params.len() {
+            return Some(Err(fail(format!("TypeError: {}() takes {} arguments but {} were given", name, builtin.params.len(), args.len()))));
+        }
+        let result = self.run_builtin(builtin, args);
+        Some(result.map_err(fail))
+    }
+
+    fn run_builtin(&mut self, builtin: &Builtin, args: &[Value]) -> Result<Value, String> {
+        let string = |idx: usize| match &args[idx] {
+            Value::Str(s) => Ok(s.as_str()),
+            value => Err(format!("TypeError: {}() argument {} must be a string, not {}", builtin.name, idx + 1, value.type_name())),
+        };
+        if let Some(capability) = builtin.needs {
+            let path = matches!(capability, Capability::Read | Capability::Write).then(|| string(0)).transpose()?;
+            self.capabilities.check(capability, path)?;
+        }
+        let os_error = |e: io::Error| format!("OSError: {}", e);
+        match builtin.name {
+            "input" => self.input(&args[0]),
+            "read_file" => fs::read_to_string(string(0)?).map(Value::Str).map_err(os_error),
+            "write_file" => fs::write(string(0)?, args[1].to_string()).map(|()| Value::Number(0.0)).map_err(os_error),
+            "env" => Ok(Value::Str(std::env::var(string(0)?).unwrap_or_default())),
+            "time" => Ok(Value::Number(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64())),
+            "random" => Ok(Value::Number(self.random())),
+            "exit" => {
+                let code = match &args[0] {
+                    Value::Number(n) => *n as i32,
+                    value => return Err(format!("TypeError: exit() argument 1 must be a number, not {}", value.type_name())),
+                };
+                self.exit_code = Some(code);
+                Err(format!("{}: {}", EXIT, code))
+            }
+            _ => unreachable!("every builtin is run"),
+        }
+    }
+
+    fn input(&mut self, prompt: &Value) -> Result<Value, String> {
+        let os_error = |e: io::Error| format!("OSError: {}", e);
+        write!(self.output, "{}", prompt).and_then(|()| self.output.flush()).map_err(os_error)?;
+        let mut line = String::new();
+        let read = match self.input.as_mut() {
+            Some(input) => input.read_line(&mut line),
+            None => io::stdin().read_line(&mut line),
+        };
+        if read.map_err(os_error)? == 0 {
+            return Err("EOFError: EOF when reading a line".to_string());
+        }
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Ok(Value::Str(line))
+    }
+
+    /// The next number of a xorshift generator, seeded on first use.
+    fn random(&mut self) -> f64 {
+        let state = self.rng.get_or_insert_with(|| RandomState::new().hash_one(SystemTime::now()) | 1);
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
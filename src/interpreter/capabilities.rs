@@ -0,0 +1,95 @@
+//! What the builtins of an interpreter may do outside it. Everything is
+//! denied unless granted; a denied builtin raises `PermissionError`.
+use std::path::{Path, PathBuf};
+
+/// Files a builtin may use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Paths {
+    Any,
+    /// Files in these directories or below them.
+    Under(Vec<PathBuf>),
+}
+
+impl Paths {
+    /// Whether `path` may be used. It is resolved first, so `..` and links
+    /// cannot lead out of an allowed directory.
+    fn allows(&self, path: &Path) -> bool {
+        let dirs = match self {
+            Paths::Any => return true,
+            Paths::Under(dirs) => dirs,
+        };
+        // A file to be created does not exist yet, but its directory must
+        let resolved = path.canonicalize().or_else(|_| {
+            let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            parent.canonicalize().map(|parent| parent.join(path.file_name().unwrap_or_default()))
+        });
+        let Ok(resolved) = resolved else {
+            return false;
+        };
+        dirs.iter().filter_map(|dir| dir.canonicalize().ok()).any(|dir| resolved.starts_with(dir))
+    }
+}
+
+/// What a builtin needs to be allowed to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Reading the file named by the first argument.
+    Read,
+    /// Writing the file named by the first argument.
+    Write,
+    Env,
+    Clock,
+    Random,
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub read: Paths,
+    pub write: Paths,
+    pub env: bool,
+    pub clock: bool,
+    pub random: bool,
+    pub exit: bool,
+}
+
+impl Capabilities {
+    pub fn none() -> Self {
+        Capabilities {
+            read: Paths::Under(Vec::new()),
+            write: Paths::Under(Vec::new()),
+            env: false,
+            clock: false,
+            random: false,
+            exit: false,
+        }
+    }
+
+    pub fn all() -> Self {
+        Capabilities { read: Paths::Any, write: Paths::Any, env: true, clock: true, random: true, exit: true }
+    }
+
+    /// Checks that `capability` is granted, for the file `path` if it
+    /// concerns files.
+    pub fn check(&self, capability: Capability, path: Option<&str>) -> Result<(), String> {
+        let path = path.unwrap_or_default();
+        let (allowed, what) = match capability {
+            Capability::Read => (self.read.allows(Path::new(path)), format!("reading '{}'", path)),
+            Capability::Write => (self.write.allows(Path::new(path)), format!("writing '{}'", path)),
+            Capability::Env => (self.env, "reading the environment".to_string()),
+            Capability::Clock => (self.clock, "reading the clock".to_string()),
+            Capability::Random => (self.random, "random numbers".to_string()),
+            Capability::Exit => (self.exit, "exiting the process".to_string()),
+        };
+        if allowed {
+            return Ok(());
+        }
+        Err(format!("PermissionError: {} is not allowed", what))
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::none()
+    }
+}
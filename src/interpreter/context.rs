@@ -1,15 +1,165 @@
 //! Interpreter context: holds variable state for execution.
 use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use crate::ast::Stmt;
+use crate::value::Value;
+use super::capabilities::Capabilities;
+use super::debug::Debugger;
+use super::memory::Memory;
+
+/// Calls nested deeper than this raise a `RecursionError`.
+pub(crate) const MAX_CALL_DEPTH: usize = 1000;
+
+/// A user-defined function.
+pub(crate) struct Function {
+    pub(crate) params: Vec<String>,
+    pub(crate) body: Vec<Stmt>,
+}
+
+/// The body of a function written in Rust. Its error message becomes a
+/// runtime error at the call.
+pub(crate) type NativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+pub(crate) struct Native {
+    pub(crate) arity: usize,
+    pub(crate) function: Rc<NativeFn>,
+}
+
+/// A caller waiting for a call to return.
+pub(crate) struct Frame {
+    /// The caller's function, or `None` for the top level.
+    pub(crate) function: Option<String>,
+    /// Line of the call.
+    pub(crate) line: usize,
+    pub(crate) variables: HashMap<String, Value>,
+}
 
 /// Holds the variable context for the interpreter.
 pub struct Interpreter {
-    pub(crate) variables: HashMap<String, f64>,
+    /// Variables of the running function, or the top-level variables outside any call.
+    pub(crate) variables: HashMap<String, Value>,
+    pub(crate) functions: HashMap<String, Rc<Function>>,
+    pub(crate) natives: HashMap<String, Native>,
+    /// Number of calls currently executing.
+    pub(crate) depth: usize,
+    /// The function being run, if any.
+    pub(crate) current: Option<String>,
+    /// Callers of the running function, outermost first.
+    pub(crate) frames: Vec<Frame>,
+    /// Called around every statement while attached.
+    pub(crate) debugger: Option<Box<dyn Debugger>>,
+    /// Where `print` and the prompts of `input` are written.
+    pub(crate) output: Box<dyn Write>,
+    /// Where `input` reads from, or `None` for stdin.
+    pub(crate) input: Option<Box<dyn BufRead>>,
+    /// What the builtins may do outside the interpreter.
+    pub(crate) capabilities: Capabilities,
+    /// State of `random`, seeded on first use.
+    pub(crate) rng: Option<u64>,
+    /// The status passed to `exit`, once called.
+    pub(crate) exit_code: Option<i32>,
+    pub(crate) memory: Memory,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         Interpreter {
             variables: HashMap::new(),
+            functions: HashMap::new(),
+            natives: HashMap::new(),
+            depth: 0,
+            current: None,
+            frames: Vec::new(),
+            debugger: None,
+            output: Box::new(io::stdout()),
+            input: None,
+            capabilities: Capabilities::none(),
+            rng: None,
+            exit_code: None,
+            memory: Memory::default(),
         }
     }
+
+    /// Forgets the variables and the functions defined by scripts, keeping
+    /// the native functions and the settings.
+    pub fn reset(&mut self) {
+        self.variables.clear();
+        self.functions.clear();
+        self.frames.clear();
+        self.depth = 0;
+        self.current = None;
+        self.exit_code = None;
+        self.memory.used = 0;
+    }
+
+    /// Names of the top-level variables assigned so far.
+    pub fn global_names(&self) -> Vec<String> {
+        self.globals().keys().cloned().collect()
+    }
+
+    /// The value of a top-level variable, even while a function is running.
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals().get(name)
+    }
+
+    /// Assigns a top-level variable, even while a function is running.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        let globals = match self.frames.first_mut() {
+            Some(frame) => &mut frame.variables,
+            None => &mut self.variables,
+        };
+        let new = value.heap_size();
+        let old = globals.insert(name.to_string(), value).map_or(0, |old| old.heap_size());
+        self.memory.used = self.memory.used - old + new;
+        self.memory.peak = self.memory.peak.max(self.memory.used);
+    }
+
+    /// The top-level variables: those of the outermost frame while a function runs.
+    pub(crate) fn globals(&self) -> &HashMap<String, Value> {
+        self.frames.first().map_or(&self.variables, |frame| &frame.variables)
+    }
+
+    /// Sends what the program prints to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Makes `input` read from `input` instead of stdin.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    /// Grants the builtins what they may do outside the interpreter.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// The status passed to `exit`, if the program called it. The program
+    /// then stops with a `SystemExit` error.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Every function defined so far, native or not, with its number of parameters.
+    pub fn function_arities(&self) -> Vec<(String, usize)> {
+        let natives = self.natives.iter().map(|(name, native)| (name.clone(), native.arity));
+        self.functions.iter().map(|(name, function)| (name.clone(), function.params.len())).chain(natives).collect()
+    }
+
+    /// Makes a Rust function callable from scripts as `name`. Functions
+    /// defined by a script take precedence.
+    pub fn define_native(&mut self, name: &str, arity: usize, function: impl Fn(&[Value]) -> Result<Value, String> + 'static) {
+        self.natives.insert(name.to_string(), Native { arity, function: Rc::new(function) });
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -1,71 +1,71 @@
-use crate::token::Token;
+use std::rc::Rc;
+use crate::ast::{Program, Stmt, StmtKind};
+use crate::value::{RuntimeError, Value};
+use super::super::context::Function;
+use super::super::debug::Event;
 use super::super::Interpreter;
 
-impl Interpreter {
-    /// Interpret tokens, handling control flow (e.g. while loops)
-    pub fn interpret(&mut self, tokens: &[Token]) {
-    // // println!("interpret: tokens = {:?}", tokens);
-        let mut i = 0;
-        while i < tokens.len() {
-            // // println!("[DEBUG] [TOP LEVEL] i: {}, next tokens: {:?}", i, &tokens[i..(i+5).min(tokens.len())]);
-            match &tokens[i] {
-                Token::Keyword(k) if k == "for" => {
-                    self.interpret_for_loop(tokens, &mut i);
-                    continue;
-                }
-                Token::Keyword(k) if k == "if" => {
-                    self.interpret_if_else(tokens, &mut i);
-                    continue;
-                }
-                Token::Keyword(k) if k == "while" => {
-    self.interpret_while_loop(tokens, &mut i);
-    continue;
+/// How a block of statements finished.
+pub enum Flow {
+    /// Ran to the end.
+    Normal,
+    /// Hit a `return`, carrying the returned value.
+    Return(Value),
+    /// Hit a `return` of a call to the running function itself, carrying the
+    /// arguments to rerun it with.
+    TailCall(Vec<Value>),
 }
-                _ => {
-    // Interpret one top-level statement (assignment, print, etc.)
-    // Find the end of the statement (usually at a semicolon)
-    let mut stmt_end = i;
-    let mut in_block = false;
-    let mut brace_count = 0;
-    while stmt_end < tokens.len() {
-        match &tokens[stmt_end] {
-            Token::Symbol('{') => {
-                in_block = true;
-                brace_count += 1;
+
+impl Interpreter {
+    /// Runs a program. Functions are defined before any statement runs, so
+    /// they may be called above their definition.
+    pub fn interpret(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        self.interpret_value(program).map(drop)
+    }
+
+    /// Runs a program like `interpret`, returning the value of its last
+    /// statement if that is an expression, or `0` if it is not.
+    pub fn interpret_value(&mut self, program: &Program) -> Result<Value, RuntimeError> {
+        for stmt in &program.stmts {
+            if let StmtKind::Def { name, params, body, .. } = &stmt.kind {
+                let params = params.iter().map(|param| param.name.clone()).collect();
+                let function = Function { params, body: body.clone() };
+                self.functions.insert(name.clone(), Rc::new(function));
             }
-            Token::Symbol('}') => {
-                if brace_count > 0 {
-                    brace_count -= 1;
-                    if brace_count == 0 {
-                        stmt_end += 1;
-                        break;
-                    }
-                }
+        }
+        let Some((last, rest)) = program.stmts.split_last() else {
+            return Ok(Value::Number(0.0));
+        };
+        self.interpret_block(rest)?;
+        let StmtKind::Expr(expr) = &last.kind else {
+            self.interpret_block(std::slice::from_ref(last))?;
+            return Ok(Value::Number(0.0));
+        };
+        if self.debugger.is_some() {
+            self.debug_event(last, Event::Before)?;
+        }
+        let value = self.eval_expression(expr)?;
+        if self.debugger.is_some() {
+            self.debug_event(last, Event::After)?;
+        }
+        Ok(value)
+    }
+
+    /// Executes statements in order, stopping early at a `return`. An
+    /// attached debugger is called before and after each statement.
+    pub fn interpret_block(&mut self, stmts: &[Stmt]) -> Result<Flow, RuntimeError> {
+        for stmt in stmts {
+            if self.debugger.is_some() {
+                self.debug_event(stmt, Event::Before)?;
             }
-            Token::Symbol(';') => {
-                if !in_block {
-                    stmt_end += 1;
-                    break;
-                }
+            let flow = self.interpret_one_statement(stmt)?;
+            if self.debugger.is_some() {
+                self.debug_event(stmt, Event::After)?;
             }
-            Token::Keyword(k) if k == "for" || k == "while" => {
-                break;
+            if !matches!(flow, Flow::Normal) {
+                return Ok(flow);
             }
-            _ => {}
         }
-        stmt_end += 1;
-    }
-    // Skip stray closing braces
-    if matches!(&tokens[i], Token::Symbol('}')) {
-        i += 1;
-        continue;
+        Ok(Flow::Normal)
     }
-    // Actually execute the statement
-    self.interpret_one_statement(&tokens[i..stmt_end]);
-    i = stmt_end;
 }
-            }
-        }
-        i += 1;
-    }
-}
\ No newline at end of file
@@ -0,0 +1,109 @@
+//! Hooks for debuggers: a call around every statement, and a view of the
+//! frames of a paused program.
+use std::collections::HashMap;
+use crate::ast::{Expr, Stmt};
+use crate::value::{RuntimeError, Value};
+use super::Interpreter;
+
+/// When a debugger is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The statement is about to run.
+    Before,
+    /// The statement has finished without an error.
+    After,
+}
+
+/// Receives control around every statement run by the interpreter it is
+/// attached to. Returning an error ends the program as a runtime error would.
+pub trait Debugger {
+    fn on_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt, event: Event) -> Result<(), RuntimeError>;
+
+    /// Takes a line printed by the program. Returns false to leave it to stdout.
+    fn print(&mut self, _line: &str) -> bool {
+        false
+    }
+
+    /// Called as the statement on `line` assigns `new` to a variable of the
+    /// running frame; `old` is `None` if it had no value.
+    fn write(&mut self, _name: &str, _old: Option<&Value>, _new: &Value, _line: usize) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    /// Called as a call to `function` starts running its body. A tail call
+    /// that reuses the frame counts as a new call.
+    fn enter(&mut self, _function: &str) {}
+
+    /// Called as a call to `function` finishes, even with an error.
+    fn leave(&mut self, _function: &str) {}
+
+    /// Called with the outcome of the condition of an `if` or a loop.
+    fn branch(&mut self, _cond: &Expr, _taken: bool) {}
+}
+
+/// One frame of a paused program.
+pub struct FrameView<'a> {
+    /// The running function, or `<main>` for the top level.
+    pub function: &'a str,
+    /// The line being run in this frame.
+    pub line: usize,
+    pub variables: &'a HashMap<String, Value>,
+}
+
+impl Interpreter {
+    pub fn attach(&mut self, debugger: Box<dyn Debugger>) {
+        self.debugger = Some(debugger);
+    }
+
+    pub(crate) fn debug_event(&mut self, stmt: &Stmt, event: Event) -> Result<(), RuntimeError> {
+        // Taken out while it runs, so the expressions it evaluates are not debugged
+        let Some(mut debugger) = self.debugger.take() else {
+            return Ok(());
+        };
+        let result = debugger.on_statement(self, stmt, event);
+        self.debugger = Some(debugger);
+        result
+    }
+
+    /// Variables of the running frame.
+    pub fn variables(&self) -> &HashMap<String, Value> {
+        &self.variables
+    }
+
+    /// The running function, or `<main>` for the top level.
+    pub fn function(&self) -> &str {
+        self.current.as_deref().unwrap_or("<main>")
+    }
+
+    /// Number of calls currently executing.
+    pub fn call_depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The frames of the program, innermost first, given the line the
+    /// innermost one is at.
+    pub fn backtrace(&self, line: usize) -> Vec<FrameView<'_>> {
+        let running = FrameView { function: self.function(), line, variables: &self.variables };
+        let callers = self.frames.iter().rev().map(|frame| FrameView {
+            function: frame.function.as_deref().unwrap_or("<main>"),
+            line: frame.line,
+            variables: &frame.variables,
+        });
+        std::iter::once(running).chain(callers).collect()
+    }
+
+    /// Evaluates `expr` with the variables of a frame, numbered as in
+    /// `backtrace`.
+    pub fn evaluate(&mut self, expr: &Expr, frame: usize) -> Result<Value, RuntimeError> {
+        if frame == 0 {
+            return self.eval_expression(expr);
+        }
+        let Some(idx) = self.frames.len().checked_sub(frame) else {
+            return Err(RuntimeError { message: format!("no frame {}", frame), line: None });
+        };
+        std::mem::swap(&mut self.variables, &mut self.frames[idx].variables);
+        let result = self.eval_expression(expr);
+        std::mem::swap(&mut self.variables, &mut self.frames[idx].variables);
+        result
+    }
+}
@@ -0,0 +1,58 @@
+//! Accounting of the memory held by the values of an interpreter's
+//! variables, with an optional limit that raises `MemoryError`.
+use crate::value::{RuntimeError, Value};
+use super::Interpreter;
+
+/// Bytes held by strings, lists and dicts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Memory {
+    /// Held by the variables of every frame.
+    pub used: usize,
+    /// The most ever used, including values about to be stored.
+    pub peak: usize,
+    pub limit: Option<usize>,
+}
+
+impl Interpreter {
+    pub fn memory(&self) -> Memory {
+        self.memory
+    }
+
+    /// Raises `MemoryError` once the variables would hold more than `limit`
+    /// bytes, or lifts the limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory.limit = limit;
+    }
+
+    /// Checks that a value of `bytes` can be held on top of what is used.
+    pub(crate) fn check_memory(&mut self, bytes: usize, line: usize) -> Result<(), RuntimeError> {
+        let total = self.memory.used + bytes;
+        if let Some(limit) = self.memory.limit
+            && total > limit
+        {
+            return Err(RuntimeError {
+                message: format!("MemoryError: {} bytes needed, over the limit of {} bytes", total, limit),
+                line: Some(line),
+            });
+        }
+        self.memory.peak = self.memory.peak.max(total);
+        Ok(())
+    }
+
+    /// Accounts for a variable's value changing from one of `old` bytes to
+    /// one of `new` bytes, if the limit allows it.
+    pub(crate) fn replace_memory(&mut self, old: usize, new: usize, line: usize) -> Result<(), RuntimeError> {
+        self.memory.used -= old;
+        if let Err(error) = self.check_memory(new, line) {
+            self.memory.used += old;
+            return Err(error);
+        }
+        self.memory.used += new;
+        Ok(())
+    }
+
+    /// Accounts for variables whose values are dropped.
+    pub(crate) fn release_memory<'a>(&mut self, values: impl IntoIterator<Item = &'a Value>) {
+        self.memory.used -= values.into_iter().map(Value::heap_size).sum::<usize>();
+    }
+}
@@ -1,5 +1,11 @@
 mod context;
+mod snapshot;
 mod statement;
+pub mod builtins;
+pub mod capabilities;
 pub mod control;
+pub mod debug;
+pub mod memory;
+pub mod streams;
 
 pub use context::Interpreter;
@@ -0,0 +1,112 @@
+//! Checkpoints of an interpreter: its top-level variables and functions as
+//! JSON, to be restored into a fresh interpreter later.
+//!
+//! ```json
+//! {"version":1,"globals":{"n":3,"name":"fork"},"functions":"def f(a) {\n    return a;\n}\n"}
+//! ```
+//!
+//! Functions are written as source. Lists are arrays and dicts objects; a
+//! number JSON cannot hold, such as `inf`, is an object `{"$number":"inf"}`.
+use std::collections::{BTreeMap, HashMap};
+use crate::ast::{Param, Program, Stmt, StmtKind};
+use crate::formatter::format_program;
+use crate::json::Json;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::Span;
+use crate::value::Value;
+use super::Interpreter;
+
+/// Version of the format written by `snapshot`.
+const VERSION: usize = 1;
+
+/// Key of the object standing for a number JSON cannot hold.
+const NUMBER: &str = "$number";
+
+impl Interpreter {
+    /// The top-level variables and the functions defined by scripts.
+    /// Native functions are left out: whoever restores the snapshot defines
+    /// them again. Where a running program has got to is not saved either,
+    /// so a snapshot taken during a call has only the top-level variables.
+    pub fn snapshot(&self) -> String {
+        let mut globals: Vec<(&String, &Value)> = self.globals().iter().collect();
+        globals.sort_by_key(|(name, _)| *name);
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort();
+        // A line of 1 keeps the printer from looking before the first line
+        let span = Span { line: 1, ..Span::default() };
+        let stmts = names
+            .into_iter()
+            .map(|name| {
+                let function = &self.functions[name];
+                let params = function.params.iter().map(|param| Param { name: param.clone(), ty: None, span }).collect();
+                Stmt { kind: StmtKind::Def { name: name.clone(), params, ret: None, body: function.body.clone() }, span }
+            })
+            .collect();
+        let functions = if self.functions.is_empty() { String::new() } else { format_program(&Program { stmts }) };
+        Json::object([
+            ("version", VERSION.into()),
+            ("globals", Json::Object(globals.into_iter().map(|(name, value)| (name.clone(), to_json(value))).collect())),
+            ("functions", functions.into()),
+        ])
+        .to_string()
+    }
+
+    /// Replaces the top-level variables and the functions defined by scripts
+    /// with those of a snapshot. Only possible outside any call.
+    pub fn restore(&mut self, snapshot: &str) -> Result<(), String> {
+        if self.depth > 0 {
+            return Err("a snapshot cannot be restored while a function is running".to_string());
+        }
+        let json = Json::parse(snapshot).map_err(|e| format!("invalid snapshot: {}", e))?;
+        if json.get("version").and_then(Json::as_usize) != Some(VERSION) {
+            return Err(format!("invalid snapshot: expected version {}", VERSION));
+        }
+        let Some(Json::Object(members)) = json.get("globals") else {
+            return Err("invalid snapshot: no globals".to_string());
+        };
+        let globals = members
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), from_json(value).map_err(|e| format!("invalid snapshot: {}: {}", name, e))?)))
+            .collect::<Result<HashMap<String, Value>, String>>()?;
+        let source = json.get("functions").and_then(Json::as_str).ok_or("invalid snapshot: no functions")?;
+        let program = Parser::new(Lexer::new().tokenize_with_spans(source))
+            .parse()
+            .map_err(|e| format!("invalid snapshot: functions: {}", e))?;
+        if program.stmts.iter().any(|stmt| !matches!(stmt.kind, StmtKind::Def { .. })) {
+            return Err("invalid snapshot: functions: only definitions are allowed".to_string());
+        }
+        self.memory.used = globals.values().map(Value::heap_size).sum();
+        self.memory.peak = self.memory.peak.max(self.memory.used);
+        self.variables = globals;
+        self.functions.clear();
+        self.interpret(&program).map_err(|e| e.to_string())
+    }
+}
+
+fn to_json(value: &Value) -> Json {
+    match value {
+        Value::Number(n) if !n.is_finite() => Json::object([(NUMBER, n.to_string().into())]),
+        Value::Number(n) => Json::Number(*n),
+        Value::Str(s) => Json::Str(s.clone()),
+        Value::List(items) => Json::Array(items.iter().map(to_json).collect()),
+        Value::Dict(entries) => Json::Object(entries.iter().map(|(key, value)| (key.clone(), to_json(value))).collect()),
+    }
+}
+
+fn from_json(json: &Json) -> Result<Value, String> {
+    match json {
+        Json::Number(n) => Ok(Value::Number(*n)),
+        Json::Str(s) => Ok(Value::Str(s.clone())),
+        Json::Array(items) => items.iter().map(from_json).collect::<Result<_, _>>().map(Value::List),
+        Json::Object(members) => match members.as_slice() {
+            [(key, Json::Str(n))] if key == NUMBER => n.parse().map(Value::Number).map_err(|_| format!("invalid number {}", n)),
+            _ => members
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), from_json(value)?)))
+                .collect::<Result<BTreeMap<_, _>, String>>()
+                .map(Value::Dict),
+        },
+        Json::Null | Json::Bool(_) => Err(format!("{} is not a value", json)),
+    }
+}
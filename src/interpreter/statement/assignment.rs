@@ -1,76 +1,64 @@
-use crate::token::Token;
+use crate::ast::{BinOp, Expr, ExprKind};
+use crate::value::{self, RuntimeError, Value};
 use super::super::Interpreter;
 
-pub fn eval_expression(interp: &Interpreter, tokens: &[Token]) -> String {
-    // If the whole expression is a single string literal, return it
-    if tokens.len() == 1 {
-        if let Token::StringLiteral(s) = &tokens[0] {
-            return s.clone();
+impl Interpreter {
+    /// `name = value;`
+    pub fn interpret_assignment(&mut self, name: &str, value: &Expr, line: usize) -> Result<(), RuntimeError> {
+        let value = self.eval_expression(value)?;
+        self.set_variable(name, value, line)
+    }
+
+    /// `name++;` or `name--;`
+    pub fn interpret_increment(&mut self, name: &str, amount: f64, line: usize) -> Result<(), RuntimeError> {
+        let current = self.lookup(name, line)?;
+        let updated = value::binary(BinOp::Add, &current, &Value::Number(amount))
+            .map_err(|message| RuntimeError { message, line: Some(line) })?;
+        self.set_variable(name, updated, line)
+    }
+
+    /// Stores a variable of the running frame if the memory limit allows,
+    /// telling an attached debugger.
+    fn set_variable(&mut self, name: &str, value: Value, line: usize) -> Result<(), RuntimeError> {
+        let (old, new) = (self.variables.get(name).map_or(0, Value::heap_size), value.heap_size());
+        self.replace_memory(old, new, line)?;
+        if let Some(debugger) = self.debugger.as_mut()
+            && let Err(error) = debugger.write(name, self.variables.get(name), &value, line)
+        {
+            self.memory.used = self.memory.used - new + old;
+            return Err(error);
         }
+        self.variables.insert(name.to_string(), value);
+        Ok(())
     }
-    let mut acc = None;
-    let mut op: Option<char> = None;
-    let mut idx = 0;
-    while idx < tokens.len() {
-        match &tokens[idx] {
-            Token::Whitespace => { idx += 1; },
-            Token::Identifier(v) => {
-                let val = *interp.variables.get(v).unwrap_or(&0.0);
-                acc = Some(match (acc, op) {
-                    (None, _) => val,
-                    (Some(lhs), Some('+')) => lhs + val,
-                    (Some(lhs), Some('-')) => lhs - val,
-                    (Some(lhs), Some('*')) => lhs * val,
-                    (Some(lhs), Some('/')) => lhs / val,
-                    _ => val
-                });
-                op = None;
-                idx += 1;
-            },
-            Token::Number(n) => {
-                let val = n.parse::<f64>().unwrap_or(0.0);
-                acc = Some(match (acc, op) {
-                    (None, _) => val,
-                    (Some(lhs), Some('+')) => lhs + val,
-                    (Some(lhs), Some('-')) => lhs - val,
-                    (Some(lhs), Some('*')) => lhs * val,
-                    (Some(lhs), Some('/')) => lhs / val,
-                    _ => val
-                });
-                op = None;
-                idx += 1;
-            },
-            Token::Symbol(s) if *s == '+' || *s == '-' || *s == '*' || *s == '/' => {
-                op = Some(*s);
-                idx += 1;
-            },
-            Token::Symbol(';') => {
-                // If there's a pending operator and a previous operand, process them
-                if let Some(opc) = op {
-                    if idx > 0 {
-                        let prev = &tokens[idx-1];
-                        let val = match prev {
-                            Token::Number(n) => n.parse::<f64>().unwrap_or(0.0),
-                            Token::Identifier(v) => *interp.variables.get(v).unwrap_or(&0.0),
-                            _ => 0.0
-                        };
-                        acc = Some(match (acc, Some(opc)) {
-                            (Some(lhs), Some('+')) => lhs + val,
-                            (Some(lhs), Some('-')) => lhs - val,
-                            (Some(lhs), Some('*')) => lhs * val,
-                            (Some(lhs), Some('/')) => lhs / val,
-                            _ => val
-                        });
-                    }
-                }
-                break;
-            },
-            _ => { break; }
+
+    /// Evaluates an expression to a value.
+    pub fn eval_expression(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        let fail = |message: String| RuntimeError { message, line: Some(expr.span.line) };
+        match &expr.kind {
+            ExprKind::Number(n) => Ok(Value::Number(*n)),
+            ExprKind::Str(s) => Ok(Value::Str(s.clone())),
+            ExprKind::Var(name) => self.lookup(name, expr.span.line),
+            ExprKind::Unary { expr: operand, .. } => {
+                let operand = self.eval_expression(operand)?;
+                value::negate(&operand).map_err(fail)
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                let lhs = self.eval_expression(lhs)?;
+                let rhs = self.eval_expression(rhs)?;
+                value::binary(*op, &lhs, &rhs).map_err(fail)
+            }
+            ExprKind::Call { name, args } => {
+                let args = args.iter().map(|arg| self.eval_expression(arg)).collect::<Result<Vec<_>, _>>()?;
+                self.call_function(name, args, expr.span.line)
+            }
         }
     }
-    if let Some(val) = acc {
-        val.to_string()
-    } else {
-        "0".to_string()
+
+    fn lookup(&self, name: &str, line: usize) -> Result<Value, RuntimeError> {
+        self.variables.get(name).cloned().ok_or_else(|| RuntimeError {
+            message: format!("NameError: name '{}' is not defined", name),
+            line: Some(line),
+        })
     }
 }
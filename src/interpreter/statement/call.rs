@@ -0,0 +1,85 @@
+use crate::value::{RuntimeError, Value};
+use super::super::context::{Frame, MAX_CALL_DEPTH};
+use super::super::control::loops::Flow;
+use super::super::Interpreter;
+
+impl Interpreter {
+    /// Calls a user-defined function. The body runs with only its parameters
+    /// in scope; a function that finishes without `return` returns `0`. A
+    /// `return` of a call to the function itself reuses the call's frame, so
+    /// tail recursion does not count towards the recursion limit.
+    pub fn call_function(&mut self, name: &str, mut args: Vec<Value>, line: usize) -> Result<Value, RuntimeError> {
+        let fail = |message: String| RuntimeError { message, line: Some(line) };
+        if !self.functions.contains_key(name)
+            && let Some(result) = self.call_builtin(name, &args, line)
+        {
+            let value = result?;
+            self.check_memory(value.heap_size(), line)?;
+            return Ok(value);
+        }
+        if !self.functions.contains_key(name)
+            && let Some(native) = self.natives.get(name)
+        {
+            if args.len() != native.arity {
+                return Err(fail(format!("TypeError: {}() takes {} arguments but {} were given", name, native.arity, args.len())));
+            }
+            let value = (native.function)(&args).map_err(fail)?;
+            self.check_memory(value.heap_size(), line)?;
+            return Ok(value);
+        }
+        let function = self
+            .functions
+            .get(name)
+            .cloned()
+            .ok_or_else(|| fail(format!("NameError: name '{}' is not defined", name)))?;
+        let check_arity = |given: usize| {
+            if given == function.params.len() {
+                return Ok(());
+            }
+            Err(fail(format!(
+                "TypeError: {}() takes {} arguments but {} were given",
+                name,
+                function.params.len(),
+                given
+            )))
+        };
+        check_arity(args.len())?;
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(fail("RecursionError: maximum recursion depth exceeded".to_string()));
+        }
+        let variables = std::mem::take(&mut self.variables);
+        let caller = self.current.replace(name.to_string());
+        self.frames.push(Frame { function: caller, line, variables });
+        self.depth += 1;
+        let result = loop {
+            if let Err(error) = self.replace_memory(0, args.iter().map(Value::heap_size).sum(), line) {
+                break Err(error);
+            }
+            self.variables = function.params.iter().cloned().zip(args).collect();
+            if let Some(debugger) = self.debugger.as_mut() {
+                debugger.enter(name);
+            }
+            let flow = self.interpret_block(&function.body);
+            if let Some(debugger) = self.debugger.as_mut() {
+                debugger.leave(name);
+            }
+            let variables = std::mem::take(&mut self.variables);
+            self.release_memory(variables.values());
+            match flow {
+                Ok(Flow::TailCall(next)) => match check_arity(next.len()) {
+                    Ok(()) => args = next,
+                    Err(error) => break Err(error),
+                },
+                other => break other,
+            }
+        };
+        self.depth -= 1;
+        let caller = self.frames.pop().expect("every call pushes its caller's frame");
+        self.current = caller.function;
+        self.variables = caller.variables;
+        match result? {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal | Flow::TailCall(_) => Ok(Value::Number(0.0)),
+        }
+    }
+}
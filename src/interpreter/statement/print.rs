@@ -0,0 +1,22 @@
+use std::io::Write;
+use crate::ast::Expr;
+use crate::value::RuntimeError;
+use super::super::Interpreter;
+
+impl Interpreter {
+    /// `print(a, b, ...);` writes the values separated by spaces to the
+    /// interpreter's output.
+    pub fn interpret_print(&mut self, args: &[Expr]) -> Result<(), RuntimeError> {
+        let mut output = Vec::with_capacity(args.len());
+        for arg in args {
+            output.push(self.eval_expression(arg)?.to_string());
+        }
+        let line = output.join(" ");
+        if let Some(debugger) = self.debugger.as_mut()
+            && debugger.print(&line)
+        {
+            return Ok(());
+        }
+        writeln!(self.output, "{}", line).map_err(|e| RuntimeError { message: format!("OSError: {}", e), line: None })
+    }
+}
@@ -0,0 +1,190 @@
+//! Three-address intermediate representation shared by the optimizer and backends.
+use std::fmt;
+use crate::ast::{BinOp, UnaryOp};
+
+/// Name of the builtin called by `print` statements.
+pub const PRINT: &str = "print";
+
+/// A lowered program. The first function holds the top-level code.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IrProgram {
+    pub functions: Vec<IrFunction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IrFunction {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Instr>,
+}
+
+/// One IR instruction and the source line it was lowered from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instr {
+    pub kind: InstrKind,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstrKind {
+    /// `dest = src`
+    Copy { dest: Var, src: Operand },
+    /// `dest = op src`
+    Unary { dest: Var, op: UnaryOp, src: Operand },
+    /// `dest = lhs op rhs`
+    Binary { dest: Var, op: BinOp, lhs: Operand, rhs: Operand },
+    /// Jump target.
+    Label(Label),
+    /// `goto target`
+    Jump(Label),
+    /// `if_false cond goto target`
+    JumpIfFalse { cond: Operand, target: Label },
+    /// `dest = call func(args)`
+    Call { dest: Option<Var>, func: String, args: Vec<Operand> },
+    /// `return value`; a function returning nothing returns `0`.
+    Return(Option<Operand>),
+}
+
+impl InstrKind {
+    /// The variable this instruction writes, if any.
+    pub fn dest(&self) -> Option<&Var> {
+        match self {
+            InstrKind::Copy { dest, .. } | InstrKind::Unary { dest, .. } | InstrKind::Binary { dest, .. } => Some(dest),
+            InstrKind::Call { dest, .. } => dest.as_ref(),
+            InstrKind::Label(_) | InstrKind::Jump(_) | InstrKind::JumpIfFalse { .. } | InstrKind::Return(_) => None,
+        }
+    }
+
+    pub fn dest_mut(&mut self) -> Option<&mut Var> {
+        match self {
+            InstrKind::Copy { dest, .. } | InstrKind::Unary { dest, .. } | InstrKind::Binary { dest, .. } => Some(dest),
+            InstrKind::Call { dest, .. } => dest.as_mut(),
+            InstrKind::Label(_) | InstrKind::Jump(_) | InstrKind::JumpIfFalse { .. } | InstrKind::Return(_) => None,
+        }
+    }
+
+    /// The operands this instruction reads, in evaluation order.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            InstrKind::Copy { src, .. } | InstrKind::Unary { src, .. } => vec![src],
+            InstrKind::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            InstrKind::JumpIfFalse { cond, .. } => vec![cond],
+            InstrKind::Call { args, .. } => args.iter().collect(),
+            InstrKind::Return(value) => value.iter().collect(),
+            InstrKind::Label(_) | InstrKind::Jump(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            InstrKind::Copy { src, .. } | InstrKind::Unary { src, .. } => vec![src],
+            InstrKind::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            InstrKind::JumpIfFalse { cond, .. } => vec![cond],
+            InstrKind::Call { args, .. } => args.iter_mut().collect(),
+            InstrKind::Return(value) => value.iter_mut().collect(),
+            InstrKind::Label(_) | InstrKind::Jump(_) => Vec::new(),
+        }
+    }
+}
+
+/// A storage location: a program variable or a compiler temporary.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Var {
+    Named(String),
+    Temp(u32),
+    /// A numbered definition of another variable, introduced by SSA renaming.
+    Version(Box<Var>, u32),
+}
+
+impl Var {
+    /// The variable this one is a version of, or itself if it is not versioned.
+    pub fn base(&self) -> &Var {
+        match self {
+            Var::Version(base, _) => base.base(),
+            _ => self,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Number(f64),
+    Str(String),
+    Var(Var),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Label(pub u32);
+
+impl fmt::Display for IrProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, function) in self.functions.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for IrFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "function {}({}):", self.name, self.params.join(", "))?;
+        for instr in &self.body {
+            match instr.kind {
+                InstrKind::Label(_) => writeln!(f, "{}", instr.kind)?,
+                _ => writeln!(f, "    {}", instr.kind)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for InstrKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstrKind::Copy { dest, src } => write!(f, "{} = {}", dest, src),
+            InstrKind::Unary { dest, op: UnaryOp::Neg, src } => write!(f, "{} = -{}", dest, src),
+            InstrKind::Binary { dest, op, lhs, rhs } => write!(f, "{} = {} {} {}", dest, lhs, op.symbol(), rhs),
+            InstrKind::Label(label) => write!(f, "{}:", label),
+            InstrKind::Jump(target) => write!(f, "goto {}", target),
+            InstrKind::JumpIfFalse { cond, target } => write!(f, "if_false {} goto {}", cond, target),
+            InstrKind::Call { dest, func, args } => {
+                if let Some(dest) = dest {
+                    write!(f, "{} = ", dest)?;
+                }
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "call {}({})", func, args.join(", "))
+            }
+            InstrKind::Return(None) => write!(f, "return"),
+            InstrKind::Return(Some(value)) => write!(f, "return {}", value),
+        }
+    }
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Var::Named(name) => write!(f, "{}", name),
+            Var::Temp(n) => write!(f, "t{}", n),
+            Var::Version(base, n) => write!(f, "{}.{}", base, n),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Number(n) => write!(f, "{}", n),
+            Operand::Str(s) => write!(f, "{:?}", s),
+            Operand::Var(var) => write!(f, "{}", var),
+        }
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
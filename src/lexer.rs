@@ -1,5 +1,5 @@
 use std::collections::HashSet;
-use crate::token::Token;
+use crate::token::{Span, Token};
 
 /// The Lexer is responsible for converting source code into a stream of tokens.
 pub struct Lexer {
@@ -10,7 +10,7 @@ impl Lexer {
     /// Creates a new Lexer with a set of recognized keywords.
     pub fn new() -> Self {
         let keywords = [
-            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield", "print"
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "let", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield", "print"
         ]
         .iter()
         .copied()
@@ -18,28 +18,36 @@ impl Lexer {
         Self { keywords }
     }
 
-    /// Tokenizes the input string into a vector of tokens.
-    pub fn tokenize(&self, input: &str) -> Vec<Token> {
+    /// Tokenizes the input string, pairing every token with its source span.
+    pub fn tokenize_with_spans(&self, input: &str) -> Vec<(Token, Span)> {
         let mut tokens = Vec::new();
-        let mut chars = input.chars().peekable();
-        while let Some(&ch) = chars.peek() {
-            if ch.is_whitespace() {
+        let mut chars = input.char_indices().peekable();
+        let mut line = 1;
+        let mut line_start = 0;
+        while let Some(&(start, ch)) = chars.peek() {
+            let token = if ch.is_whitespace() {
                 chars.next();
-                tokens.push(Token::Whitespace);
+                Token::Whitespace
+            } else if input[start..].starts_with("//") {
+                let text: String = input[start + 2..].chars().take_while(|&c| c != '\n').collect();
+                for _ in 0..text.chars().count() + 2 {
+                    chars.next();
+                }
+                Token::Comment(text)
             } else if ch == '"' {
                 chars.next();
                 let mut literal = String::new();
-                while let Some(&c) = chars.peek() {
+                while let Some(&(_, c)) = chars.peek() {
                     chars.next();
                     if c == '"' {
                         break;
                     }
                     literal.push(c);
                 }
-                tokens.push(Token::StringLiteral(literal));
+                Token::StringLiteral(literal)
             } else if ch.is_ascii_digit() {
                 let mut number = String::new();
-                while let Some(&c) = chars.peek() {
+                while let Some(&(_, c)) = chars.peek() {
                     if c.is_ascii_digit() || c == '.' {
                         number.push(c);
                         chars.next();
@@ -47,10 +55,10 @@ impl Lexer {
                         break;
                     }
                 }
-                tokens.push(Token::Number(number));
+                Token::Number(number)
             } else if ch.is_ascii_alphabetic() || ch == '_' {
                 let mut ident = String::new();
-                while let Some(&c) = chars.peek() {
+                while let Some(&(_, c)) = chars.peek() {
                     if c.is_ascii_alphanumeric() || c == '_' {
                         ident.push(c);
                         chars.next();
@@ -59,18 +67,25 @@ impl Lexer {
                     }
                 }
                 if self.keywords.contains(ident.as_str()) {
-                    tokens.push(Token::Keyword(ident));
+                    Token::Keyword(ident)
                 } else {
-                    tokens.push(Token::Identifier(ident));
+                    Token::Identifier(ident)
                 }
             } else if Self::is_symbol(ch) {
-                tokens.push(Token::Symbol(ch));
                 chars.next();
+                Token::Symbol(ch)
             } else {
-                let mut unknown = String::new();
-                unknown.push(ch);
                 chars.next();
-                tokens.push(Token::Unknown(unknown));
+                Token::Unknown(ch.to_string())
+            };
+            let end = chars.peek().map_or(input.len(), |&(idx, _)| idx);
+            tokens.push((token, Span { start, end, line, col: start - line_start + 1 }));
+            // String literals may span lines, so count every newline the token consumed
+            for (offset, c) in input[start..end].char_indices() {
+                if c == '\n' {
+                    line += 1;
+                    line_start = start + offset + 1;
+                }
             }
         }
         tokens
@@ -78,6 +93,12 @@ impl Lexer {
 
     /// Returns true if the given character is a recognized symbol.
     fn is_symbol(ch: char) -> bool {
-        matches!(ch, ',' | ';' | '(' | ')' | '{' | '}' | '[' | ']' | '+' | '-' | '*' | '/' | '=' | '<' | '>' | '!' | '&' | '|' | '^' | '%')
+        matches!(ch, ',' | ';' | '(' | ')' | '{' | '}' | '[' | ']' | '+' | '-' | '*' | '/' | '=' | '<' | '>' | '!' | '&' | '|' | '^' | '%' | ':')
+    }
+}
+
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,41 @@
+//! Fork: a small scripting language with an interpreter, a bytecode
+//! compiler and virtual machine, and the tools around them.
+//!
+//! [`Engine`] runs Fork source from Rust; the `fork` binary is a command
+//! line over the same modules.
+pub mod token;
+pub mod lexer;
+pub mod ast;
+pub mod parser;
+pub mod semantic;
+pub mod config;
+pub mod lint;
+pub mod formatter;
+pub mod json;
+pub mod lsp;
+pub mod debugger;
+pub mod dap;
+pub mod trace;
+pub mod profiler;
+pub mod coverage;
+pub mod testing;
+pub mod ir;
+pub mod icg;
+pub mod cfg;
+pub mod ssa;
+pub mod optimizer;
+pub mod loops;
+pub mod calls;
+pub mod bytecode;
+pub mod codegen;
+pub mod disasm;
+pub mod engine;
+pub mod native;
+pub mod repl;
+pub mod value;
+pub mod vm;
+pub mod interpreter;
+
+pub use engine::{Engine, ForkError, Script};
+pub use interpreter::Interpreter;
+pub use value::{RuntimeError, Value};
@@ -0,0 +1,556 @@
+//! `fork lint`: warnings about code that is legal but probably wrong, built on
+//! the scopes found by the semantic pass.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use crate::ast::{BinOp, Expr, ExprKind, Program, Stmt, StmtKind};
+use crate::config::{Config, ConfigError, Value as Setting};
+use crate::semantic::{Access, Analysis, SymbolKind};
+use crate::token::{Span, Token};
+use crate::value::{self, Value};
+
+/// Comment that silences lint rules: `// lint-ignore` for every rule, or
+/// `// lint-ignore: rule-id, rule-id` for some. After code it applies to its
+/// own line; on a line by itself, to the next line.
+const SUPPRESS: &str = "lint-ignore";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// A variable that is assigned but never read.
+    UnusedVariable,
+    /// An assignment whose value is overwritten or discarded before any read.
+    UnusedAssignment,
+    /// An `if`, `while` or `for` condition made of constants only.
+    ConstantCondition,
+    /// A `while` or `for` loop whose condition can never become false.
+    InfiniteLoop,
+    /// A function variable named like a top-level variable or a function.
+    Shadowing,
+    /// Statements after a `return`.
+    UnreachableCode,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedVariable,
+        Rule::UnusedAssignment,
+        Rule::ConstantCondition,
+        Rule::InfiniteLoop,
+        Rule::Shadowing,
+        Rule::UnreachableCode,
+    ];
+
+    /// The name used in `fork.toml`, suppression comments and output.
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedAssignment => "unused-assignment",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::InfiniteLoop => "infinite-loop",
+            Rule::Shadowing => "shadowing",
+            Rule::UnreachableCode => "unreachable-code",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.id() == id)
+    }
+
+    /// Severity when `fork.toml` does not configure the rule.
+    pub fn default_severity(self) -> Severity {
+        match self {
+            Rule::InfiniteLoop => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+/// One finding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}[{}]: {}", self.span.line, self.span.col, self.severity.name(), self.rule.id(), self.message)
+    }
+}
+
+/// Runs the enabled rules at their configured severities.
+pub struct Linter {
+    /// Severity of each rule; rules turned off are absent.
+    severities: HashMap<Rule, Severity>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Linter { severities: Rule::ALL.into_iter().map(|rule| (rule, rule.default_severity())).collect() }
+    }
+
+    /// Applies the `[lint]` section of `fork.toml`, where each entry sets a
+    /// rule to `"off"`, `"warning"` or `"error"`.
+    pub fn configure(mut self, config: &Config) -> Result<Self, ConfigError> {
+        for entry in config.section("lint") {
+            let rule = Rule::from_id(&entry.key).ok_or_else(|| config.error(format!("unknown lint rule '{}'", entry.key), entry.line))?;
+            match &entry.value {
+                Setting::Str(level) if level == "off" => {
+                    self.severities.remove(&rule);
+                }
+                Setting::Str(level) if level == "warning" => {
+                    self.severities.insert(rule, Severity::Warning);
+                }
+                Setting::Str(level) if level == "error" => {
+                    self.severities.insert(rule, Severity::Error);
+                }
+                value => {
+                    let message = format!("expected \"off\", \"warning\" or \"error\" for '{}', found {}", entry.key, value);
+                    return Err(config.error(message, entry.line));
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Lints a program that passed semantic analysis. `tokens` are the
+    /// program's tokens, where the suppression comments are found.
+    pub fn lint(&self, program: &Program, analysis: &Analysis, tokens: &[(Token, Span)]) -> Vec<Diagnostic> {
+        let mut findings = Findings::default();
+        unused_variables(analysis, &mut findings);
+        shadowing(analysis, &mut findings);
+
+        let mut bodies = vec![program.stmts.as_slice()];
+        bodies.extend(program.stmts.iter().filter_map(|stmt| match &stmt.kind {
+            StmtKind::Def { body, .. } => Some(body.as_slice()),
+            _ => None,
+        }));
+        for (scope, body) in bodies.into_iter().enumerate() {
+            let mut liveness = Liveness { scope, unused: &findings.unused, report: true, dead: Vec::new() };
+            liveness.block(body, HashSet::new());
+            for (name, span) in liveness.dead {
+                findings.push(Rule::UnusedAssignment, format!("value assigned to '{}' is never read", name), span);
+            }
+            check_statements(body, &mut findings);
+        }
+
+        let suppressed = suppressions(tokens);
+        let mut diagnostics: Vec<Diagnostic> = findings
+            .list
+            .into_iter()
+            .filter(|(rule, _, span)| {
+                !suppressed.get(&span.line).is_some_and(|rules| rules.as_ref().is_none_or(|rules| rules.contains(rule)))
+            })
+            .filter_map(|(rule, message, span)| {
+                let severity = *self.severities.get(&rule)?;
+                Some(Diagnostic { rule, severity, message, span })
+            })
+            .collect();
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        diagnostics
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Findings before severities and suppressions are applied.
+#[derive(Default)]
+struct Findings {
+    list: Vec<(Rule, String, Span)>,
+    /// Variables reported as unused, whose assignments are not reported again.
+    unused: HashSet<(usize, String)>,
+}
+
+impl Findings {
+    fn push(&mut self, rule: Rule, message: String, span: Span) {
+        self.list.push((rule, message, span));
+    }
+}
+
+/// Variables with no read anywhere in their scope.
+fn unused_variables(analysis: &Analysis, findings: &mut Findings) {
+    for (idx, scope) in analysis.scopes.iter().enumerate() {
+        for (slot, symbol) in scope.symbols.iter().enumerate() {
+            let read = analysis
+                .resolutions
+                .iter()
+                .any(|r| r.scope == idx && r.slot == slot && matches!(r.access, Access::Read | Access::ReadWrite));
+            if let (SymbolKind::Variable, Some(span), false) = (symbol.kind, symbol.declared, read) {
+                findings.push(Rule::UnusedVariable, format!("variable '{}' is assigned but never used", symbol.name), span);
+                findings.unused.insert((idx, symbol.name.clone()));
+            }
+        }
+    }
+}
+
+/// Function variables named like a top-level variable or a function. A
+/// function cannot see top-level variables, so the shared name is misleading.
+fn shadowing(analysis: &Analysis, findings: &mut Findings) {
+    let Some((main, functions)) = analysis.scopes.split_first() else {
+        return;
+    };
+    let function_names: HashSet<&str> = functions.iter().map(|scope| scope.name.as_str()).collect();
+    for symbol in &main.symbols {
+        if let (Some(span), true) = (symbol.declared, function_names.contains(symbol.name.as_str())) {
+            findings.push(Rule::Shadowing, format!("variable '{}' shadows function '{}'", symbol.name, symbol.name), span);
+        }
+    }
+    for scope in functions {
+        for symbol in &scope.symbols {
+            let Some(span) = symbol.declared else { continue };
+            let what = if symbol.kind == SymbolKind::Param { "parameter" } else { "variable" };
+            if function_names.contains(symbol.name.as_str()) {
+                findings.push(Rule::Shadowing, format!("{} '{}' shadows function '{}'", what, symbol.name, symbol.name), span);
+            } else if main.symbols.iter().any(|global| global.name == symbol.name) {
+                let message = format!("{} '{}' in {}() shadows top-level variable '{}'", what, symbol.name, scope.name, symbol.name);
+                findings.push(Rule::Shadowing, message, span);
+            }
+        }
+    }
+}
+
+/// Backward liveness over the syntax tree, finding assignments whose value
+/// no later statement reads.
+struct Liveness<'a> {
+    /// Index of the scope being walked, as in `Analysis::scopes`.
+    scope: usize,
+    unused: &'a HashSet<(usize, String)>,
+    /// False while a loop body is walked only to find what is live around it.
+    report: bool,
+    dead: Vec<(String, Span)>,
+}
+
+impl Liveness<'_> {
+    /// Returns the variables live before `stmts`, given those live after.
+    fn block(&mut self, stmts: &[Stmt], mut live: HashSet<String>) -> HashSet<String> {
+        for stmt in stmts.iter().rev() {
+            live = self.stmt(stmt, live);
+        }
+        live
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, mut live: HashSet<String>) -> HashSet<String> {
+        match &stmt.kind {
+            StmtKind::Assign { name, value } | StmtKind::Let { name, value, .. } => {
+                if !live.remove(name) {
+                    self.dead_store(name, stmt.span);
+                }
+                reads(value, &mut live);
+            }
+            StmtKind::Increment { name, .. } => {
+                if !live.contains(name) {
+                    self.dead_store(name, stmt.span);
+                }
+                live.insert(name.clone());
+            }
+            StmtKind::Print(args) => args.iter().for_each(|arg| reads(arg, &mut live)),
+            StmtKind::Expr(expr) => reads(expr, &mut live),
+            StmtKind::If { cond, then_block, else_block } => {
+                let then_live = self.block(then_block, live.clone());
+                let mut else_live = match else_block {
+                    Some(else_block) => self.block(else_block, live),
+                    None => live,
+                };
+                else_live.extend(then_live);
+                live = else_live;
+                reads(cond, &mut live);
+            }
+            StmtKind::While { cond, body } => live = self.repeat(Some(cond), body, None, live),
+            StmtKind::For { init, cond, update, body } => {
+                live = self.repeat(cond.as_ref(), body, update.as_deref(), live);
+                if let Some(init) = init {
+                    live = self.stmt(init, live);
+                }
+            }
+            StmtKind::Return(value) => {
+                live.clear();
+                if let Some(value) = value {
+                    reads(value, &mut live);
+                }
+            }
+            StmtKind::Def { .. } => {}
+        }
+        live
+    }
+
+    /// Variables live at the top of a loop: read by the condition, live after
+    /// the loop, or live at the start of the body. The body is walked until
+    /// that set stops growing, then once more to report.
+    fn repeat(&mut self, cond: Option<&Expr>, body: &[Stmt], update: Option<&Stmt>, after: HashSet<String>) -> HashSet<String> {
+        let mut head = after;
+        if let Some(cond) = cond {
+            reads(cond, &mut head);
+        }
+        let report = std::mem::replace(&mut self.report, false);
+        loop {
+            let start = self.iteration(body, update, head.clone());
+            let before = head.len();
+            head.extend(start);
+            if head.len() == before {
+                break;
+            }
+        }
+        self.report = report;
+        self.iteration(body, update, head.clone());
+        head
+    }
+
+    fn iteration(&mut self, body: &[Stmt], update: Option<&Stmt>, head: HashSet<String>) -> HashSet<String> {
+        let live = match update {
+            Some(update) => self.stmt(update, head),
+            None => head,
+        };
+        self.block(body, live)
+    }
+
+    fn dead_store(&mut self, name: &str, span: Span) {
+        // Unused variables are already reported as a whole
+        if self.report && !self.unused.contains(&(self.scope, name.to_string())) {
+            self.dead.push((name.to_string(), span));
+        }
+    }
+}
+
+/// Adds every variable `expr` reads to `live`.
+fn reads(expr: &Expr, live: &mut HashSet<String>) {
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Str(_) => {}
+        ExprKind::Var(name) => {
+            live.insert(name.clone());
+        }
+        ExprKind::Unary { expr, .. } => reads(expr, live),
+        ExprKind::Binary { lhs, rhs, .. } => {
+            reads(lhs, live);
+            reads(rhs, live);
+        }
+        ExprKind::Call { args, .. } => args.iter().for_each(|arg| reads(arg, live)),
+    }
+}
+
+/// Statement-level rules: constant conditions, infinite loops and unreachable code.
+fn check_statements(stmts: &[Stmt], findings: &mut Findings) {
+    let mut returned = false;
+    for stmt in stmts {
+        if returned {
+            findings.push(Rule::UnreachableCode, "unreachable code after return".to_string(), stmt.span);
+            break;
+        }
+        returned = always_returns(stmt);
+        match &stmt.kind {
+            StmtKind::If { cond, then_block, else_block } => {
+                constant_condition(cond, false, findings);
+                check_statements(then_block, findings);
+                if let Some(else_block) = else_block {
+                    check_statements(else_block, findings);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                constant_condition(cond, true, findings);
+                infinite_loop(stmt, cond, body, None, findings);
+                check_statements(body, findings);
+            }
+            StmtKind::For { cond, update, body, .. } => {
+                if let Some(cond) = cond {
+                    constant_condition(cond, true, findings);
+                    infinite_loop(stmt, cond, body, update.as_deref(), findings);
+                }
+                check_statements(body, findings);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// True if running `stmt` always ends in a `return`.
+fn always_returns(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::If { then_block, else_block: Some(else_block), .. } => {
+            then_block.iter().any(always_returns) && else_block.iter().any(always_returns)
+        }
+        _ => false,
+    }
+}
+
+/// Reports a condition that does not depend on any variable. A loop on a
+/// plain nonzero number, such as `while (1)`, is taken as deliberate.
+fn constant_condition(cond: &Expr, is_loop: bool, findings: &mut Findings) {
+    let Some(value) = constant(cond) else {
+        return;
+    };
+    if is_loop && matches!(cond.kind, ExprKind::Number(n) if n != 0.0) {
+        return;
+    }
+    let outcome = if value.is_truthy() { "true" } else { "false" };
+    findings.push(Rule::ConstantCondition, format!("condition is always {}", outcome), cond.span);
+}
+
+/// The value of an expression made of literals only.
+fn constant(expr: &Expr) -> Option<Value> {
+    match &expr.kind {
+        ExprKind::Number(n) => Some(Value::Number(*n)),
+        ExprKind::Str(s) => Some(Value::Str(s.clone())),
+        ExprKind::Unary { expr, .. } => value::negate(&constant(expr)?).ok(),
+        ExprKind::Binary { op, lhs, rhs } => value::binary(*op, &constant(lhs)?, &constant(rhs)?).ok(),
+        ExprKind::Var(_) | ExprKind::Call { .. } => None,
+    }
+}
+
+/// Reports a loop without a `return` whose condition cannot change from true
+/// to false: no variable it reads is assigned in the loop, or it compares a
+/// variable with a value the loop leaves alone while the variable only moves
+/// away from it, like `while (x > 0) { x = x + 1; }`.
+fn infinite_loop(stmt: &Stmt, cond: &Expr, body: &[Stmt], update: Option<&Stmt>, findings: &mut Findings) {
+    let mut cond_vars = HashSet::new();
+    reads(cond, &mut cond_vars);
+    if cond_vars.is_empty() || body.iter().any(contains_return) {
+        return;
+    }
+    let mut steps: HashMap<String, Vec<Option<f64>>> = HashMap::new();
+    collect_steps(body, &mut steps);
+    if let Some(update) = update {
+        collect_steps(std::slice::from_ref(update), &mut steps);
+    }
+    if !cond_vars.iter().any(|name| steps.contains_key(name)) {
+        let mut names: Vec<&String> = cond_vars.iter().collect();
+        names.sort();
+        let names: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+        let message = format!("loop condition never changes: {} not modified in the loop", names.join(", "));
+        findings.push(Rule::InfiniteLoop, message, stmt.span);
+        return;
+    }
+    let ExprKind::Binary { op, lhs, rhs } = &cond.kind else {
+        return;
+    };
+    // Normalize to `var op bound`
+    let (name, op, bound) = match (&lhs.kind, &rhs.kind) {
+        (ExprKind::Var(name), _) => (name, *op, rhs),
+        (_, ExprKind::Var(name)) => {
+            let flipped = match op {
+                BinOp::Lt => BinOp::Gt,
+                BinOp::Gt => BinOp::Lt,
+                BinOp::Le => BinOp::Ge,
+                BinOp::Ge => BinOp::Le,
+                _ => return,
+            };
+            (name, flipped, lhs)
+        }
+        _ => return,
+    };
+    let mut bound_vars = HashSet::new();
+    reads(bound, &mut bound_vars);
+    if bound_vars.contains(name) || bound_vars.iter().any(|var| steps.contains_key(var)) {
+        return;
+    }
+    let Some(var_steps) = steps.get(name) else {
+        return;
+    };
+    let Some(var_steps) = var_steps.iter().copied().collect::<Option<Vec<f64>>>() else {
+        return;
+    };
+    let direction = match op {
+        BinOp::Gt | BinOp::Ge if var_steps.iter().all(|&step| step >= 0.0) => "increases",
+        BinOp::Lt | BinOp::Le if var_steps.iter().all(|&step| step <= 0.0) => "decreases",
+        _ => return,
+    };
+    findings.push(Rule::InfiniteLoop, format!("loop condition never becomes false: '{}' only {}", name, direction), stmt.span);
+}
+
+fn contains_return(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::If { then_block, else_block, .. } => {
+            then_block.iter().any(contains_return) || else_block.iter().flatten().any(contains_return)
+        }
+        StmtKind::While { body, .. } | StmtKind::For { body, .. } => body.iter().any(contains_return),
+        _ => false,
+    }
+}
+
+/// Records every assignment in `stmts` by variable: the constant it adds, as
+/// in `x++` or `x = x - 2`, or `None` for any other assignment.
+fn collect_steps(stmts: &[Stmt], steps: &mut HashMap<String, Vec<Option<f64>>>) {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Assign { name, value } | StmtKind::Let { name, value, .. } => {
+                steps.entry(name.clone()).or_default().push(step(name, value));
+            }
+            StmtKind::Increment { name, amount } => steps.entry(name.clone()).or_default().push(Some(*amount)),
+            StmtKind::If { then_block, else_block, .. } => {
+                collect_steps(then_block, steps);
+                if let Some(else_block) = else_block {
+                    collect_steps(else_block, steps);
+                }
+            }
+            StmtKind::While { body, .. } => collect_steps(body, steps),
+            StmtKind::For { init, update, body, .. } => {
+                for part in [init, update].into_iter().flatten() {
+                    collect_steps(std::slice::from_ref(&**part), steps);
+                }
+                collect_steps(body, steps);
+            }
+            StmtKind::Print(_) | StmtKind::Return(_) | StmtKind::Expr(_) | StmtKind::Def { .. } => {}
+        }
+    }
+}
+
+/// The constant `value` adds to `name`, if it is `name + c`, `c + name` or `name - c`.
+fn step(name: &str, value: &Expr) -> Option<f64> {
+    let ExprKind::Binary { op, lhs, rhs } = &value.kind else {
+        return None;
+    };
+    let is_var = |expr: &Expr| matches!(&expr.kind, ExprKind::Var(var) if var == name);
+    let number = |expr: &Expr| match constant(expr) {
+        Some(Value::Number(n)) => Some(n),
+        _ => None,
+    };
+    match op {
+        BinOp::Add if is_var(lhs) => number(rhs),
+        BinOp::Add if is_var(rhs) => number(lhs),
+        BinOp::Sub if is_var(lhs) => number(rhs).map(|n| -n),
+        _ => None,
+    }
+}
+
+/// Lines with suppressed rules; `None` suppresses every rule.
+fn suppressions(tokens: &[(Token, Span)]) -> HashMap<usize, Option<Vec<Rule>>> {
+    let mut suppressed = HashMap::new();
+    let mut code_lines = HashSet::new();
+    for (token, span) in tokens {
+        match token {
+            Token::Whitespace => {}
+            Token::Comment(text) => {
+                let Some(rest) = text.trim().strip_prefix(SUPPRESS) else {
+                    continue;
+                };
+                let rules = rest.trim().strip_prefix(':').map(|ids| ids.split(',').filter_map(|id| Rule::from_id(id.trim())).collect());
+                let line = if code_lines.contains(&span.line) { span.line } else { span.line + 1 };
+                suppressed.insert(line, rules);
+            }
+            _ => {
+                code_lines.insert(span.line);
+            }
+        }
+    }
+    suppressed
+}
@@ -0,0 +1,509 @@
+//! Loop optimizations over functions in SSA form: invariant code motion,
+//! induction variable strength reduction and full unrolling.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::ast::BinOp;
+use crate::cfg::{Block, BlockId, Cfg, Dominators, Loop, Phi, Terminator};
+use crate::ir::{Instr, InstrKind, Operand, Var};
+use crate::optimizer::fold_binary;
+use crate::ssa;
+
+/// Loops that run more times than this are never unrolled.
+const MAX_UNROLL_TRIPS: usize = 16;
+/// Upper bound on the phis and instructions an unrolled loop may expand to.
+const MAX_UNROLL_SIZE: usize = 128;
+
+/// Moves arithmetic whose operands do not change inside a loop into the
+/// loop's preheader, so it runs once instead of on every iteration.
+pub fn hoist_invariants(cfg: &mut Cfg) -> bool {
+    let numeric = numeric_vars(cfg);
+    for_each_loop(cfg, |cfg, lp, dominators| {
+        let defined = loop_defs(cfg, lp);
+        let order: Vec<BlockId> = cfg.reverse_postorder().into_iter().filter(|b| lp.blocks.contains(b)).collect();
+        let mut invariant = HashSet::new();
+        let mut hoisted = Vec::new();
+        loop {
+            let before = hoisted.len();
+            for &block in &order {
+                for (idx, instr) in cfg.blocks[block].instrs.iter().enumerate() {
+                    let (InstrKind::Binary { dest, .. } | InstrKind::Unary { dest, .. }) = &instr.kind else {
+                        continue;
+                    };
+                    let movable = instr.kind.operands().iter().all(|operand| match operand {
+                        Operand::Number(_) => true,
+                        Operand::Str(_) => false,
+                        Operand::Var(var) => {
+                            numeric.contains(var) && (!defined.contains(var) || invariant.contains(var))
+                        }
+                    });
+                    if movable && invariant.insert(dest.clone()) {
+                        hoisted.push((block, idx));
+                    }
+                }
+            }
+            if hoisted.len() == before {
+                break;
+            }
+        }
+        if hoisted.is_empty() {
+            return false;
+        }
+        let Some(preheader) = preheader(cfg, lp, dominators) else {
+            return false;
+        };
+        let moved: Vec<Instr> = hoisted.iter().map(|&(block, idx)| cfg.blocks[block].instrs[idx].clone()).collect();
+        for &block in &lp.blocks {
+            let mut idx = 0;
+            cfg.blocks[block].instrs.retain(|_| {
+                idx += 1;
+                !hoisted.contains(&(block, idx - 1))
+            });
+        }
+        cfg.blocks[preheader].instrs.extend(moved);
+        true
+    })
+}
+
+/// Replaces `i * k`, where `i` steps by a constant every iteration, with an
+/// accumulator that starts at `init * k` and steps by `step * k`. Only loops
+/// with integer start, step and factor are rewritten, so the sums stay exact.
+pub fn reduce_strength(cfg: &mut Cfg) -> bool {
+    let mut namer = Namer::new(cfg);
+    for_each_loop(cfg, |cfg, lp, _| {
+        let ivs = induction_vars(cfg, lp);
+        let mut candidates = Vec::new();
+        for &block in &lp.blocks {
+            for (idx, instr) in cfg.blocks[block].instrs.iter().enumerate() {
+                let factor = match &instr.kind {
+                    InstrKind::Binary { op: BinOp::Mul, lhs: Operand::Var(var), rhs: Operand::Number(k), .. }
+                    | InstrKind::Binary { op: BinOp::Mul, lhs: Operand::Number(k), rhs: Operand::Var(var), .. } => {
+                        ivs.get(var).filter(|_| k.fract() == 0.0).map(|iv| (var.clone(), iv, *k))
+                    }
+                    _ => None,
+                };
+                if let Some(factor) = factor {
+                    candidates.push((block, idx, factor));
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return false;
+        }
+        let mut accumulators: HashMap<(Var, u64), Var> = HashMap::new();
+        let mut updates = Vec::new();
+        for (block, idx, (var, iv, k)) in candidates {
+            let acc = accumulators.entry((var.clone(), k.to_bits())).or_insert_with(|| {
+                let base = namer.temp();
+                let acc = namer.version(&base);
+                let next = namer.version(&base);
+                cfg.blocks[lp.header].phis.push(Phi {
+                    dest: acc.clone(),
+                    args: vec![(iv.entry, Operand::Number(iv.init * k)), (iv.latch, Operand::Var(next.clone()))],
+                });
+                let (update_block, update_idx) = iv.update;
+                let line = cfg.blocks[update_block].instrs[update_idx].line;
+                let kind = InstrKind::Binary {
+                    dest: next,
+                    op: iv.op,
+                    lhs: Operand::Var(acc.clone()),
+                    rhs: Operand::Number(iv.step * k),
+                };
+                updates.push((update_block, update_idx, Instr { kind, line }));
+                acc
+            });
+            let instr = &mut cfg.blocks[block].instrs[idx];
+            let dest = instr.kind.dest().unwrap().clone();
+            instr.kind = InstrKind::Copy { dest, src: Operand::Var(acc.clone()) };
+        }
+        // Insert from the back so earlier positions stay valid
+        updates.sort_by_key(|&(block, idx, _)| std::cmp::Reverse((block, idx)));
+        for (block, idx, instr) in updates {
+            cfg.blocks[block].instrs.insert(idx + 1, instr);
+        }
+        true
+    })
+}
+
+/// Fully unrolls innermost loops whose trip count is a small constant,
+/// replacing them with straight-line copies of the body.
+pub fn unroll_loops(cfg: &mut Cfg) -> bool {
+    let mut namer = Namer::new(cfg);
+    let changed = for_each_loop(cfg, |cfg, lp, dominators| {
+        let Some(unroll) = plan_unroll(cfg, lp, dominators) else {
+            return false;
+        };
+        unroll_loop(cfg, lp, &unroll, &mut namer);
+        true
+    });
+    if changed {
+        cfg.remove_unreachable();
+    }
+    changed
+}
+
+/// A basic induction variable: a header phi that starts at a constant and is
+/// stepped by a constant once per iteration.
+struct InductionVar {
+    init: f64,
+    step: f64,
+    /// `Add` or `Sub`, applied as `next = var op step`.
+    op: BinOp,
+    /// Position of the instruction computing the next value.
+    update: (BlockId, usize),
+    /// The block control enters the loop from.
+    entry: BlockId,
+    latch: BlockId,
+}
+
+/// Finds the basic induction variables of a loop with a single latch and a
+/// single entry edge.
+fn induction_vars(cfg: &Cfg, lp: &Loop) -> HashMap<Var, InductionVar> {
+    let mut ivs = HashMap::new();
+    let [latch] = lp.latches[..] else {
+        return ivs;
+    };
+    for phi in &cfg.blocks[lp.header].phis {
+        let [(a, ref a_value), (b, ref b_value)] = phi.args[..] else {
+            continue;
+        };
+        let ((entry, init), next) = match (a == latch, b == latch) {
+            (false, true) => ((a, a_value), b_value),
+            (true, false) => ((b, b_value), a_value),
+            _ => continue,
+        };
+        let (Operand::Number(init), Operand::Var(next)) = (init, next) else {
+            continue;
+        };
+        let this = Operand::Var(phi.dest.clone());
+        let update = lp.blocks.iter().find_map(|&block| {
+            cfg.blocks[block].instrs.iter().enumerate().find_map(|(idx, instr)| match &instr.kind {
+                InstrKind::Binary { dest, op: op @ (BinOp::Add | BinOp::Sub), lhs, rhs: Operand::Number(step) }
+                    if dest == next && *lhs == this =>
+                {
+                    Some((*op, *step, (block, idx)))
+                }
+                InstrKind::Binary { dest, op: BinOp::Add, lhs: Operand::Number(step), rhs } if dest == next && *rhs == this => {
+                    Some((BinOp::Add, *step, (block, idx)))
+                }
+                _ => None,
+            })
+        });
+        if let Some((op, step, update)) = update
+            && init.fract() == 0.0
+            && step.fract() == 0.0
+            && !lp.blocks.contains(&entry)
+        {
+            ivs.insert(phi.dest.clone(), InductionVar { init: *init, step, op, update, entry, latch });
+        }
+    }
+    ivs
+}
+
+/// What to do to unroll a loop.
+struct Unroll {
+    trips: usize,
+    /// The successor of the header that stays in the loop, and the one that leaves.
+    inside: BlockId,
+    exit: BlockId,
+    entry: BlockId,
+    latch: BlockId,
+}
+
+/// Decides whether a loop can be fully unrolled: it must be innermost, only
+/// leave from its header, and test an induction variable against a constant.
+fn plan_unroll(cfg: &Cfg, lp: &Loop, dominators: &Dominators) -> Option<Unroll> {
+    let nested = lp.blocks.iter().any(|&block| {
+        cfg.successors(block).iter().any(|&succ| succ != lp.header && lp.blocks.contains(&succ) && dominators.dominates(succ, block))
+    });
+    let leaves = lp.blocks.iter().any(|&block| {
+        block != lp.header && cfg.successors(block).iter().any(|succ| !lp.blocks.contains(succ))
+    });
+    if nested || leaves {
+        return None;
+    }
+    let header = &cfg.blocks[lp.header];
+    let Terminator::Branch { cond: Operand::Var(cond), then_block, else_block } = &header.terminator else {
+        return None;
+    };
+    let (inside, exit, continue_if) = match (lp.blocks.contains(then_block), lp.blocks.contains(else_block)) {
+        (true, false) => (*then_block, *else_block, true),
+        (false, true) => (*else_block, *then_block, false),
+        _ => return None,
+    };
+    let ivs = induction_vars(cfg, lp);
+    let test = header.instrs.iter().find_map(|instr| match &instr.kind {
+        InstrKind::Binary { dest, op, lhs, rhs } if dest == cond => Some((*op, lhs, rhs)),
+        _ => None,
+    })?;
+    let (op, lhs, rhs) = test;
+    let (var, iv) = [lhs, rhs].into_iter().find_map(|operand| match operand {
+        Operand::Var(var) => ivs.get(var).map(|iv| (var, iv)),
+        _ => None,
+    })?;
+    let substitute = |operand: &Operand, value: f64| match operand {
+        Operand::Var(v) if v == var => Some(Operand::Number(value)),
+        Operand::Number(_) => Some(operand.clone()),
+        _ => None,
+    };
+    let mut value = iv.init;
+    let mut trips = 0;
+    loop {
+        let Some(Operand::Number(result)) = fold_binary(op, &substitute(lhs, value)?, &substitute(rhs, value)?) else {
+            return None;
+        };
+        if (result != 0.0) != continue_if {
+            break;
+        }
+        trips += 1;
+        if trips > MAX_UNROLL_TRIPS {
+            return None;
+        }
+        value = if iv.op == BinOp::Add { value + iv.step } else { value - iv.step };
+    }
+    let size: usize = lp.blocks.iter().map(|&b| cfg.blocks[b].phis.len() + cfg.blocks[b].instrs.len() + 1).sum();
+    if size * trips.max(1) > MAX_UNROLL_SIZE {
+        return None;
+    }
+    Some(Unroll { trips, inside, exit, entry: iv.entry, latch: iv.latch })
+}
+
+/// Lays out `trips` renamed copies of the loop body, each followed by the next,
+/// and a final copy of the header that falls through to the exit.
+fn unroll_loop(cfg: &mut Cfg, lp: &Loop, unroll: &Unroll, namer: &mut Namer) {
+    let header_phis = cfg.blocks[lp.header].phis.clone();
+    let header_line = cfg.blocks[lp.header].terminator_line;
+    let mut previous: HashMap<Var, Var> = HashMap::new();
+    // The edge into the next copy's header, patched once that copy exists
+    let mut pending_edge = (unroll.entry, lp.header);
+    for copy in 0..=unroll.trips {
+        let last = copy == unroll.trips;
+        let blocks: Vec<BlockId> = if last { vec![lp.header] } else { lp.blocks.iter().copied().collect() };
+        let ids: HashMap<BlockId, BlockId> = blocks.iter().enumerate().map(|(i, &b)| (b, cfg.blocks.len() + i)).collect();
+        let mut names = HashMap::new();
+        for &block in &blocks {
+            let data = &cfg.blocks[block];
+            for dest in data.phis.iter().map(|p| &p.dest).chain(data.instrs.iter().filter_map(|i| i.kind.dest())) {
+                names.insert(dest.clone(), namer.version(dest));
+            }
+        }
+        let rename = |names: &HashMap<Var, Var>, operand: &mut Operand| {
+            if let Operand::Var(var) = operand
+                && let Some(renamed) = names.get(var)
+            {
+                *var = renamed.clone();
+            }
+        };
+        let (from, to) = pending_edge;
+        for succ in cfg.blocks[from].terminator.successors_mut() {
+            if *succ == to {
+                *succ = ids[&lp.header];
+            }
+        }
+        for &block in &blocks {
+            let mut data = cfg.blocks[block].clone();
+            let mut instrs = Vec::new();
+            let mut phis = Vec::new();
+            if block == lp.header {
+                for phi in &header_phis {
+                    let pred = if copy == 0 { unroll.entry } else { unroll.latch };
+                    let mut src = phi.args.iter().find(|(p, _)| *p == pred).map(|(_, v)| v.clone()).unwrap();
+                    if copy > 0 {
+                        rename(&previous, &mut src);
+                    }
+                    instrs.push(Instr { kind: InstrKind::Copy { dest: names[&phi.dest].clone(), src }, line: header_line });
+                }
+                // A loop that is a single block continues straight into the next header copy
+                data.terminator = Terminator::Jump(match last {
+                    true => unroll.exit,
+                    false if unroll.inside == lp.header => lp.header,
+                    false => ids[&unroll.inside],
+                });
+            } else {
+                for mut phi in data.phis {
+                    phi.dest = names[&phi.dest].clone();
+                    for (pred, value) in &mut phi.args {
+                        *pred = ids[pred];
+                        rename(&names, value);
+                    }
+                    phis.push(phi);
+                }
+                if let Some(operand) = data.terminator.operand_mut() {
+                    rename(&names, operand);
+                }
+                for succ in data.terminator.successors_mut() {
+                    if *succ != lp.header {
+                        *succ = ids[succ];
+                    }
+                }
+            }
+            for mut instr in data.instrs {
+                for operand in instr.kind.operands_mut() {
+                    rename(&names, operand);
+                }
+                if let Some(dest) = instr.kind.dest_mut() {
+                    *dest = names[dest].clone();
+                }
+                instrs.push(instr);
+            }
+            if block == unroll.latch && !last {
+                pending_edge = (ids[&block], lp.header);
+            }
+            cfg.blocks.push(Block { phis, instrs, terminator: data.terminator, terminator_line: data.terminator_line, preds: Vec::new() });
+        }
+        if last {
+            // Code after the loop now reads the values computed by the final header copy
+            let exit_copy = ids[&lp.header];
+            for phi in &mut cfg.blocks[unroll.exit].phis {
+                for (pred, _) in &mut phi.args {
+                    if *pred == lp.header {
+                        *pred = exit_copy;
+                    }
+                }
+            }
+            for (id, block) in cfg.blocks.iter_mut().enumerate() {
+                if lp.blocks.contains(&id) || id >= exit_copy {
+                    continue;
+                }
+                for phi in &mut block.phis {
+                    for (_, value) in &mut phi.args {
+                        rename(&names, value);
+                    }
+                }
+                for instr in &mut block.instrs {
+                    for operand in instr.kind.operands_mut() {
+                        rename(&names, operand);
+                    }
+                }
+                if let Some(operand) = block.terminator.operand_mut() {
+                    rename(&names, operand);
+                }
+            }
+        }
+        previous = names;
+    }
+    cfg.recompute_preds();
+}
+
+/// Runs `transform` on every reachable loop, innermost first, recomputing the
+/// loop nest after each change.
+fn for_each_loop(cfg: &mut Cfg, mut transform: impl FnMut(&mut Cfg, &Loop, &Dominators) -> bool) -> bool {
+    let mut changed = false;
+    let mut visited = BTreeSet::new();
+    loop {
+        let dominators = Dominators::compute(cfg);
+        let loops = cfg.natural_loops(&dominators);
+        let Some(lp) = loops.into_iter().find(|lp| !visited.contains(&lp.header)) else {
+            break;
+        };
+        visited.insert(lp.header);
+        changed |= transform(cfg, &lp, &dominators);
+    }
+    changed
+}
+
+/// The block that enters the loop from outside, creating one on the entry
+/// edge if needed. Loops entered from more than one block have none.
+fn preheader(cfg: &mut Cfg, lp: &Loop, dominators: &Dominators) -> Option<BlockId> {
+    let outside: Vec<BlockId> = cfg.blocks[lp.header]
+        .preds
+        .iter()
+        .copied()
+        .filter(|pred| !lp.blocks.contains(pred) && dominators.dominates(0, *pred))
+        .collect();
+    match outside[..] {
+        [pred] if cfg.successors(pred) == [lp.header] => Some(pred),
+        [pred] => Some(cfg.split_edge(pred, lp.header)),
+        _ => None,
+    }
+}
+
+/// Variables defined by a phi or an instruction inside the loop.
+fn loop_defs(cfg: &Cfg, lp: &Loop) -> HashSet<Var> {
+    let mut defs = HashSet::new();
+    for &block in &lp.blocks {
+        let data = &cfg.blocks[block];
+        defs.extend(data.phis.iter().map(|phi| phi.dest.clone()));
+        defs.extend(data.instrs.iter().filter_map(|instr| instr.kind.dest().cloned()));
+    }
+    defs
+}
+
+/// Variables that always hold a number once defined. Arithmetic on them cannot
+/// fail, so it is safe to run even on paths that would not have run it.
+fn numeric_vars(cfg: &Cfg) -> HashSet<Var> {
+    // Start from every candidate and drop the ones with a non-numeric input
+    let mut numeric = HashSet::new();
+    for block in &cfg.blocks {
+        numeric.extend(block.phis.iter().map(|phi| phi.dest.clone()));
+        for instr in &block.instrs {
+            if let InstrKind::Copy { dest, .. } | InstrKind::Unary { dest, .. } | InstrKind::Binary { dest, .. } = &instr.kind {
+                numeric.insert(dest.clone());
+            }
+        }
+    }
+    let is_numeric = |operand: &Operand, numeric: &HashSet<Var>| match operand {
+        Operand::Number(_) => true,
+        Operand::Str(_) => false,
+        Operand::Var(var) => numeric.contains(var),
+    };
+    loop {
+        let mut dropped = Vec::new();
+        for block in &cfg.blocks {
+            for phi in &block.phis {
+                if numeric.contains(&phi.dest) && !phi.args.iter().all(|(_, value)| is_numeric(value, &numeric)) {
+                    dropped.push(phi.dest.clone());
+                }
+            }
+            for instr in &block.instrs {
+                if let InstrKind::Copy { dest, src } = &instr.kind
+                    && numeric.contains(dest)
+                    && !is_numeric(src, &numeric)
+                {
+                    dropped.push(dest.clone());
+                }
+            }
+        }
+        if dropped.is_empty() {
+            break;
+        }
+        for var in dropped {
+            numeric.remove(&var);
+        }
+    }
+    numeric
+}
+
+/// Hands out SSA names that are not yet defined anywhere in a graph.
+struct Namer {
+    versions: HashMap<Var, u32>,
+    next_temp: u32,
+}
+
+impl Namer {
+    fn new(cfg: &Cfg) -> Namer {
+        let mut versions: HashMap<Var, u32> = HashMap::new();
+        for block in &cfg.blocks {
+            let dests = block.phis.iter().map(|phi| &phi.dest).chain(block.instrs.iter().filter_map(|i| i.kind.dest()));
+            for dest in dests {
+                if let Var::Version(_, n) = dest {
+                    let max = versions.entry(dest.base().clone()).or_default();
+                    *max = (*max).max(*n);
+                }
+            }
+        }
+        Namer { versions, next_temp: ssa::max_temp(cfg).map_or(0, |t| t + 1) }
+    }
+
+    /// A fresh version of `var`.
+    fn version(&mut self, var: &Var) -> Var {
+        let base = var.base().clone();
+        let n = self.versions.entry(base.clone()).or_default();
+        *n += 1;
+        Var::Version(Box::new(base), *n)
+    }
+
+    /// A temporary not used anywhere in the graph.
+    fn temp(&mut self) -> Var {
+        self.next_temp += 1;
+        Var::Temp(self.next_temp - 1)
+    }
+}
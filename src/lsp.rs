@@ -0,0 +1,567 @@
+//! `fork lsp`: a Language Server Protocol server speaking JSON-RPC over stdin
+//! and stdout. Documents are kept in memory and analyzed again on each change.
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use crate::ast::{Expr, ExprKind, Program, Stmt, StmtKind};
+use crate::formatter::{self, signature};
+use crate::json::Json;
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, Parser};
+use crate::semantic::{Analysis, SemanticAnalyzer, SemanticError, SymbolKind};
+use crate::token::{Span, Token};
+
+// JSON-RPC and LSP error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+const REQUEST_FAILED: i64 = -32803;
+
+// LSP enumerations
+const SEVERITY_ERROR: usize = 1;
+const SYNC_FULL: usize = 1;
+const SYMBOL_FUNCTION: usize = 12;
+const SYMBOL_VARIABLE: usize = 13;
+const COMPLETION_TEXT: usize = 1;
+const COMPLETION_FUNCTION: usize = 3;
+const COMPLETION_VARIABLE: usize = 6;
+const COMPLETION_KEYWORD: usize = 14;
+
+/// Words offered by completion everywhere.
+const KEYWORDS: [&str; 11] = ["if", "else", "while", "for", "def", "return", "let", "print", "int", "float", "str"];
+
+type RpcError = (i64, String);
+
+/// Serves one session, reading requests from `input` until the `exit`
+/// notification or the end of the input. Returns whether `shutdown` came
+/// first, which decides the exit status the protocol asks for.
+pub fn serve(mut input: impl BufRead, output: impl Write) -> io::Result<bool> {
+    let mut server = Server { out: output, documents: HashMap::new(), initialized: false, shutdown: false };
+    while let Some(body) = read_message(&mut input)? {
+        if !server.handle(&body)? {
+            return Ok(server.shutdown);
+        }
+    }
+    Ok(false)
+}
+
+/// Reads one `Content-Length` framed message, or `None` at the end of the input.
+/// The debug adapter uses the same framing.
+pub(crate) fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            match length {
+                Some(_) => break,
+                None => continue,
+            }
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            let value = value.trim().parse::<usize>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length"))?;
+            length = Some(value);
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub(crate) fn write_message(out: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+struct Server<W: Write> {
+    out: W,
+    /// Open documents by URI.
+    documents: HashMap<String, Document>,
+    initialized: bool,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    /// Handles one message. Returns false once the client asks the server to exit.
+    fn handle(&mut self, body: &str) -> io::Result<bool> {
+        let message = match Json::parse(body) {
+            Ok(message) => message,
+            Err(e) => {
+                self.reply(Json::Null, Err((PARSE_ERROR, e)))?;
+                return Ok(true);
+            }
+        };
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let method = message.get("method").and_then(Json::as_str);
+        match (method, message.get("id")) {
+            (Some(method), Some(id)) => {
+                let result = self.request(method, &params);
+                self.reply(id.clone(), result)?;
+            }
+            (Some("exit"), None) => return Ok(false),
+            (Some(method), None) => self.notification(method, &params)?,
+            // Responses to requests the server never sends
+            (None, _) => {}
+        }
+        Ok(true)
+    }
+
+    fn reply(&mut self, id: Json, result: Result<Json, RpcError>) -> io::Result<()> {
+        let outcome = match result {
+            Ok(result) => ("result", result),
+            Err((code, message)) => ("error", Json::object([("code", Json::Number(code as f64)), ("message", message.into())])),
+        };
+        write_message(&mut self.out, &Json::object([("jsonrpc", "2.0".into()), ("id", id), outcome]))
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, RpcError> {
+        if !self.initialized && method != "initialize" {
+            return Err((SERVER_NOT_INITIALIZED, "the server has not been initialized".to_string()));
+        }
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "the server is shutting down".to_string()));
+        }
+        match method {
+            "initialize" => {
+                self.initialized = true;
+                Ok(capabilities())
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => {
+                let (document, offset) = self.position(params)?;
+                Ok(document.definition(offset).map(|span| document.location(params, span)).into())
+            }
+            "textDocument/references" => {
+                let (document, offset) = self.position(params)?;
+                let include_declaration = params.get("context").and_then(|c| c.get("includeDeclaration")).and_then(Json::as_bool).unwrap_or(true);
+                let spans = document.references(offset, include_declaration);
+                Ok(Json::Array(spans.into_iter().map(|span| document.location(params, span)).collect()))
+            }
+            "textDocument/hover" => {
+                let (document, offset) = self.position(params)?;
+                Ok(document.hover(offset).into())
+            }
+            "textDocument/documentSymbol" => Ok(Json::Array(self.document(params)?.symbols())),
+            "textDocument/completion" => {
+                let (document, offset) = self.position(params)?;
+                Ok(Json::Array(document.completions(offset)))
+            }
+            "textDocument/formatting" => self.document(params)?.formatting(),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let uri = params.get("textDocument").and_then(|doc| doc.get("uri")).and_then(Json::as_str).map(str::to_string);
+        let Some(uri) = uri.filter(|_| self.initialized) else {
+            return Ok(());
+        };
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").and_then(|doc| doc.get("text")).and_then(Json::as_str).unwrap_or_default();
+                self.documents.insert(uri.clone(), Document::new(text.to_string()));
+            }
+            "textDocument/didChange" => {
+                // Changes are whole texts, as asked for in the capabilities
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or_default();
+                let Some(text) = changes.last().and_then(|change| change.get("text")).and_then(Json::as_str) else {
+                    return Ok(());
+                };
+                self.documents.insert(uri.clone(), Document::new(text.to_string()));
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+            }
+            _ => return Ok(()),
+        }
+        let diagnostics = self.documents.get(&uri).map_or_else(Vec::new, Document::diagnostics);
+        let params = Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]);
+        let message = Json::object([("jsonrpc", "2.0".into()), ("method", "textDocument/publishDiagnostics".into()), ("params", params)]);
+        write_message(&mut self.out, &message)
+    }
+
+    fn document(&self, params: &Json) -> Result<&Document, RpcError> {
+        let uri = params.get("textDocument").and_then(|doc| doc.get("uri")).and_then(Json::as_str);
+        let uri = uri.ok_or_else(|| (INVALID_PARAMS, "missing textDocument.uri".to_string()))?;
+        self.documents.get(uri).ok_or_else(|| (INVALID_PARAMS, format!("document '{}' is not open", uri)))
+    }
+
+    /// The document and byte offset a text-document position request is about.
+    fn position(&self, params: &Json) -> Result<(&Document, usize), RpcError> {
+        let document = self.document(params)?;
+        let offset = params.get("position").and_then(|position| document.offset(position));
+        Ok((document, offset.ok_or_else(|| (INVALID_PARAMS, "missing or invalid position".to_string()))?))
+    }
+}
+
+fn capabilities() -> Json {
+    let capabilities = Json::object([
+        ("textDocumentSync", SYNC_FULL.into()),
+        ("definitionProvider", true.into()),
+        ("referencesProvider", true.into()),
+        ("hoverProvider", true.into()),
+        ("documentSymbolProvider", true.into()),
+        ("completionProvider", Json::object([])),
+        ("documentFormattingProvider", true.into()),
+    ]);
+    let info = Json::object([("name", "fork".into()), ("version", env!("CARGO_PKG_VERSION").into())]);
+    Json::object([("capabilities", capabilities), ("serverInfo", info)])
+}
+
+/// What a name refers to.
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    /// A slot of a scope in `Analysis::scopes`.
+    Variable { scope: usize, slot: usize },
+    Function(String),
+}
+
+/// A name in the source and what it refers to.
+struct Occurrence {
+    /// Span of the name itself.
+    name: Span,
+    /// Span the analysis records the use under, which for a statement is the
+    /// span of its first token.
+    key: Span,
+    target: Target,
+}
+
+/// An open document and everything found out about it.
+struct Document {
+    text: String,
+    /// Tokens other than whitespace and comments.
+    tokens: Vec<(Token, Span)>,
+    program: Result<Program, ParseError>,
+    analysis: Option<Analysis>,
+    errors: Vec<SemanticError>,
+    occurrences: Vec<Occurrence>,
+}
+
+impl Document {
+    fn new(text: String) -> Document {
+        let tokens = Lexer::new().tokenize_with_spans(&text);
+        let program = Parser::new(tokens.clone()).parse();
+        let tokens = tokens.into_iter().filter(|(token, _)| !matches!(token, Token::Whitespace | Token::Comment(_))).collect();
+        let mut document = Document { text, tokens, program, analysis: None, errors: Vec::new(), occurrences: Vec::new() };
+        if let Ok(program) = &document.program {
+            let (analysis, errors) = SemanticAnalyzer::new().inspect(program);
+            document.occurrences = document.find_occurrences(program, &analysis);
+            document.analysis = Some(analysis);
+            document.errors = errors;
+        }
+        document
+    }
+
+    fn find_occurrences(&self, program: &Program, analysis: &Analysis) -> Vec<Occurrence> {
+        let mut occurrences = Vec::new();
+        for (scope, symbols) in analysis.scopes.iter().enumerate() {
+            for (slot, symbol) in symbols.symbols.iter().enumerate() {
+                if let (SymbolKind::Param, Some(span)) = (symbol.kind, symbol.declared) {
+                    occurrences.push(Occurrence { name: span, key: span, target: Target::Variable { scope, slot } });
+                }
+            }
+        }
+        for resolution in &analysis.resolutions {
+            let name = &analysis.scopes[resolution.scope].symbols[resolution.slot].name;
+            if let Some(span) = self.name_span(resolution.span.start, name) {
+                let target = Target::Variable { scope: resolution.scope, slot: resolution.slot };
+                occurrences.push(Occurrence { name: span, key: resolution.span, target });
+            }
+        }
+        for stmt in &program.stmts {
+            if let StmtKind::Def { name, .. } = &stmt.kind
+                && let Some(span) = self.name_span(stmt.span.start, name)
+            {
+                occurrences.push(Occurrence { name: span, key: stmt.span, target: Target::Function(name.clone()) });
+            }
+        }
+        let mut calls = Vec::new();
+        collect_calls(&program.stmts, &mut calls);
+        for (name, span) in calls {
+            occurrences.push(Occurrence { name: span, key: span, target: Target::Function(name) });
+        }
+        occurrences.sort_by_key(|occurrence| occurrence.name.start);
+        occurrences
+    }
+
+    /// The span of the first identifier `name` at or after `start`. Statements
+    /// are recorded at their first token, which for `let x` is the keyword.
+    fn name_span(&self, start: usize, name: &str) -> Option<Span> {
+        self.tokens
+            .iter()
+            .find(|(token, span)| span.start >= start && matches!(token, Token::Identifier(ident) if ident == name))
+            .map(|(_, span)| *span)
+    }
+
+    fn occurrence_at(&self, offset: usize) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|occurrence| occurrence.name.start <= offset && offset <= occurrence.name.end)
+    }
+
+    /// Where the target of a name is declared: its first assignment, its
+    /// parameter or its `def`.
+    fn declaration(&self, target: &Target) -> Option<Span> {
+        match target {
+            Target::Variable { scope, slot } => {
+                let symbol = &self.analysis.as_ref()?.scopes[*scope].symbols[*slot];
+                let declared = symbol.declared?;
+                match symbol.kind {
+                    SymbolKind::Param => Some(declared),
+                    _ => self.name_span(declared.start, &symbol.name),
+                }
+            }
+            Target::Function(name) => self.def(name).and_then(|def| self.name_span(def.span.start, name)),
+        }
+    }
+
+    fn def(&self, name: &str) -> Option<&Stmt> {
+        let program = self.program.as_ref().ok()?;
+        program.stmts.iter().find(|stmt| matches!(&stmt.kind, StmtKind::Def { name: def, .. } if def == name))
+    }
+
+    fn definition(&self, offset: usize) -> Option<Span> {
+        self.declaration(&self.occurrence_at(offset)?.target)
+    }
+
+    fn references(&self, offset: usize, include_declaration: bool) -> Vec<Span> {
+        let Some(target) = self.occurrence_at(offset).map(|occurrence| &occurrence.target) else {
+            return Vec::new();
+        };
+        let declaration = self.declaration(target);
+        let mut seen = HashSet::new();
+        self.occurrences
+            .iter()
+            .filter(|occurrence| occurrence.target == *target)
+            .map(|occurrence| occurrence.name)
+            .filter(|span| include_declaration || Some(*span) != declaration)
+            .filter(|span| seen.insert(span.start))
+            .collect()
+    }
+
+    fn hover(&self, offset: usize) -> Option<Json> {
+        let occurrence = self.occurrence_at(offset)?;
+        let signature = match &occurrence.target {
+            Target::Variable { scope, slot } => {
+                let analysis = self.analysis.as_ref()?;
+                let symbol = &analysis.scopes[*scope].symbols[*slot];
+                let ty = match analysis.types.get(&occurrence.key.start) {
+                    Some(ty) => ty.name(),
+                    None => symbol.ty.map_or("any", |ty| ty.name()),
+                };
+                let kind = if symbol.kind == SymbolKind::Param { "parameter" } else { "variable" };
+                format!("({}) {}: {}", kind, symbol.name, ty)
+            }
+            Target::Function(name) => signature(self.def(name)?),
+        };
+        let contents = Json::object([("kind", "markdown".into()), ("value", format!("```fork\n{}\n```", signature).into())]);
+        Some(Json::object([("contents", contents), ("range", self.range(occurrence.name))]))
+    }
+
+    /// Functions with their parameters and variables, then top-level variables.
+    fn symbols(&self) -> Vec<Json> {
+        let (Ok(program), Some(analysis)) = (&self.program, &self.analysis) else {
+            return Vec::new();
+        };
+        let variables = |scope: usize| -> Vec<Json> {
+            let symbols = analysis.scopes[scope].symbols.iter().enumerate();
+            symbols
+                .filter_map(|(slot, symbol)| {
+                    let span = self.declaration(&Target::Variable { scope, slot })?;
+                    let range = self.range(span);
+                    Some(Json::object([
+                        ("name", symbol.name.as_str().into()),
+                        ("kind", SYMBOL_VARIABLE.into()),
+                        ("range", range.clone()),
+                        ("selectionRange", range),
+                    ]))
+                })
+                .collect()
+        };
+        let defs = program.stmts.iter().filter(|stmt| matches!(stmt.kind, StmtKind::Def { .. }));
+        let mut symbols: Vec<Json> = defs
+            .enumerate()
+            .filter_map(|(idx, def)| {
+                let StmtKind::Def { name, .. } = &def.kind else { return None };
+                let end = self.block_end(def.span.start);
+                let whole = Span { start: def.span.start, end, ..def.span };
+                Some(Json::object([
+                    ("name", name.as_str().into()),
+                    ("detail", signature(def).into()),
+                    ("kind", SYMBOL_FUNCTION.into()),
+                    ("range", self.range(whole)),
+                    ("selectionRange", self.range(self.name_span(def.span.start, name)?)),
+                    ("children", variables(idx + 1).into()),
+                ]))
+            })
+            .collect();
+        symbols.extend(variables(0));
+        symbols
+    }
+
+    /// Keywords, the functions, and the variables of the scope at `offset`.
+    /// While the document does not parse, every identifier in it is offered.
+    fn completions(&self, offset: usize) -> Vec<Json> {
+        let item = |label: &str, kind: usize, detail: Option<String>| {
+            let mut item = vec![("label".to_string(), label.into()), ("kind".to_string(), kind.into())];
+            item.extend(detail.map(|detail| ("detail".to_string(), detail.into())));
+            Json::Object(item)
+        };
+        let mut items: Vec<Json> = KEYWORDS.iter().map(|keyword| item(keyword, COMPLETION_KEYWORD, None)).collect();
+        let (Ok(program), Some(analysis)) = (&self.program, &self.analysis) else {
+            let mut seen = HashSet::new();
+            for (token, _) in &self.tokens {
+                if let Token::Identifier(name) = token
+                    && seen.insert(name)
+                {
+                    items.push(item(name, COMPLETION_TEXT, None));
+                }
+            }
+            return items;
+        };
+        let mut scope = 0;
+        for (idx, def) in program.stmts.iter().filter(|stmt| matches!(stmt.kind, StmtKind::Def { .. })).enumerate() {
+            if def.span.start <= offset && offset <= self.block_end(def.span.start) {
+                scope = idx + 1;
+            }
+        }
+        for def in &program.stmts {
+            if let StmtKind::Def { name, .. } = &def.kind {
+                items.push(item(name, COMPLETION_FUNCTION, Some(signature(def))));
+            }
+        }
+        for symbol in &analysis.scopes[scope].symbols {
+            items.push(item(&symbol.name, COMPLETION_VARIABLE, symbol.ty.map(|ty| ty.name().to_string())));
+        }
+        items
+    }
+
+    fn formatting(&self) -> Result<Json, RpcError> {
+        let formatted = formatter::format_source(&self.text).map_err(|e| (REQUEST_FAILED, e.to_string()))?;
+        if formatted == self.text {
+            return Ok(Json::Array(Vec::new()));
+        }
+        let whole = Json::object([("start", self.position(0)), ("end", self.position(self.text.len()))]);
+        Ok(Json::Array(vec![Json::object([("range", whole), ("newText", formatted.into())])]))
+    }
+
+    fn diagnostics(&self) -> Vec<Json> {
+        let diagnostic = |span: Span, message: String| {
+            Json::object([
+                ("range", self.range(span)),
+                ("severity", SEVERITY_ERROR.into()),
+                ("source", "fork".into()),
+                ("message", message.into()),
+            ])
+        };
+        match &self.program {
+            Err(e) => vec![diagnostic(e.span, format!("SyntaxError: {}", e.message))],
+            Ok(_) => self.errors.iter().map(|e| diagnostic(e.span, e.message.clone())).collect(),
+        }
+    }
+
+    /// The end of the block that follows `start`, such as a function body.
+    fn block_end(&self, start: usize) -> usize {
+        let mut depth = 0;
+        for (token, span) in self.tokens.iter().filter(|(_, span)| span.start >= start) {
+            match token {
+                Token::Symbol('{') => depth += 1,
+                Token::Symbol('}') if depth == 1 => return span.end,
+                Token::Symbol('}') => depth -= 1,
+                _ => {}
+            }
+        }
+        self.text.len()
+    }
+
+    fn location(&self, params: &Json, span: Span) -> Json {
+        let uri = params.get("textDocument").and_then(|doc| doc.get("uri")).cloned().unwrap_or(Json::Null);
+        Json::object([("uri", uri), ("range", self.range(span))])
+    }
+
+    fn range(&self, span: Span) -> Json {
+        Json::object([("start", self.position(span.start)), ("end", self.position(span.end))])
+    }
+
+    /// The LSP position of a byte offset: a 0-based line, and a column
+    /// counted in UTF-16 code units.
+    fn position(&self, offset: usize) -> Json {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let line = before.matches('\n').count();
+        let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+        Json::object([("line", line.into()), ("character", character.into())])
+    }
+
+    /// The byte offset of an LSP position. A column past the end of its line
+    /// means the end of the line.
+    fn offset(&self, position: &Json) -> Option<usize> {
+        let line = position.get("line")?.as_usize()?;
+        let character = position.get("character")?.as_usize()?;
+        let line_start = if line == 0 {
+            0
+        } else {
+            self.text.match_indices('\n').nth(line - 1).map(|(idx, _)| idx + 1)?
+        };
+        let mut units = 0;
+        for (idx, c) in self.text[line_start..].char_indices() {
+            if units >= character || c == '\n' {
+                return Some(line_start + idx);
+            }
+            units += c.len_utf16();
+        }
+        Some(self.text.len())
+    }
+}
+
+/// Every call in `stmts`, as the function name and the span of the name.
+fn collect_calls(stmts: &[Stmt], calls: &mut Vec<(String, Span)>) {
+    fn expr(e: &Expr, calls: &mut Vec<(String, Span)>) {
+        match &e.kind {
+            ExprKind::Number(_) | ExprKind::Str(_) | ExprKind::Var(_) => {}
+            ExprKind::Unary { expr: operand, .. } => expr(operand, calls),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                expr(lhs, calls);
+                expr(rhs, calls);
+            }
+            ExprKind::Call { name, args } => {
+                calls.push((name.clone(), e.span));
+                args.iter().for_each(|arg| expr(arg, calls));
+            }
+        }
+    }
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Assign { value, .. } | StmtKind::Let { value, .. } | StmtKind::Expr(value) | StmtKind::Return(Some(value)) => expr(value, calls),
+            StmtKind::Print(args) => args.iter().for_each(|arg| expr(arg, calls)),
+            StmtKind::If { cond, then_block, else_block } => {
+                expr(cond, calls);
+                collect_calls(then_block, calls);
+                collect_calls(else_block.as_deref().unwrap_or_default(), calls);
+            }
+            StmtKind::While { cond, body } => {
+                expr(cond, calls);
+                collect_calls(body, calls);
+            }
+            StmtKind::For { init, cond, update, body } => {
+                for part in [init, update].into_iter().flatten() {
+                    collect_calls(std::slice::from_ref(&**part), calls);
+                }
+                if let Some(cond) = cond {
+                    expr(cond, calls);
+                }
+                collect_calls(body, calls);
+            }
+            StmtKind::Def { body, .. } => collect_calls(body, calls),
+            StmtKind::Increment { .. } | StmtKind::Return(None) => {}
+        }
+    }
+}
@@ -1,53 +1,737 @@
-mod token;
-mod lexer;
-mod interpreter;
-
+use std::cell::RefCell;
 use std::env;
 use std::fs;
-use std::io::{self, Write};
-use lexer::Lexer;
-use interpreter::Interpreter;
+use std::io;
+use std::process;
+use std::rc::Rc;
+use std::time::Instant;
+use fork::lexer::Lexer;
+use fork::parser::Parser;
+use fork::semantic::SemanticAnalyzer;
+use fork::config::Config;
+use fork::lint::{Linter, Severity};
+use fork::bytecode::Module;
+use fork::codegen::CodeGenerator;
+use fork::icg::ICG;
+use fork::ir::IrProgram;
+use fork::cfg::{Cfg, Dominators};
+use fork::coverage::FileCoverage;
+use fork::optimizer::{OptLevel, Optimizer, PrintAfter};
+use fork::interpreter::capabilities::{Capabilities, Paths};
+use fork::interpreter::streams::StdinLines;
+use fork::interpreter::Interpreter;
+use fork::vm::Vm;
+use fork::engine::{read_checked_program, semantic_errors};
+use fork::{coverage, dap, debugger, disasm, formatter, lint, lsp, profiler, repl, ssa, testing, trace, Engine, ForkError};
 
 fn main() {
-    let lexer = Lexer::new();
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        // File mode
-        let filename = &args[1];
-        if !filename.ends_with(".fork") {
-            eprintln!("Error: Only .fork files are supported.");
-            return;
+    match args.get(1).map(String::as_str) {
+        Some("disasm") => disasm_command(&args[2..]),
+        Some("compile") => compile_command(&args[2..]),
+        Some("ir") => ir_command(&args[2..]),
+        Some("cfg") => cfg_command(&args[2..]),
+        Some("exec") => exec_command(&args[2..]),
+        Some("check") => check_command(&args[2..]),
+        Some("lint") => lint_command(&args[2..]),
+        Some("fmt") => fmt_command(&args[2..]),
+        Some("lsp") => lsp_command(&args[2..]),
+        Some("debug") => debug_command(&args[2..]),
+        Some("dap") => dap_command(&args[2..]),
+        Some("profile") => profile_command(&args[2..]),
+        Some("test") => test_command(&args[2..]),
+        Some(arg) if arg.starts_with("--trace") => trace_command(&args[1..]),
+        _ => run_command(&args[1..]),
+    }
+}
+
+/// `fork [--allow-...] [--max-memory=SIZE] [--stats] [file.fork]`: interpret a
+/// source file, or start the REPL without one. The scripts may only do what
+/// the flags allow.
+fn run_command(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!(
+            "Usage: fork [--allow-read=DIR] [--allow-write=DIR] [--allow-env] [--allow-clock] [--allow-random] [--allow-exit] [--allow-all] [--max-memory=SIZE] [--stats] [file.fork]"
+        );
+        process::exit(2);
+    };
+    let mut capabilities = Capabilities::none();
+    let mut max_memory = None;
+    let mut stats = false;
+    let mut files = Vec::new();
+    for arg in args {
+        if let Some(dir) = arg.strip_prefix("--allow-read=") {
+            if let Paths::Under(dirs) = &mut capabilities.read {
+                dirs.push(dir.into());
+            }
+        } else if let Some(dir) = arg.strip_prefix("--allow-write=") {
+            if let Paths::Under(dirs) = &mut capabilities.write {
+                dirs.push(dir.into());
+            }
+        } else if arg == "--allow-env" {
+            capabilities.env = true;
+        } else if arg == "--allow-clock" {
+            capabilities.clock = true;
+        } else if arg == "--allow-random" {
+            capabilities.random = true;
+        } else if arg == "--allow-exit" {
+            capabilities.exit = true;
+        } else if arg == "--allow-all" {
+            capabilities = Capabilities::all();
+        } else if let Some(size) = arg.strip_prefix("--max-memory=") {
+            max_memory = Some(parse_size(size).unwrap_or_else(|| usage()));
+        } else if arg == "--stats" {
+            stats = true;
+        } else if arg.starts_with("--") {
+            usage();
+        } else {
+            files.push(arg);
+        }
+    }
+    let mut engine = Engine::new();
+    engine.set_capabilities(capabilities);
+    engine.set_memory_limit(max_memory);
+    let start = Instant::now();
+    let code = match files.as_slice() {
+        [] => repl::run(&mut engine),
+        [filename] => run_file(&mut engine, filename),
+        _ => usage(),
+    };
+    if stats {
+        let memory = engine.interpreter().memory();
+        eprintln!("time: {:.3}ms", start.elapsed().as_secs_f64() * 1000.0);
+        eprintln!("peak memory: {} bytes", memory.peak);
+    }
+    if let Some(code) = code {
+        process::exit(code);
+    }
+}
+
+/// A number of bytes, optionally with a `K`, `M` or `G` suffix for units of 1024.
+fn parse_size(size: &str) -> Option<usize> {
+    let (digits, unit) = match size.char_indices().last()? {
+        (idx, 'K' | 'k') => (&size[..idx], 1 << 10),
+        (idx, 'M' | 'm') => (&size[..idx], 1 << 20),
+        (idx, 'G' | 'g') => (&size[..idx], 1 << 30),
+        _ => (size, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+/// Runs a source file, returning the status the script passed to `exit`.
+fn run_file(engine: &mut Engine, filename: &str) -> Option<i32> {
+    match engine.run_file(filename) {
+        Ok(_) => {}
+        Err(ForkError::Exit(code)) => return Some(code),
+        Err(e) => eprintln!("{}", e),
+    }
+    None
+}
+
+/// `fork check file.fork...`: report name and type errors without running anything.
+/// Exits with status 1 if any file has errors.
+fn check_command(args: &[String]) {
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with('-')) {
+        eprintln!("Usage: fork check <file.fork>...");
+        process::exit(2);
+    }
+    let mut failed = false;
+    for filename in args {
+        match check_file(filename) {
+            Ok(()) => println!("{}: ok", filename),
+            Err(e) => {
+                failed = true;
+                for line in e.lines() {
+                    eprintln!("{}: {}", filename, line);
+                }
+            }
         }
-        match fs::read_to_string(filename) {
-            Ok(content) => {
-                let tokens = lexer.tokenize(&content);
-                let mut interpreter = Interpreter::new();
-                interpreter.interpret(&tokens);
-            },
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// `fork dap`: serve the Debug Adapter Protocol over stdin and stdout.
+fn dap_command(args: &[String]) {
+    if !args.is_empty() {
+        eprintln!("Usage: fork dap");
+        process::exit(2);
+    }
+    if let Err(e) = dap::serve(io::stdin().lock(), io::stdout().lock()) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Reads, parses and analyzes a `.fork` file.
+fn check_file(filename: &str) -> Result<(), String> {
+    if !filename.ends_with(".fork") {
+        return Err("Error: Only .fork files are supported.".to_string());
+    }
+    let source = fs::read_to_string(filename).map_err(|e| format!("Error reading file {}: {}", filename, e))?;
+    let tokens = Lexer::new().tokenize_with_spans(&source);
+    let program = Parser::new(tokens).parse().map_err(|e| e.to_string())?;
+    SemanticAnalyzer::new().analyze(&program).map_err(|errors| semantic_errors(&errors))?;
+    Ok(())
+}
+
+/// `fork lint file.fork...`: report likely mistakes, with the rules configured
+/// by the closest `fork.toml`. Exits with status 1 if a file has errors or a
+/// diagnostic of severity `error`.
+fn lint_command(args: &[String]) {
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with('-')) {
+        eprintln!("Usage: fork lint <file.fork>...");
+        process::exit(2);
+    }
+    let mut failed = false;
+    for filename in args {
+        match lint_file(filename) {
+            Ok(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    println!("{}:{}", filename, diagnostic);
+                }
+                failed |= diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error);
+            }
             Err(e) => {
-                eprintln!("Error reading file {}: {}", filename, e);
-                return;
+                failed = true;
+                for line in e.lines() {
+                    eprintln!("{}: {}", filename, line);
+                }
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Reads, parses, analyzes and lints a `.fork` file.
+fn lint_file(filename: &str) -> Result<Vec<lint::Diagnostic>, String> {
+    if !filename.ends_with(".fork") {
+        return Err("Error: Only .fork files are supported.".to_string());
+    }
+    let source = fs::read_to_string(filename).map_err(|e| format!("Error reading file {}: {}", filename, e))?;
+    let config = Config::for_file(std::path::Path::new(filename)).map_err(|e| e.to_string())?;
+    let linter = Linter::new().configure(&config).map_err(|e| e.to_string())?;
+    let tokens = Lexer::new().tokenize_with_spans(&source);
+    let program = Parser::new(tokens.clone()).parse().map_err(|e| e.to_string())?;
+    let analysis = SemanticAnalyzer::new().analyze(&program).map_err(|errors| semantic_errors(&errors))?;
+    Ok(linter.lint(&program, &analysis, &tokens))
+}
+
+/// `fork fmt [--check] file.fork...`: rewrite files in the canonical style.
+/// With `--check`, list the files that would change instead, and exit with
+/// status 1 if there are any.
+fn fmt_command(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if files.is_empty() || files.iter().any(|arg| arg.starts_with('-')) {
+        eprintln!("Usage: fork fmt [--check] <file.fork>...");
+        process::exit(2);
+    }
+    let mut failed = false;
+    for filename in files {
+        match fmt_file(filename, check) {
+            Ok(false) => {}
+            Ok(true) if check => {
+                failed = true;
+                println!("{}: not formatted", filename);
+            }
+            Ok(true) => println!("{}: formatted", filename),
+            Err(e) => {
+                failed = true;
+                eprintln!("{}: {}", filename, e);
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Formats a `.fork` file, writing it back unless `check` is set. Returns
+/// whether the formatted text differs from the file.
+fn fmt_file(filename: &str, check: bool) -> Result<bool, String> {
+    if !filename.ends_with(".fork") {
+        return Err("Error: Only .fork files are supported.".to_string());
+    }
+    let source = fs::read_to_string(filename).map_err(|e| format!("Error reading file {}: {}", filename, e))?;
+    let formatted = formatter::format_source(&source).map_err(|e| e.to_string())?;
+    if formatted == source {
+        return Ok(false);
+    }
+    if !check {
+        fs::write(filename, &formatted).map_err(|e| format!("Error writing file {}: {}", filename, e))?;
+    }
+    Ok(true)
+}
+
+/// `fork lsp`: serve the Language Server Protocol over stdin and stdout.
+/// `--stdio`, which editors pass to choose that transport, is accepted.
+fn lsp_command(args: &[String]) {
+    if args.iter().any(|arg| arg != "--stdio") {
+        eprintln!("Usage: fork lsp [--stdio]");
+        process::exit(2);
+    }
+    match lsp::serve(io::stdin().lock(), io::stdout().lock()) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// `fork --trace file.fork`: run a file, logging each statement and each
+/// variable write to stderr, or as JSON Lines to the `--trace-out` file.
+fn trace_command(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("Usage: fork --trace [--trace-lines=FIRST-LAST] [--trace-var=NAME,...] [--trace-out=FILE] <file.fork>");
+        process::exit(2);
+    };
+    let mut filter = trace::Filter::default();
+    let mut out_path = None;
+    let mut files = Vec::new();
+    for arg in args {
+        if let Some(lines) = arg.strip_prefix("--trace-lines=") {
+            let (first, last) = lines.split_once('-').unwrap_or((lines, lines));
+            match (first.parse::<usize>(), last.parse::<usize>()) {
+                (Ok(first), Ok(last)) if first <= last => filter.lines = Some(first..=last),
+                _ => usage(),
+            }
+        } else if let Some(names) = arg.strip_prefix("--trace-var=") {
+            filter.names.extend(names.split(',').map(str::to_string));
+        } else if let Some(path) = arg.strip_prefix("--trace-out=") {
+            out_path = Some(path);
+        } else if arg.starts_with('-') && arg != "--trace" {
+            usage();
+        } else if arg != "--trace" {
+            files.push(arg);
+        }
+    }
+    let [filename] = files.as_slice() else {
+        usage();
+    };
+    let (program, source) = match read_checked_program(filename) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let tracer = match out_path {
+        Some(path) => match fs::File::create(path) {
+            Ok(file) => trace::Tracer::new(Box::new(io::LineWriter::new(file)), true, filter, &source),
+            Err(e) => {
+                eprintln!("Error creating {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => trace::Tracer::new(Box::new(io::stderr()), false, filter, &source),
+    };
+    let mut interpreter = Interpreter::new();
+    interpreter.attach(Box::new(tracer));
+    if let Err(e) = interpreter.interpret(&program) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// `fork test [path...]`: run the `test_` functions of the `*_test.fork` files
+/// under the given files and directories, each in a fresh interpreter. A
+/// file without test functions is a single test that passes if it runs
+/// without an error. With `--coverage[=DIR]`, also writes `lcov.info` and
+/// `index.html` to DIR, `coverage` by default.
+fn test_command(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("Usage: fork test [--filter=TEXT] [--jobs=N] [--junit=FILE] [--coverage[=DIR]] [path...]");
+        process::exit(2);
+    };
+    let mut coverage_dir = None;
+    let mut filter = None;
+    let mut jobs = 1;
+    let mut junit_path = None;
+    let mut paths = Vec::new();
+    for arg in args {
+        if arg == "--coverage" {
+            coverage_dir = Some("coverage");
+        } else if let Some(dir) = arg.strip_prefix("--coverage=") {
+            coverage_dir = Some(dir);
+        } else if let Some(text) = arg.strip_prefix("--filter=") {
+            filter = Some(text);
+        } else if let Some(count) = arg.strip_prefix("--jobs=") {
+            jobs = count.parse().ok().filter(|count| *count > 0).unwrap_or_else(|| usage());
+        } else if let Some(path) = arg.strip_prefix("--junit=") {
+            junit_path = Some(path);
+        } else if arg.starts_with('-') {
+            usage();
+        } else {
+            paths.push(arg.clone());
+        }
+    }
+    let files = testing::discover(&paths).unwrap_or_else(|e| {
+        eprintln!("Error finding tests: {}", e);
+        process::exit(1);
+    });
+    let suites: Vec<testing::Suite> = files.iter().map(|file| testing::Suite::load(file)).collect();
+    let tests: Vec<testing::Test> = suites
+        .iter()
+        .enumerate()
+        .flat_map(|(suite, tests)| tests.tests().into_iter().map(move |name| testing::Test { suite, name }))
+        .filter(|test| filter.is_none_or(|filter| test.full_name(&suites).contains(filter)))
+        .collect();
+    if tests.is_empty() {
+        eprintln!("No tests found.");
+        process::exit(1);
+    }
+    let outcomes = testing::run_all(&suites, &tests, jobs, coverage_dir.is_some());
+    let mut failed = 0;
+    for (test, outcome) in tests.iter().zip(&outcomes) {
+        let name = test.full_name(&suites);
+        match &outcome.result {
+            Ok(()) => println!("PASS {}", name),
+            Err(failure) => {
+                failed += 1;
+                println!("FAIL {}", name);
+                match &failure.location {
+                    Some((line, text)) => println!("    {}:{}: {}\n        {}", suites[test.suite].path, line, failure.message, text),
+                    None => println!("    {}", failure.message.replace('\n', "\n    ")),
+                }
+            }
+        }
+    }
+    println!("\n{} passed, {} failed", tests.len() - failed, failed);
+    if let Some(path) = junit_path
+        && let Err(e) = fs::write(path, testing::junit(&suites, &tests, &outcomes))
+    {
+        eprintln!("Error writing {}: {}", path, e);
+        process::exit(1);
+    }
+    if let Some(dir) = coverage_dir {
+        let mut covered: Vec<FileCoverage> = Vec::new();
+        for outcome in outcomes {
+            match (outcome.coverage, covered.last_mut()) {
+                (Some(coverage), Some(last)) if last.path == coverage.path => last.merge(coverage),
+                (Some(coverage), _) => covered.push(coverage),
+                (None, _) => {}
+            }
+        }
+        let written = fs::create_dir_all(dir)
+            .and_then(|()| fs::write(format!("{}/lcov.info", dir), coverage::lcov(&covered)))
+            .and_then(|()| fs::write(format!("{}/index.html", dir), coverage::html(&covered)));
+        if let Err(e) = written {
+            eprintln!("Error writing coverage to {}: {}", dir, e);
+            process::exit(1);
+        }
+        println!("Coverage: {}", coverage::Summary::total(&covered));
+        println!("Wrote {}/lcov.info and {}/index.html", dir, dir);
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// `fork profile file.fork`: run a file, then print execution counts and
+/// times per function and per line to stderr. `--folded=FILE` also writes the
+/// call stacks in the folded format of flame graph tools.
+fn profile_command(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("Usage: fork profile [--folded=FILE] <file.fork>");
+        process::exit(2);
+    };
+    let mut folded_path = None;
+    let mut files = Vec::new();
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("--folded=") {
+            folded_path = Some(path);
+        } else if arg.starts_with('-') {
+            usage();
+        } else {
+            files.push(arg);
+        }
+    }
+    let [filename] = files.as_slice() else {
+        usage();
+    };
+    let (program, source) = match read_checked_program(filename) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let profiler = Rc::new(RefCell::new(profiler::Profiler::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.attach(Box::new(Rc::clone(&profiler)));
+    let result = interpreter.interpret(&program);
+    let mut profiler = profiler.borrow_mut();
+    profiler.finish();
+    if let Err(e) = &result {
+        eprintln!("{}", e);
+    }
+    eprint!("\n{}", profiler.table(&source));
+    if let Some(path) = folded_path
+        && let Err(e) = fs::write(path, profiler.folded_stacks())
+    {
+        eprintln!("Error writing {}: {}", path, e);
+        process::exit(1);
+    }
+    if result.is_err() {
+        process::exit(1);
+    }
+}
+
+/// `fork debug file.fork`: run a file under the interactive debugger, pausing
+/// before its first statement.
+fn debug_command(args: &[String]) {
+    let [filename] = args else {
+        eprintln!("Usage: fork debug <file.fork>");
+        process::exit(2);
+    };
+    let (program, source) = match read_checked_program(filename) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let terminal = debugger::Terminal::new(StdinLines::new(), io::stdout(), &source);
+    let mut interpreter = Interpreter::new();
+    interpreter.attach(Box::new(debugger::Engine::new(terminal, debugger::Points::default(), true)));
+    match interpreter.interpret(&program) {
+        Ok(()) => println!("Program finished."),
+        Err(e) if e.message == debugger::STOPPED => println!("Program stopped."),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Optimization options accepted by every compiler subcommand.
+const OPTIMIZE_USAGE: &str = "[-O0|-O1|-O2] [--disable-pass=PASS,...] [--print-after-pass[=PASS,...]]";
+
+/// Arguments shared by the compiler subcommands.
+struct CompileArgs {
+    files: Vec<String>,
+    flags: Vec<String>,
+    output: Option<String>,
+    level: OptLevel,
+    print_after: PrintAfter,
+    disabled: Vec<String>,
+}
+
+impl CompileArgs {
+    /// Splits `args` into file names, `-o`, `-O0`/`-O1`/`-O2`, `--print-after-pass[=a,b]`,
+    /// `--disable-pass=a,b` and the subcommand-specific flags listed in `allowed`.
+    fn parse(args: &[String], allowed: &[&str]) -> Result<CompileArgs, String> {
+        let mut parsed = CompileArgs {
+            files: Vec::new(),
+            flags: Vec::new(),
+            output: None,
+            level: OptLevel::O0,
+            print_after: PrintAfter::Never,
+            disabled: Vec::new(),
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if let Some(level) = OptLevel::from_flag(arg) {
+                parsed.level = level;
+            } else if arg == "-o" {
+                parsed.output = Some(iter.next().ok_or("Error: -o needs a file name.")?.clone());
+            } else if arg == "--print-after-pass" {
+                parsed.print_after = PrintAfter::All;
+            } else if let Some(passes) = arg.strip_prefix("--print-after-pass=") {
+                let passes: Vec<String> = passes.split(',').map(str::to_string).collect();
+                if let Some(unknown) = passes.iter().find(|p| !Optimizer::is_pass(p) && *p != "ssa" && *p != "out-of-ssa") {
+                    return Err(format!("Error: unknown optimization pass '{}'.", unknown));
+                }
+                parsed.print_after = PrintAfter::Passes(passes);
+            } else if let Some(passes) = arg.strip_prefix("--disable-pass=") {
+                let passes: Vec<String> = passes.split(',').map(str::to_string).collect();
+                if let Some(unknown) = passes.iter().find(|p| !Optimizer::is_pass(p)) {
+                    return Err(format!("Error: unknown optimization pass '{}'.", unknown));
+                }
+                parsed.disabled.extend(passes);
+            } else if allowed.contains(&arg.as_str()) {
+                parsed.flags.push(arg.clone());
+            } else if arg.starts_with('-') {
+                return Err(format!("Error: unknown option '{}'.", arg));
+            } else {
+                parsed.files.push(arg.clone());
             }
         }
+        Ok(parsed)
+    }
+
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+
+    fn optimizer(&self) -> Optimizer {
+        Optimizer::new(self.level).print_after(self.print_after.clone()).disable(self.disabled.clone())
+    }
+}
+
+/// Parses subcommand arguments that must name exactly one file, printing
+/// `usage` when they do not.
+fn single_file_args(args: &[String], allowed: &[&str], usage: &str) -> Option<(String, CompileArgs)> {
+    match CompileArgs::parse(args, allowed) {
+        Ok(parsed) if parsed.files.len() == 1 => Some((parsed.files[0].clone(), parsed)),
+        Ok(_) => {
+            eprintln!("Usage: {}", usage);
+            None
+        }
+        Err(e) => {
+            eprintln!("{}\nUsage: {}", e, usage);
+            None
+        }
+    }
+}
+
+/// `fork compile file.fork [-o file.forkc] [-O0|-O1|-O2]`: write bytecode to disk.
+fn compile_command(args: &[String]) {
+    let usage = &format!("fork compile <file.fork> [-o <file.forkc>] {}", OPTIMIZE_USAGE);
+    let Some((filename, parsed)) = single_file_args(args, &[], usage) else {
+        return;
+    };
+    let output = parsed.output.clone().unwrap_or_else(|| filename.trim_end_matches(".fork").to_string() + ".forkc");
+    match compile_source_file(&filename, &parsed) {
+        Ok((module, _)) => {
+            if let Err(e) = fs::write(&output, module.to_bytes()) {
+                eprintln!("Error writing file {}: {}", output, e);
+            }
+        }
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// `fork disasm file.fork|file.forkc`: print the bytecode listing.
+fn disasm_command(args: &[String]) {
+    let usage = &format!("fork disasm <file.fork|file.forkc> {}", OPTIMIZE_USAGE);
+    let Some((filename, parsed)) = single_file_args(args, &[], usage) else {
+        return;
+    };
+    let loaded = if filename.ends_with(".forkc") {
+        read_module(&filename).map(|module| (module, None))
     } else {
-        // REPL mode
-        let mut interpreter = Interpreter::new();
-        let stdin = io::stdin();
-        loop {
-            print!("fork> ");
-            io::stdout().flush().unwrap();
-            let mut input = String::new();
-            if stdin.read_line(&mut input).is_err() || input.trim().is_empty() {
-                break;
+        compile_source_file(&filename, &parsed).map(|(module, source)| (module, Some(source)))
+    };
+    match loaded {
+        Ok((module, source)) => print!("{}", disasm::disassemble(&module, source.as_deref())),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// `fork exec file.fork|file.forkc`: run compiled bytecode on the virtual machine.
+fn exec_command(args: &[String]) {
+    let usage = &format!("fork exec <file.fork|file.forkc> {}", OPTIMIZE_USAGE);
+    let Some((filename, parsed)) = single_file_args(args, &[], usage) else {
+        return;
+    };
+    let module = if filename.ends_with(".forkc") {
+        read_module(&filename)
+    } else {
+        compile_source_file(&filename, &parsed).map(|(module, _)| module)
+    };
+    match module {
+        Ok(module) => {
+            if let Err(e) = Vm::new().run(&module) {
+                eprintln!("{}", e);
             }
-            let trimmed = input.trim();
-            if trimmed == "exit" || trimmed == "quit" {
-                break;
+        }
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// `fork ir file.fork [--ssa] [-O0|-O1|-O2]`: print the three-address intermediate
+/// code, optionally after a round trip through SSA form.
+fn ir_command(args: &[String]) {
+    let usage = &format!("fork ir <file.fork> [--ssa] {}", OPTIMIZE_USAGE);
+    let Some((filename, parsed)) = single_file_args(args, &["--ssa"], usage) else {
+        return;
+    };
+    match lower_source_file(&filename, &parsed) {
+        Ok((mut ir, _)) => {
+            if parsed.has_flag("--ssa") {
+                for function in &mut ir.functions {
+                    let mut cfg = Cfg::from_function(function);
+                    ssa::into_ssa(&mut cfg);
+                    ssa::out_of_ssa(&mut cfg);
+                    *function = cfg.to_function();
+                }
             }
-            let tokens = lexer.tokenize(trimmed);
-            // No pipeline in REPL, just interpret
-            interpreter.interpret(&tokens);
+            print!("{}", ir)
         }
+        Err(e) => eprintln!("{}", e),
     }
+}
+
+/// `fork cfg file.fork [--dot] [--ssa] [-O0|-O1|-O2]`: print each function's
+/// control-flow graph as a block listing or as Graphviz DOT, optionally in SSA form.
+fn cfg_command(args: &[String]) {
+    let usage = &format!("fork cfg <file.fork> [--dot] [--ssa] {}", OPTIMIZE_USAGE);
+    let Some((filename, parsed)) = single_file_args(args, &["--dot", "--ssa"], usage) else {
+        return;
+    };
+    let ir = match lower_source_file(&filename, &parsed) {
+        Ok((ir, _)) => ir,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let cfgs: Vec<Cfg> = ir
+        .functions
+        .iter()
+        .map(|function| {
+            let mut cfg = Cfg::from_function(function);
+            if parsed.has_flag("--ssa") {
+                ssa::into_ssa(&mut cfg);
+            }
+            cfg
+        })
+        .collect();
+    if parsed.has_flag("--dot") {
+        let mut out = String::from("digraph fork {\n");
+        for (idx, cfg) in cfgs.iter().enumerate() {
+            cfg.to_dot(&mut out, idx, &Dominators::compute(cfg));
+        }
+        out.push_str("}\n");
+        print!("{}", out);
+    } else {
+        for (idx, cfg) in cfgs.iter().enumerate() {
+            if idx > 0 {
+                println!();
+            }
+            print!("{}", cfg);
+        }
+    }
+}
+
+/// Reads, parses, lowers and optimizes a `.fork` file, returning the IR and the source text.
+fn lower_source_file(filename: &str, args: &CompileArgs) -> Result<(IrProgram, String), String> {
+    if !filename.ends_with(".fork") {
+        return Err("Error: Only .fork files are supported.".to_string());
+    }
+    let source = fs::read_to_string(filename).map_err(|e| format!("Error reading file {}: {}", filename, e))?;
+    let tokens = Lexer::new().tokenize_with_spans(&source);
+    let program = Parser::new(tokens).parse().map_err(|e| e.to_string())?;
+    SemanticAnalyzer::new().analyze(&program).map_err(|errors| semantic_errors(&errors))?;
+    let ir = ICG::new().generate(&program).map_err(|e| e.to_string())?;
+    Ok((args.optimizer().optimize(&ir), source))
+}
+
+/// Reads and decodes a `.forkc` file.
+fn read_module(filename: &str) -> Result<Module, String> {
+    let bytes = fs::read(filename).map_err(|e| format!("Error reading file {}: {}", filename, e))?;
+    Module::from_bytes(&bytes).map_err(|e| e.to_string())
+}
 
+/// Reads and compiles a `.fork` file, returning the module and the source text.
+fn compile_source_file(filename: &str, args: &CompileArgs) -> Result<(Module, String), String> {
+    let (ir, source) = lower_source_file(filename, args)?;
+    Ok((CodeGenerator::new().generate(&ir), source))
 }
@@ -0,0 +1,194 @@
+//! Rust functions callable from scripts: conversions between Rust types and
+//! values, and the closures `Engine::register_fn` accepts.
+use std::collections::{BTreeMap, HashMap};
+use crate::engine::ForkError;
+use crate::value::Value;
+
+/// A Rust type an argument can be converted to.
+pub trait FromValue: Sized {
+    /// The value as this type, or what was expected instead.
+    fn from_value(value: &Value) -> Result<Self, String>;
+}
+
+fn expected(what: &str, value: &Value) -> String {
+    format!("expected {}, found {}", what, value.type_name())
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            _ => Err(expected("a number", value)),
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() <= i64::MAX as f64 => Ok(*n as i64),
+            Value::Number(n) => Err(format!("expected an integer, found {}", n)),
+            _ => Err(expected("an integer", value)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Str(s) => Ok(s.clone()),
+            _ => Err(expected("a string", value)),
+        }
+    }
+}
+
+/// Any value converts by its truthiness.
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        Ok(value.is_truthy())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let Value::List(items) = value else {
+            return Err(expected("a list", value));
+        };
+        items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| T::from_value(item).map_err(|e| format!("item {}: {}", idx, e)))
+            .collect()
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let Value::Dict(entries) = value else {
+            return Err(expected("a dict", value));
+        };
+        entries
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), T::from_value(value).map_err(|e| format!("key {:?}: {}", key, e))?)))
+            .collect()
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+/// Like comparisons, `1` or `0`.
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Number(if b { 1.0 } else { 0.0 })
+    }
+}
+
+/// Like a function without `return`, `0`.
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Number(0.0)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<HashMap<String, T>> for Value {
+    fn from(entries: HashMap<String, T>) -> Self {
+        Value::Dict(entries.into_iter().map(|(key, value)| (key, value.into())).collect())
+    }
+}
+
+impl<T: Into<Value>> From<BTreeMap<String, T>> for Value {
+    fn from(entries: BTreeMap<String, T>) -> Self {
+        Value::Dict(entries.into_iter().map(|(key, value)| (key, value.into())).collect())
+    }
+}
+
+/// What a native function returns: a value, or a value or an error.
+pub trait IntoResult {
+    fn into_result(self) -> Result<Value, ForkError>;
+}
+
+impl<T: Into<Value>> IntoResult for T {
+    fn into_result(self) -> Result<Value, ForkError> {
+        Ok(self.into())
+    }
+}
+
+impl<T: Into<Value>> IntoResult for Result<T, ForkError> {
+    fn into_result(self) -> Result<Value, ForkError> {
+        self.map(Into::into)
+    }
+}
+
+/// A closure whose parameters and result convert from and to values, such
+/// as `|currency: String| -> Result<f64, ForkError>`. `Args` is the tuple of
+/// its parameter types.
+pub trait NativeFunction<Args> {
+    /// Number of parameters.
+    const ARITY: usize;
+
+    /// Converts the arguments and calls the closure. A conversion error is a
+    /// `TypeError` naming the function `name`.
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, ForkError>;
+}
+
+macro_rules! native_function {
+    ($arity:expr; $($arg:ident $idx:tt),*) => {
+        impl<F, R, $($arg: FromValue),*> NativeFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: IntoResult,
+        {
+            const ARITY: usize = $arity;
+
+            #[allow(unused_variables)]
+            fn call(&self, name: &str, args: &[Value]) -> Result<Value, ForkError> {
+                self($(
+                    $arg::from_value(&args[$idx]).map_err(|e| ForkError::runtime(format!("TypeError: {}() argument {}: {}", name, $idx + 1, e)))?
+                ),*)
+                .into_result()
+            }
+        }
+    };
+}
+
+native_function!(0;);
+native_function!(1; A 0);
+native_function!(2; A 0, B 1);
+native_function!(3; A 0, B 1, C 2);
+native_function!(4; A 0, B 1, C 2, D 3);
+native_function!(5; A 0, B 1, C 2, D 3, E 4);
+native_function!(6; A 0, B 1, C 2, D 3, E 4, G 5);
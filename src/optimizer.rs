@@ -1,19 +1,448 @@
-use crate::token::Token;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use crate::ast::{BinOp, UnaryOp};
+use crate::cfg::{Cfg, Terminator};
+use crate::ir::{Instr, InstrKind, IrProgram, Operand, Var};
+use crate::{calls, loops, ssa};
 
-pub struct Optimizer;
+/// How aggressively to optimize, as selected by `-O0`, `-O1` or `-O2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// No optimization.
+    O0,
+    /// Constant folding and propagation, copy propagation, branch simplification
+    /// and tail-call elimination.
+    O1,
+    /// Everything in `O1`, plus inlining, loop optimizations, dead code elimination
+    /// and block merging.
+    O2,
+}
+
+impl OptLevel {
+    /// Parses a `-O0`, `-O1` or `-O2` command-line flag.
+    pub fn from_flag(flag: &str) -> Option<OptLevel> {
+        match flag {
+            "-O0" => Some(OptLevel::O0),
+            "-O1" => Some(OptLevel::O1),
+            "-O2" => Some(OptLevel::O2),
+            _ => None,
+        }
+    }
+}
+
+/// Which passes to dump the IR after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrintAfter {
+    Never,
+    All,
+    Passes(Vec<String>),
+}
+
+/// A named transformation over a function in SSA form. `run` returns true if
+/// it changed anything.
+struct Pass {
+    name: &'static str,
+    level: OptLevel,
+    run: fn(&mut Cfg) -> bool,
+}
+
+/// Passes in the order they run. The whole list repeats until nothing changes.
+const PASSES: [Pass; 10] = [
+    Pass { name: "fold", level: OptLevel::O1, run: fold_constants },
+    Pass { name: "const-prop", level: OptLevel::O1, run: propagate_constants },
+    Pass { name: "copy-prop", level: OptLevel::O1, run: propagate_copies },
+    Pass { name: "branch-fold", level: OptLevel::O1, run: fold_branches },
+    Pass { name: "unreachable", level: OptLevel::O1, run: Cfg::remove_unreachable },
+    Pass { name: "licm", level: OptLevel::O2, run: loops::hoist_invariants },
+    Pass { name: "unroll", level: OptLevel::O2, run: loops::unroll_loops },
+    Pass { name: "strength-reduce", level: OptLevel::O2, run: loops::reduce_strength },
+    Pass { name: "dse", level: OptLevel::O2, run: eliminate_dead_code },
+    Pass { name: "simplify-cfg", level: OptLevel::O2, run: merge_blocks },
+];
+
+/// A transformation over the whole program's linear IR, run once before the
+/// functions are put in SSA form.
+struct ProgramPass {
+    name: &'static str,
+    level: OptLevel,
+    run: fn(&mut IrProgram) -> bool,
+}
+
+/// Whole-program passes in the order they run.
+const PROGRAM_PASSES: [ProgramPass; 2] = [
+    ProgramPass { name: "inline", level: OptLevel::O2, run: calls::inline_calls },
+    ProgramPass { name: "tail-call", level: OptLevel::O1, run: calls::eliminate_tail_calls },
+];
+
+/// Upper bound on pipeline repetitions, in case passes keep undoing each other.
+const MAX_ROUNDS: usize = 16;
+
+/// Runs optimization passes over the intermediate representation.
+pub struct Optimizer {
+    level: OptLevel,
+    print_after: PrintAfter,
+    disabled: Vec<String>,
+}
 
 impl Optimizer {
-    pub fn new() -> Self {
-        Optimizer
+    pub fn new(level: OptLevel) -> Self {
+        Optimizer { level, print_after: PrintAfter::Never, disabled: Vec::new() }
+    }
+
+    /// Skips the named passes, so their effect can be compared against a run without them.
+    pub fn disable(mut self, passes: Vec<String>) -> Self {
+        self.disabled = passes;
+        self
+    }
+
+    /// Dumps the function to stderr after each selected pass that changed it.
+    pub fn print_after(mut self, print_after: PrintAfter) -> Self {
+        self.print_after = print_after;
+        self
+    }
+
+    /// Returns true if `name` is one of the optimizer's passes.
+    pub fn is_pass(name: &str) -> bool {
+        PASSES.iter().any(|pass| pass.name == name) || PROGRAM_PASSES.iter().any(|pass| pass.name == name)
+    }
+
+    pub fn optimize(&self, program: &IrProgram) -> IrProgram {
+        if self.level == OptLevel::O0 {
+            return program.clone();
+        }
+        let mut optimized = program.clone();
+        for pass in PROGRAM_PASSES.iter().filter(|pass| self.enabled(pass.name, pass.level)) {
+            if (pass.run)(&mut optimized) {
+                self.dump(pass.name, &optimized);
+            }
+        }
+        for function in &mut optimized.functions {
+            let mut cfg = Cfg::from_function(function);
+            ssa::into_ssa(&mut cfg);
+            self.dump("ssa", &cfg);
+            for _ in 0..MAX_ROUNDS {
+                let mut changed = false;
+                for pass in PASSES.iter().filter(|pass| self.enabled(pass.name, pass.level)) {
+                    if (pass.run)(&mut cfg) {
+                        changed = true;
+                        self.dump(pass.name, &cfg);
+                    }
+                }
+                if !changed {
+                    break;
+                }
+            }
+            ssa::out_of_ssa(&mut cfg);
+            self.dump("out-of-ssa", &cfg);
+            *function = cfg.to_function();
+        }
+        optimized
+    }
+
+    fn enabled(&self, name: &str, level: OptLevel) -> bool {
+        level <= self.level && !self.disabled.iter().any(|disabled| disabled == name)
+    }
+
+    fn dump(&self, pass: &str, ir: &dyn fmt::Display) {
+        let selected = match &self.print_after {
+            PrintAfter::Never => false,
+            PrintAfter::All => true,
+            PrintAfter::Passes(passes) => passes.iter().any(|p| p == pass),
+        };
+        if selected {
+            eprintln!("*** IR after {} ***\n{}", pass, ir);
+        }
+    }
+}
+
+/// Evaluates a binary operator on constant operands, if both are constants of
+/// types the operator is defined for.
+pub fn fold_binary(op: BinOp, lhs: &Operand, rhs: &Operand) -> Option<Operand> {
+    let truth = |b: bool| Operand::Number(if b { 1.0 } else { 0.0 });
+    match (lhs, rhs) {
+        (Operand::Number(a), Operand::Number(b)) => Some(match op {
+            BinOp::Add => Operand::Number(a + b),
+            BinOp::Sub => Operand::Number(a - b),
+            BinOp::Mul => Operand::Number(a * b),
+            BinOp::Div => Operand::Number(a / b),
+            BinOp::Lt => truth(a < b),
+            BinOp::Gt => truth(a > b),
+            BinOp::Le => truth(a <= b),
+            BinOp::Ge => truth(a >= b),
+            BinOp::Eq => truth(a == b),
+            BinOp::Ne => truth(a != b),
+        }),
+        (Operand::Str(a), Operand::Str(b)) => match op {
+            BinOp::Eq => Some(truth(a == b)),
+            BinOp::Ne => Some(truth(a != b)),
+            _ => None,
+        },
+        _ => None,
     }
+}
 
-    pub fn optimize(&self, tokens: &[Token]) -> Vec<Token> {
-        // For demonstration, just print a stub for real optimization
+/// Replaces arithmetic and comparisons on constants with their result. Blocks
+/// are visited in reverse postorder, so results folded earlier feed later
+/// folds in the same sweep.
+fn fold_constants(cfg: &mut Cfg) -> bool {
+    let mut changed = false;
+    let mut known: HashMap<Var, Operand> = HashMap::new();
+    for id in cfg.reverse_postorder() {
+        for instr in &mut cfg.blocks[id].instrs {
+            let resolve = |operand: &Operand| match operand {
+                Operand::Var(var) => known.get(var).cloned().unwrap_or_else(|| operand.clone()),
+                _ => operand.clone(),
+            };
+            let folded = match &instr.kind {
+                InstrKind::Binary { dest, op, lhs, rhs } => {
+                    fold_binary(*op, &resolve(lhs), &resolve(rhs)).map(|src| (dest.clone(), src))
+                }
+                InstrKind::Unary { dest, op: UnaryOp::Neg, src } => match resolve(src) {
+                    Operand::Number(n) => Some((dest.clone(), Operand::Number(-n))),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some((dest, src)) = folded {
+                instr.kind = InstrKind::Copy { dest, src };
+                changed = true;
+            }
+            if let InstrKind::Copy { dest, src: src @ (Operand::Number(_) | Operand::Str(_)) } = &instr.kind {
+                known.insert(dest.clone(), src.clone());
+            }
+        }
+    }
+    changed
+}
 
-        for (_i, _token) in tokens.iter().enumerate() {
+/// Substitutes constants for variables that are assigned a constant.
+fn propagate_constants(cfg: &mut Cfg) -> bool {
+    let constants: HashMap<Var, Operand> = cfg
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instrs)
+        .filter_map(|instr| match &instr.kind {
+            InstrKind::Copy { dest, src: src @ (Operand::Number(_) | Operand::Str(_)) } => Some((dest.clone(), src.clone())),
+            _ => None,
+        })
+        .collect();
+    let mut changed = false;
+    cfg.for_each_use_mut(|operand| {
+        if let Operand::Var(var) = operand
+            && let Some(constant) = constants.get(var)
+        {
+            *operand = constant.clone();
+            changed = true;
+        }
+    });
+    changed
+}
 
+/// Replaces phis whose incoming values are all the same with a copy, then
+/// substitutes the source of every copy for its destination.
+fn propagate_copies(cfg: &mut Cfg) -> bool {
+    let mut changed = false;
+    for block in &mut cfg.blocks {
+        let mut copies = Vec::new();
+        block.phis.retain(|phi| {
+            let mut values = phi.args.iter().map(|(_, v)| v).filter(|v| **v != Operand::Var(phi.dest.clone()));
+            let first = values.next();
+            match first {
+                Some(first) if values.all(|v| v == first) => {
+                    copies.push(Instr {
+                        kind: InstrKind::Copy { dest: phi.dest.clone(), src: first.clone() },
+                        line: block.instrs.first().map_or(block.terminator_line, |i| i.line),
+                    });
+                    false
+                }
+                _ => true,
+            }
+        });
+        if !copies.is_empty() {
+            changed = true;
+            block.instrs.splice(0..0, copies);
         }
+    }
+    let sources: HashMap<Var, Var> = cfg
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instrs)
+        .filter_map(|instr| match &instr.kind {
+            InstrKind::Copy { dest, src: Operand::Var(src) } if dest != src => Some((dest.clone(), src.clone())),
+            _ => None,
+        })
+        .collect();
+    let resolve = |var: &Var| {
+        let mut current = var;
+        // Bounded walk in case a chain of copies loops back on itself
+        for _ in 0..sources.len() {
+            match sources.get(current) {
+                Some(src) => current = src,
+                None => break,
+            }
+        }
+        current.clone()
+    };
+    cfg.for_each_use_mut(|operand| {
+        if let Operand::Var(var) = operand
+            && sources.contains_key(var)
+        {
+            *var = resolve(var);
+            changed = true;
+        }
+    });
+    changed
+}
+
+/// Turns branches on constant conditions into jumps.
+fn fold_branches(cfg: &mut Cfg) -> bool {
+    let mut changed = false;
+    for id in 0..cfg.blocks.len() {
+        let Terminator::Branch { cond, then_block, else_block } = &cfg.blocks[id].terminator else {
+            continue;
+        };
+        let (then_block, else_block) = (*then_block, *else_block);
+        let taken = match cond {
+            _ if then_block == else_block => then_block,
+            Operand::Number(n) => if *n != 0.0 { then_block } else { else_block },
+            Operand::Str(s) => if !s.is_empty() { then_block } else { else_block },
+            Operand::Var(_) => continue,
+        };
+        let dropped = if taken == then_block { else_block } else { then_block };
+        cfg.blocks[id].terminator = Terminator::Jump(taken);
+        if dropped != taken {
+            for phi in &mut cfg.blocks[dropped].phis {
+                phi.args.retain(|&(pred, _)| pred != id);
+            }
+        }
+        changed = true;
+    }
+    if changed {
+        cfg.recompute_preds();
+    }
+    changed
+}
 
-        tokens.to_vec()
+/// Removes computations whose result never reaches a call, a branch or a return.
+fn eliminate_dead_code(cfg: &mut Cfg) -> bool {
+    let mut inputs: HashMap<Var, Vec<Var>> = HashMap::new();
+    let mut live = HashSet::new();
+    let mut worklist = Vec::new();
+    let vars = |operands: Vec<&Operand>| -> Vec<Var> {
+        operands
+            .into_iter()
+            .filter_map(|operand| match operand {
+                Operand::Var(var) => Some(var.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    for block in &cfg.blocks {
+        for phi in &block.phis {
+            inputs.insert(phi.dest.clone(), vars(phi.args.iter().map(|(_, v)| v).collect()));
+        }
+        for instr in &block.instrs {
+            match &instr.kind {
+                InstrKind::Call { .. } => worklist.extend(vars(instr.kind.operands())),
+                kind => {
+                    if let Some(dest) = kind.dest() {
+                        inputs.insert(dest.clone(), vars(kind.operands()));
+                    }
+                }
+            }
+        }
+        if let Some(operand) = block.terminator.operand() {
+            worklist.extend(vars(vec![operand]));
+        }
+    }
+    while let Some(var) = worklist.pop() {
+        if live.insert(var.clone())
+            && let Some(vars) = inputs.get(&var)
+        {
+            worklist.extend(vars.iter().cloned());
+        }
+    }
+    let mut changed = false;
+    for block in &mut cfg.blocks {
+        let before = block.phis.len() + block.instrs.len();
+        block.phis.retain(|phi| live.contains(&phi.dest));
+        block.instrs.retain(|instr| match &instr.kind {
+            InstrKind::Call { .. } => true,
+            kind => kind.dest().is_none_or(|dest| live.contains(dest)),
+        });
+        changed |= block.phis.len() + block.instrs.len() != before;
+        for instr in &mut block.instrs {
+            if let InstrKind::Call { dest: dest @ Some(_), .. } = &mut instr.kind
+                && !live.contains(dest.as_ref().unwrap())
+            {
+                *dest = None;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// Merges a block into its predecessor when that predecessor is its only one
+/// and jumps straight to it, and skips over empty blocks that only jump.
+fn merge_blocks(cfg: &mut Cfg) -> bool {
+    let mut changed = false;
+    for id in 1..cfg.blocks.len() {
+        let block = &cfg.blocks[id];
+        if let Terminator::Jump(target) = block.terminator
+            && block.phis.is_empty()
+            && block.instrs.is_empty()
+            && target != id
+            && cfg.blocks[target].phis.is_empty()
+        {
+            for pred in block.preds.clone() {
+                for succ in cfg.blocks[pred].terminator.successors_mut() {
+                    if *succ == id {
+                        *succ = target;
+                    }
+                }
+            }
+            cfg.recompute_preds();
+            changed = true;
+        }
+    }
+    let mut id = 0;
+    while id < cfg.blocks.len() {
+        let Terminator::Jump(next) = cfg.blocks[id].terminator else {
+            id += 1;
+            continue;
+        };
+        if next == id || next == 0 || cfg.blocks[next].preds != [id] {
+            id += 1;
+            continue;
+        }
+        let absorbed = std::mem::take(&mut cfg.blocks[next].instrs);
+        let phis = std::mem::take(&mut cfg.blocks[next].phis);
+        let line = cfg.blocks[next].terminator_line;
+        let terminator = std::mem::replace(&mut cfg.blocks[next].terminator, Terminator::Return(None));
+        let block = &mut cfg.blocks[id];
+        for phi in phis {
+            let src = phi.args.into_iter().next().map_or(Operand::Var(phi.dest.base().clone()), |(_, v)| v);
+            block.instrs.push(Instr { kind: InstrKind::Copy { dest: phi.dest, src }, line });
+        }
+        block.instrs.extend(absorbed);
+        block.terminator = terminator;
+        block.terminator_line = line;
+        // Successors of the absorbed block now see this block as their predecessor
+        for succ in cfg.successors(id) {
+            for phi in &mut cfg.blocks[succ].phis {
+                for (pred, _) in &mut phi.args {
+                    if *pred == next {
+                        *pred = id;
+                    }
+                }
+            }
+        }
+        cfg.recompute_preds();
+        changed = true;
+    }
+    if changed {
+        cfg.remove_unreachable();
     }
+    changed
 }
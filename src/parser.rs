@@ -1,33 +1,478 @@
-use crate::token::Token;
+use std::fmt;
+use crate::ast::{BinOp, Expr, ExprKind, Param, Program, Stmt, StmtKind, TypeName, UnaryOp};
+use crate::token::{Span, Token};
+
+/// A syntax error found while parsing, with the location of the offending token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SyntaxError: {} (line {}, column {})", self.message, self.span.line, self.span.col)
+    }
+}
 
 /// The Parser is responsible for converting a stream of tokens into an AST or intermediate structure.
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<(Token, Span)>,
     pos: usize,
+    /// Number of enclosing blocks; functions may only be defined at depth zero.
+    depth: usize,
+    in_function: bool,
 }
 
 impl Parser {
-    /// Creates a new Parser from a vector of tokens.
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+    /// Creates a new Parser from spanned tokens. Whitespace and comment tokens are dropped.
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        let tokens = tokens
+            .into_iter()
+            .filter(|(token, _)| !matches!(token, Token::Whitespace | Token::Comment(_)))
+            .collect();
+        Self { tokens, pos: 0, depth: 0, in_function: false }
+    }
+
+    /// Parses the token stream into a program.
+    pub fn parse(&mut self) -> Result<Program, ParseError> {
+        let mut stmts = Vec::new();
+        while self.peek().is_some() {
+            stmts.push(self.statement()?);
+        }
+        Ok(Program { stmts })
+    }
+
+    /// Parses input that is a single expression, such as one typed at a
+    /// debugger prompt.
+    pub fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.expression()?;
+        if self.peek().is_some() {
+            return Err(self.unexpected("end of expression"));
+        }
+        Ok(expr)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.span();
+        match self.peek() {
+            Some(Token::Keyword(k)) if k == "print" => {
+                self.next_token();
+                self.expect_symbol('(', "after 'print'")?;
+                let args = self.arguments("print arguments")?;
+                self.expect_symbol(';', "after print statement")?;
+                Ok(Stmt { kind: StmtKind::Print(args), span })
+            }
+            Some(Token::Keyword(k)) if k == "if" => self.if_statement(),
+            Some(Token::Keyword(k)) if k == "while" => {
+                self.next_token();
+                let cond = self.condition("while")?;
+                let body = self.block("while loop")?;
+                Ok(Stmt { kind: StmtKind::While { cond, body }, span })
+            }
+            Some(Token::Keyword(k)) if k == "for" => self.for_statement(),
+            Some(Token::Keyword(k)) if k == "def" => self.def_statement(),
+            Some(Token::Keyword(k)) if k == "let" => {
+                let stmt = self.let_statement()?;
+                self.expect_symbol(';', "after let statement")?;
+                Ok(stmt)
+            }
+            Some(Token::Keyword(k)) if k == "return" => {
+                if !self.in_function {
+                    return Err(self.error("'return' outside function".to_string()));
+                }
+                self.next_token();
+                let value = if self.check_symbol(';') { None } else { Some(self.expression()?) };
+                self.expect_symbol(';', "after return statement")?;
+                Ok(Stmt { kind: StmtKind::Return(value), span })
+            }
+            _ => {
+                let stmt = self.simple_statement()?;
+                self.expect_symbol(';', "after statement")?;
+                Ok(stmt)
+            }
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.span();
+        self.next_token();
+        let cond = self.condition("if")?;
+        let then_block = self.block("if")?;
+        let else_block = if matches!(self.peek(), Some(Token::Keyword(k)) if k == "else") {
+            self.next_token();
+            if matches!(self.peek(), Some(Token::Keyword(k)) if k == "if") {
+                Some(vec![self.if_statement()?])
+            } else {
+                Some(self.block("else")?)
+            }
+        } else {
+            None
+        };
+        Ok(Stmt { kind: StmtKind::If { cond, then_block, else_block }, span })
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.span();
+        self.next_token();
+        self.expect_symbol('(', "after 'for'")?;
+        let init = if self.check_symbol(';') {
+            None
+        } else if matches!(self.peek(), Some(Token::Keyword(k)) if k == "let") {
+            Some(Box::new(self.let_statement()?))
+        } else if let Some(Token::Identifier(ty)) = self.peek()
+            && let Some(ty) = TypeName::from_name(ty)
+            && matches!(self.peek_at(1), Some(Token::Identifier(_)))
+        {
+            // `int i = 0` declares `i` like `let i: int = 0`
+            let span = self.span();
+            self.next_token();
+            let name = self.identifier("variable name")?;
+            self.expect_symbol('=', "after variable name")?;
+            let value = self.expression()?;
+            Some(Box::new(Stmt { kind: StmtKind::Let { name, ty: Some(ty), value }, span }))
+        } else {
+            Some(Box::new(self.simple_statement()?))
+        };
+        self.expect_symbol(';', "after for loop initializer")?;
+        let cond = if self.check_symbol(';') { None } else { Some(self.expression()?) };
+        self.expect_symbol(';', "after for loop condition")?;
+        let update = if self.check_symbol(')') { None } else { Some(Box::new(self.simple_statement()?)) };
+        self.expect_symbol(')', "after for loop header")?;
+        let body = self.block("for loop")?;
+        Ok(Stmt { kind: StmtKind::For { init, cond, update, body }, span })
+    }
+
+    fn def_statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.span();
+        if self.depth > 0 {
+            return Err(self.error("functions can only be defined at the top level".to_string()));
+        }
+        self.next_token();
+        let name = self.identifier("function name after 'def'")?;
+        self.expect_symbol('(', "after function name")?;
+        let mut params = Vec::new();
+        if !self.check_symbol(')') {
+            loop {
+                let span = self.span();
+                let param = self.identifier("parameter name")?;
+                if params.iter().any(|p: &Param| p.name == param) {
+                    return Err(ParseError { message: format!("duplicate parameter '{}'", param), span: self.tokens[self.pos - 1].1 });
+                }
+                let ty = if self.eat_symbol(':') { Some(self.type_name()?) } else { None };
+                params.push(Param { name: param, ty, span });
+                if !self.eat_symbol(',') {
+                    break;
+                }
+            }
+        }
+        self.expect_symbol(')', "after parameters")?;
+        let ret = if self.check_pair('-', '>') {
+            self.pos += 2;
+            Some(self.type_name()?)
+        } else {
+            None
+        };
+        self.in_function = true;
+        let body = self.block("function");
+        self.in_function = false;
+        Ok(Stmt { kind: StmtKind::Def { name, params, ret, body: body? }, span })
+    }
+
+    /// Parses `let name = value` or `let name: type = value` without its trailing semicolon.
+    fn let_statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.span();
+        self.next_token();
+        let name = self.identifier("variable name after 'let'")?;
+        let ty = if self.eat_symbol(':') { Some(self.type_name()?) } else { None };
+        self.expect_symbol('=', "after variable name")?;
+        let value = self.expression()?;
+        Ok(Stmt { kind: StmtKind::Let { name, ty, value }, span })
+    }
+
+    fn type_name(&mut self) -> Result<TypeName, ParseError> {
+        let ty = match self.peek() {
+            Some(Token::Identifier(name)) => TypeName::from_name(name),
+            _ => None,
+        };
+        match ty {
+            Some(ty) => {
+                self.next_token();
+                Ok(ty)
+            }
+            None => Err(self.unexpected("a type ('int', 'float' or 'str')")),
+        }
+    }
+
+    /// Parses an assignment, increment or call without its trailing semicolon.
+    fn simple_statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.span();
+        let name = match self.peek() {
+            Some(Token::Identifier(name)) => name.clone(),
+            _ => return Err(self.unexpected("a statement")),
+        };
+        if matches!(self.peek_at(1), Some(Token::Symbol('('))) {
+            return Ok(Stmt { kind: StmtKind::Expr(self.expression()?), span });
+        }
+        self.next_token();
+        if self.check_pair('+', '+') || self.check_pair('-', '-') {
+            let amount = if self.check_symbol('+') { 1.0 } else { -1.0 };
+            self.pos += 2;
+            return Ok(Stmt { kind: StmtKind::Increment { name, amount }, span });
+        }
+        if self.check_symbol('=') && !self.check_pair('=', '=') {
+            self.next_token();
+            let value = self.expression()?;
+            return Ok(Stmt { kind: StmtKind::Assign { name, value }, span });
+        }
+        Err(self.unexpected("'=', '++' or '--'"))
+    }
+
+    fn condition(&mut self, construct: &str) -> Result<Expr, ParseError> {
+        self.expect_symbol('(', &format!("after '{}'", construct))?;
+        let cond = self.expression()?;
+        self.expect_symbol(')', &format!("after {} condition", construct))?;
+        Ok(cond)
+    }
+
+    fn block(&mut self, construct: &str) -> Result<Vec<Stmt>, ParseError> {
+        self.expect_symbol('{', &format!("to open {} block", construct))?;
+        self.depth += 1;
+        let stmts = self.block_body(construct);
+        self.depth -= 1;
+        stmts
+    }
+
+    fn block_body(&mut self, construct: &str) -> Result<Vec<Stmt>, ParseError> {
+        let mut stmts = Vec::new();
+        while !self.check_symbol('}') {
+            if self.peek().is_none() {
+                return Err(self.unexpected(&format!("'}}' to close {} block", construct)));
+            }
+            stmts.push(self.statement()?);
+        }
+        self.next_token();
+        Ok(stmts)
+    }
+
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        self.equality()
     }
 
-    /// Parses the token stream.
-    pub fn parse(&mut self) {
-        // For demonstration, this is a stub.
-        while let Some(_token) = self.next_token() {
-            // Parsing logic would go here
+    fn equality(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.comparison()?;
+        loop {
+            let op = if self.check_pair('=', '=') {
+                BinOp::Eq
+            } else if self.check_pair('!', '=') {
+                BinOp::Ne
+            } else {
+                break;
+            };
+            self.pos += 2;
+            let rhs = self.comparison()?;
+            lhs = binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.term()?;
+        loop {
+            let (op, width) = if self.check_pair('<', '=') {
+                (BinOp::Le, 2)
+            } else if self.check_pair('>', '=') {
+                (BinOp::Ge, 2)
+            } else if self.check_symbol('<') {
+                (BinOp::Lt, 1)
+            } else if self.check_symbol('>') {
+                (BinOp::Gt, 1)
+            } else {
+                break;
+            };
+            self.pos += width;
+            let rhs = self.term()?;
+            lhs = binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.factor()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol('+')) => BinOp::Add,
+                Some(Token::Symbol('-')) => BinOp::Sub,
+                _ => break,
+            };
+            self.next_token();
+            let rhs = self.factor()?;
+            lhs = binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol('*')) => BinOp::Mul,
+                Some(Token::Symbol('/')) => BinOp::Div,
+                _ => break,
+            };
+            self.next_token();
+            let rhs = self.unary()?;
+            lhs = binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.check_symbol('-') {
+            let span = self.span();
+            self.next_token();
+            let expr = self.unary()?;
+            return Ok(Expr { kind: ExprKind::Unary { op: UnaryOp::Neg, expr: Box::new(expr) }, span });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let span = self.span();
+        let kind = match self.peek() {
+            Some(Token::Number(n)) => match n.parse::<f64>() {
+                Ok(value) => ExprKind::Number(value),
+                Err(_) => return Err(self.error(format!("invalid number literal '{}'", n))),
+            },
+            Some(Token::StringLiteral(s)) => ExprKind::Str(s.clone()),
+            Some(Token::Identifier(name)) if matches!(self.peek_at(1), Some(Token::Symbol('('))) => {
+                let name = name.clone();
+                self.pos += 2;
+                let args = self.arguments("call arguments")?;
+                return Ok(Expr { kind: ExprKind::Call { name, args }, span });
+            }
+            Some(Token::Identifier(name)) => ExprKind::Var(name.clone()),
+            Some(Token::Symbol('(')) => {
+                self.next_token();
+                let expr = self.expression()?;
+                self.expect_symbol(')', "to close parenthesized expression")?;
+                return Ok(expr);
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        self.next_token();
+        Ok(Expr { kind, span })
+    }
+
+    /// Parses comma-separated expressions up to and including the closing `)`.
+    fn arguments(&mut self, context: &str) -> Result<Vec<Expr>, ParseError> {
+        let mut args = Vec::new();
+        if !self.check_symbol(')') {
+            loop {
+                args.push(self.expression()?);
+                if !self.eat_symbol(',') {
+                    break;
+                }
+            }
+        }
+        self.expect_symbol(')', &format!("after {}", context))?;
+        Ok(args)
+    }
+
+    fn identifier(&mut self, expected: &str) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Identifier(name)) => {
+                let name = name.clone();
+                self.next_token();
+                Ok(name)
+            }
+            _ => Err(self.unexpected(expected)),
         }
     }
 
     /// Returns the next token, if any.
     fn next_token(&mut self) -> Option<&Token> {
         if self.pos < self.tokens.len() {
-            let tok = &self.tokens[self.pos];
+            let tok = &self.tokens[self.pos].0;
             self.pos += 1;
             Some(tok)
         } else {
             None
         }
     }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|(token, _)| token)
+    }
+
+    /// Span of the next token, or an empty span just past the input at end of file.
+    fn span(&self) -> Span {
+        match self.tokens.get(self.pos) {
+            Some((_, span)) => *span,
+            None => self.tokens.last().map_or(Span { line: 1, col: 1, ..Span::default() }, |(_, last)| Span {
+                start: last.end,
+                end: last.end,
+                line: last.line,
+                col: last.col + (last.end - last.start),
+            }),
+        }
+    }
+
+    fn check_symbol(&self, symbol: char) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    /// True if the next two tokens are the given symbols with nothing between them,
+    /// which is how two-character operators such as `==` arrive from the lexer.
+    fn check_pair(&self, first: char, second: char) -> bool {
+        match (self.tokens.get(self.pos), self.tokens.get(self.pos + 1)) {
+            (Some((Token::Symbol(a), span_a)), Some((Token::Symbol(b), span_b))) => {
+                *a == first && *b == second && span_a.end == span_b.start
+            }
+            _ => false,
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: char) -> bool {
+        if self.check_symbol(symbol) {
+            self.next_token();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: char, context: &str) -> Result<(), ParseError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}' {}", symbol, context)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        let found = match self.peek() {
+            Some(Token::Keyword(k)) => format!("keyword '{}'", k),
+            Some(Token::Identifier(id)) => format!("identifier '{}'", id),
+            Some(Token::Number(n)) => format!("number '{}'", n),
+            Some(Token::StringLiteral(s)) => format!("string literal \"{}\"", s),
+            Some(Token::Symbol(s)) => format!("symbol '{}'", s),
+            Some(Token::Unknown(u)) => format!("unknown token '{}'", u),
+            Some(Token::Whitespace | Token::Comment(_)) | None => "end of input".to_string(),
+        };
+        self.error(format!("expected {}, found {}", expected, found))
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError { message, span: self.span() }
+    }
+}
+
+fn binary(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    let span = lhs.span;
+    Expr { kind: ExprKind::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) }, span }
 }
@@ -0,0 +1,198 @@
+//! `fork profile`: execution counts and times per line and per function,
+//! measured at the interpreter's statement and call hooks.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::hash::Hash;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use crate::ast::Stmt;
+use crate::interpreter::debug::{Debugger, Event};
+use crate::interpreter::Interpreter;
+use crate::value::RuntimeError;
+
+/// Name of the top level in reports and stacks.
+const MAIN: &str = "<main>";
+
+/// Counts and times of a line or a function. Inclusive time counts what runs
+/// while it is active, including calls; exclusive time leaves those out.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub count: usize,
+    pub inclusive: Duration,
+    pub exclusive: Duration,
+}
+
+/// A running call, with the statements open in it, innermost last.
+struct Call {
+    function: String,
+    lines: Vec<usize>,
+    /// Length of the folded stack of the caller.
+    caller_stack: usize,
+}
+
+/// How many times a line or function is active at once, and since when.
+type Active<K> = HashMap<K, (usize, Instant)>;
+
+pub struct Profiler {
+    pub lines: HashMap<usize, Stats>,
+    pub functions: HashMap<String, Stats>,
+    /// Exclusive time by call stack, written `<main>;f;g`.
+    pub folded: HashMap<String, Duration>,
+    calls: Vec<Call>,
+    stack: String,
+    active_lines: Active<usize>,
+    active_functions: Active<String>,
+    /// Time of the last event; what ran since is charged to the next one.
+    last: Instant,
+}
+
+impl Profiler {
+    /// Starts measuring the top level of a program.
+    pub fn new() -> Self {
+        let now = Instant::now();
+        let mut profiler = Profiler {
+            lines: HashMap::new(),
+            functions: HashMap::new(),
+            folded: HashMap::new(),
+            calls: vec![Call { function: MAIN.to_string(), lines: Vec::new(), caller_stack: 0 }],
+            stack: MAIN.to_string(),
+            active_lines: HashMap::new(),
+            active_functions: HashMap::new(),
+            last: now,
+        };
+        profiler.functions.entry(MAIN.to_string()).or_default().count = 1;
+        activate(&mut profiler.active_functions, MAIN.to_string(), now);
+        profiler
+    }
+
+    /// Charges the time since the last event to the running line, function
+    /// and stack, and returns the time now.
+    fn charge(&mut self) -> Instant {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        let call = self.calls.last().expect("the top level is never left");
+        self.functions.entry(call.function.clone()).or_default().exclusive += elapsed;
+        if let Some(line) = call.lines.last() {
+            self.lines.entry(*line).or_default().exclusive += elapsed;
+        }
+        *self.folded.entry(self.stack.clone()).or_default() += elapsed;
+        now
+    }
+
+    /// Stops measuring, ending the lines and calls still active after an error.
+    pub fn finish(&mut self) {
+        let now = self.charge();
+        for call in self.calls.drain(..).rev() {
+            for line in call.lines {
+                deactivate(&mut self.active_lines, &mut self.lines, line, now);
+            }
+            deactivate(&mut self.active_functions, &mut self.functions, call.function, now);
+        }
+    }
+
+    /// The functions by inclusive time, then the lines by exclusive time.
+    pub fn table(&self, source: &str) -> String {
+        let source: Vec<&str> = source.lines().collect();
+        let mut functions: Vec<(&String, &Stats)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+        let mut lines: Vec<(&usize, &Stats)> = self.lines.iter().collect();
+        lines.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+        let mut table = String::new();
+        let _ = writeln!(table, "{:<20} {:>10} {:>12} {:>12}", "function", "calls", "inclusive", "exclusive");
+        for (name, stats) in functions {
+            let _ = writeln!(table, "{:<20} {:>10} {:>12} {:>12}", name, stats.count, millis(stats.inclusive), millis(stats.exclusive));
+        }
+        let _ = writeln!(table, "\n{:>6} {:>10} {:>12} {:>12}  source", "line", "count", "inclusive", "exclusive");
+        for (line, stats) in lines {
+            let text = source.get(line - 1).map_or("", |text| text.trim());
+            let _ = writeln!(table, "{:>6} {:>10} {:>12} {:>12}  {}", line, stats.count, millis(stats.inclusive), millis(stats.exclusive), text);
+        }
+        table
+    }
+
+    /// Exclusive time by call stack in microseconds, one `stack count` line
+    /// each, in the folded format flame graph tools read.
+    pub fn folded_stacks(&self) -> String {
+        let mut stacks: Vec<(&String, u128)> = self.folded.iter().map(|(stack, time)| (stack, time.as_micros())).filter(|(_, micros)| *micros > 0).collect();
+        stacks.sort();
+        stacks.iter().map(|(stack, micros)| format!("{} {}\n", stack, micros)).collect()
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn millis(time: Duration) -> String {
+    format!("{:.3}ms", time.as_secs_f64() * 1000.0)
+}
+
+fn activate<K: Hash + Eq>(active: &mut Active<K>, key: K, now: Instant) {
+    active.entry(key).or_insert((0, now)).0 += 1;
+}
+
+/// Ends one activation; the inclusive time is added when the last one ends,
+/// so recursion is not counted twice.
+fn deactivate<K: Hash + Eq + Clone>(active: &mut Active<K>, stats: &mut HashMap<K, Stats>, key: K, now: Instant) {
+    let Some((count, since)) = active.get_mut(&key) else {
+        return;
+    };
+    *count -= 1;
+    if *count == 0 {
+        stats.entry(key.clone()).or_default().inclusive += now - *since;
+        active.remove(&key);
+    }
+}
+
+/// The hooks write to a profiler shared with whoever reports on it.
+impl Debugger for Rc<RefCell<Profiler>> {
+    fn on_statement(&mut self, _interpreter: &mut Interpreter, stmt: &Stmt, event: Event) -> Result<(), RuntimeError> {
+        let mut profiler = self.borrow_mut();
+        let now = profiler.charge();
+        let line = stmt.span.line;
+        let profiler = &mut *profiler;
+        let call = profiler.calls.last_mut().expect("the top level is never left");
+        match event {
+            Event::Before => {
+                call.lines.push(line);
+                profiler.lines.entry(line).or_default().count += 1;
+                activate(&mut profiler.active_lines, line, now);
+            }
+            Event::After => {
+                call.lines.pop();
+                deactivate(&mut profiler.active_lines, &mut profiler.lines, line, now);
+            }
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, function: &str) {
+        let mut profiler = self.borrow_mut();
+        let now = profiler.charge();
+        profiler.functions.entry(function.to_string()).or_default().count += 1;
+        activate(&mut profiler.active_functions, function.to_string(), now);
+        let caller_stack = profiler.stack.len();
+        profiler.calls.push(Call { function: function.to_string(), lines: Vec::new(), caller_stack });
+        profiler.stack.push(';');
+        profiler.stack.push_str(function);
+    }
+
+    fn leave(&mut self, function: &str) {
+        let mut profiler = self.borrow_mut();
+        let now = profiler.charge();
+        let profiler = &mut *profiler;
+        let Some(call) = profiler.calls.pop() else {
+            return;
+        };
+        // Statements left open by an error
+        for line in call.lines {
+            deactivate(&mut profiler.active_lines, &mut profiler.lines, line, now);
+        }
+        deactivate(&mut profiler.active_functions, &mut profiler.functions, function.to_string(), now);
+        profiler.stack.truncate(call.caller_stack);
+    }
+}
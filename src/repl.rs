@@ -0,0 +1,254 @@
+//! The read-eval-print loop of `fork` without arguments. Input is read until
+//! it forms a complete statement, the value of a bare expression is shown,
+//! and lines starting with `:` are commands.
+use std::fs;
+use std::io::{self, Write};
+use std::time::Instant;
+use crate::ast::{Expr, ExprKind, Stmt, StmtKind};
+use crate::debugger::show;
+use crate::engine::{Engine, ForkError, Script};
+use crate::lexer::Lexer;
+use crate::token::Token;
+
+const PROMPT: &str = "fork> ";
+
+/// Shown while the input so far is not complete.
+const CONTINUATION: &str = "...> ";
+
+const HELP: &str = "\
+Commands:
+  :vars              list the variables and their values
+  :reset             forget every variable and function
+  :load FILE         run a .fork file, or load a snapshot saved with :save
+  :save FILE         save the variables and functions to a snapshot file
+  :type EXPR         the type of the value of an expression
+  :time CODE         run code and show how long it took
+  :tokens CODE       the tokens of code
+  :ast CODE          the syntax tree of code
+  :help              show this help
+  exit, quit         leave
+A statement may span several lines; `...>` asks for the rest.";
+
+/// Reads and runs input from stdin until it ends or `exit` or `quit` is
+/// typed. Returns the status passed to `exit()`, if a script called it.
+pub fn run(engine: &mut Engine) -> Option<i32> {
+    let stdin = io::stdin();
+    let mut buffer = String::new();
+    loop {
+        print!("{}", if buffer.is_empty() { PROMPT } else { CONTINUATION });
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        let ended = !matches!(stdin.read_line(&mut line), Ok(read) if read > 0);
+        if ended && buffer.is_empty() {
+            break;
+        }
+        if buffer.is_empty() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if trimmed == "exit" || trimmed == "quit" {
+                break;
+            }
+            if let Some(command) = trimmed.strip_prefix(':') {
+                match self::command(engine, command) {
+                    Ok(Some(code)) => return Some(code),
+                    Ok(None) => {}
+                    Err(e) => eprintln!("{}", e),
+                }
+                continue;
+            }
+        }
+        buffer.push_str(&line);
+        if !ended && incomplete(&buffer) {
+            continue;
+        }
+        let source = std::mem::take(&mut buffer);
+        match evaluate(engine, &source) {
+            Ok(()) => {}
+            Err(ForkError::Exit(code)) => return Some(code),
+            Err(e) => eprintln!("{}", e),
+        }
+        if ended {
+            println!();
+            break;
+        }
+    }
+    None
+}
+
+/// Whether more lines are needed to complete `source`: a bracket or a
+/// string is left open, or it ends with an operator or a comma.
+pub fn incomplete(source: &str) -> bool {
+    let tokens = Lexer::new().tokenize_with_spans(source);
+    let mut depth = 0i32;
+    let mut last = None;
+    for (token, span) in &tokens {
+        match token {
+            Token::Symbol('(' | '{') => depth += 1,
+            Token::Symbol(')' | '}') => depth -= 1,
+            Token::StringLiteral(_) if span.end - span.start < 2 || !source[..span.end].ends_with('"') => return true,
+            Token::Whitespace | Token::Comment(_) => continue,
+            _ => {}
+        }
+        last = Some(token);
+    }
+    depth > 0 || matches!(last, Some(Token::Symbol('+' | '-' | '*' | '/' | '<' | '>' | '=' | '!' | ',')))
+}
+
+/// Runs `source`, showing its value if it is a bare expression: one not
+/// ended by a semicolon.
+fn evaluate(engine: &mut Engine, source: &str) -> Result<(), ForkError> {
+    let source = source.trim();
+    let script = engine.compile(source)?;
+    let value = engine.run(&script)?;
+    let bare = matches!(script.program().stmts.last(), Some(stmt) if matches!(stmt.kind, StmtKind::Expr(_)));
+    if bare && !source.ends_with(';') {
+        println!("{}", show(Some(&value)));
+    }
+    Ok(())
+}
+
+/// Runs a command, given without its colon. Returns the status passed to
+/// `exit()` if code it ran called it.
+fn command(engine: &mut Engine, command: &str) -> Result<Option<i32>, String> {
+    let (name, arg) = command.split_once(char::is_whitespace).map_or((command, ""), |(name, arg)| (name, arg.trim()));
+    let exited = |result: Result<(), ForkError>| match result {
+        Ok(()) => Ok(None),
+        Err(ForkError::Exit(code)) => Ok(Some(code)),
+        Err(e) => Err(e.to_string()),
+    };
+    match (name, arg) {
+        ("help", _) => println!("{}", HELP),
+        ("vars", _) => {
+            let mut variables: Vec<_> = engine.interpreter().variables().iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+            variables.sort_by(|a, b| a.0.cmp(&b.0));
+            for (name, value) in variables {
+                println!("{} = {}", name, show(Some(&value)));
+            }
+        }
+        ("reset", _) => engine.interpreter().reset(),
+        ("save" | "load" | "type" | "time" | "tokens" | "ast", "") => {
+            let operand = if matches!(name, "save" | "load") { "FILE" } else if name == "type" { "EXPR" } else { "CODE" };
+            return Err(format!("Usage: :{} {}", name, operand));
+        }
+        ("save", path) => fs::write(path, engine.interpreter().snapshot()).map_err(|e| format!("Error writing {}: {}", path, e))?,
+        ("load", path) if path.ends_with(".fork") => return exited(engine.run_file(path).map(drop)),
+        ("load", path) => {
+            let snapshot = fs::read_to_string(path).map_err(|e| format!("Error reading file {}: {}", path, e))?;
+            engine.interpreter().restore(&snapshot).map_err(|e| format!("Error loading {}: {}", path, e))?;
+        }
+        ("type", expr) => {
+            let result = engine.eval(expr).map(|value| println!("{}", value.type_name()));
+            return exited(result);
+        }
+        ("time", code) => {
+            let start = Instant::now();
+            let result = evaluate(engine, code);
+            println!("time: {:.3}ms", start.elapsed().as_secs_f64() * 1000.0);
+            return exited(result);
+        }
+        ("tokens", code) => {
+            for (token, span) in Lexer::new().tokenize_with_spans(code) {
+                if token != Token::Whitespace {
+                    println!("{}:{} {:?}", span.line, span.col, token);
+                }
+            }
+        }
+        ("ast", code) => {
+            let program = Script::parse(code).map_err(|e| e.to_string())?;
+            let mut lines = Vec::new();
+            tree_block(&program.stmts, 0, &mut lines);
+            println!("{}", lines.join("\n"));
+        }
+        _ => return Err(format!("Unknown command :{}; type :help for the commands", name)),
+    }
+    Ok(None)
+}
+
+/// Adds the syntax tree of statements to `lines`, a node per line indented
+/// below its parent.
+fn tree_block(stmts: &[Stmt], depth: usize, lines: &mut Vec<String>) {
+    for stmt in stmts {
+        tree_stmt(stmt, depth, lines);
+    }
+}
+
+fn tree_stmt(stmt: &Stmt, depth: usize, lines: &mut Vec<String>) {
+    let mut node = |text: String| lines.push(format!("{}{}", "  ".repeat(depth), text));
+    match &stmt.kind {
+        StmtKind::Assign { name, value } => {
+            node(format!("Assign {}", name));
+            tree_expr(value, depth + 1, lines);
+        }
+        StmtKind::Let { name, ty, value } => {
+            node(format!("Let {}{}", name, ty.map(|ty| format!(": {}", ty.name())).unwrap_or_default()));
+            tree_expr(value, depth + 1, lines);
+        }
+        StmtKind::Increment { name, amount } => node(format!("Increment {} {}", name, amount)),
+        StmtKind::Print(args) => {
+            node("Print".to_string());
+            args.iter().for_each(|arg| tree_expr(arg, depth + 1, lines));
+        }
+        StmtKind::If { cond, then_block, else_block } => {
+            node("If".to_string());
+            tree_expr(cond, depth + 1, lines);
+            lines.push(format!("{}Then", "  ".repeat(depth + 1)));
+            tree_block(then_block, depth + 2, lines);
+            if let Some(else_block) = else_block {
+                lines.push(format!("{}Else", "  ".repeat(depth + 1)));
+                tree_block(else_block, depth + 2, lines);
+            }
+        }
+        StmtKind::While { cond, body } => {
+            node("While".to_string());
+            tree_expr(cond, depth + 1, lines);
+            tree_block(body, depth + 1, lines);
+        }
+        StmtKind::For { init, cond, update, body } => {
+            node("For".to_string());
+            if let Some(init) = init {
+                tree_stmt(init, depth + 1, lines);
+            }
+            if let Some(cond) = cond {
+                tree_expr(cond, depth + 1, lines);
+            }
+            if let Some(update) = update {
+                tree_stmt(update, depth + 1, lines);
+            }
+            tree_block(body, depth + 1, lines);
+        }
+        StmtKind::Def { body, .. } => {
+            node(format!("Def {}", crate::formatter::signature(stmt).trim_start_matches("def ")));
+            tree_block(body, depth + 1, lines);
+        }
+        StmtKind::Return(value) => {
+            node("Return".to_string());
+            if let Some(value) = value {
+                tree_expr(value, depth + 1, lines);
+            }
+        }
+        StmtKind::Expr(expr) => tree_expr(expr, depth, lines),
+    }
+}
+
+fn tree_expr(expr: &Expr, depth: usize, lines: &mut Vec<String>) {
+    let text = match &expr.kind {
+        ExprKind::Number(n) => format!("Number {}", n),
+        ExprKind::Str(s) => format!("Str {:?}", s),
+        ExprKind::Var(name) => format!("Var {}", name),
+        ExprKind::Unary { .. } => "Neg".to_string(),
+        ExprKind::Binary { op, .. } => format!("Binary {}", op.symbol()),
+        ExprKind::Call { name, .. } => format!("Call {}", name),
+    };
+    lines.push(format!("{}{}", "  ".repeat(depth), text));
+    match &expr.kind {
+        ExprKind::Unary { expr, .. } => tree_expr(expr, depth + 1, lines),
+        ExprKind::Binary { lhs, rhs, .. } => {
+            tree_expr(lhs, depth + 1, lines);
+            tree_expr(rhs, depth + 1, lines);
+        }
+        ExprKind::Call { args, .. } => args.iter().for_each(|arg| tree_expr(arg, depth + 1, lines)),
+        _ => {}
+    }
+}
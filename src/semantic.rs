@@ -1,18 +1,583 @@
-use crate::token::Token;
+//! Static checks run before a program executes: every identifier is resolved
+//! to a variable of its scope or to a function, and expressions are type checked.
+use std::collections::HashMap;
+use std::fmt;
+use crate::ast::{BinOp, Expr, ExprKind, Program, Stmt, StmtKind, TypeName};
+use crate::interpreter::builtins::BUILTINS;
+use crate::token::Span;
 
-pub struct SemanticAnalyzer;
+/// A problem found before the program runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (line {}, column {})", self.message, self.span.line, self.span.col)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Param,
+    /// Assigned somewhere in the scope's body.
+    Variable,
+    /// Assigned before the program started, such as by earlier REPL input.
+    Predefined,
+}
+
+/// One variable of a scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where the variable is first assigned, or the parameter's name.
+    /// `None` for predefined variables.
+    pub declared: Option<Span>,
+    /// The type it is annotated with, if any.
+    pub ty: Option<TypeName>,
+}
+
+/// The variables of the top-level code or of one function. Functions see
+/// only their own scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    /// `<main>` for the top level, else the function name.
+    pub name: String,
+    /// Variables by slot number, parameters first.
+    pub symbols: Vec<Symbol>,
+}
+
+/// A use of a variable name and the slot it refers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resolution {
+    pub span: Span,
+    pub scope: usize,
+    pub slot: usize,
+    pub access: Access,
+}
+
+/// Whether a use of a variable reads it, assigns it, or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// `x++` and `x--`.
+    ReadWrite,
+}
+
+/// Result of a successful analysis. Scope 0 is the top level; the others
+/// follow the `def`s in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub scopes: Vec<Scope>,
+    pub resolutions: Vec<Resolution>,
+    /// Inferred type of the variable at each resolution, by the start of its
+    /// span. A use inside a loop has the type it has on any iteration.
+    pub types: HashMap<usize, Type>,
+}
+
+/// Parameter and return annotations of a function. Unannotated positions are `None`.
+#[derive(Debug, Clone, PartialEq)]
+struct Signature {
+    params: Vec<Option<TypeName>>,
+    ret: Option<TypeName>,
+}
+
+pub struct SemanticAnalyzer {
+    globals: Vec<String>,
+    functions: HashMap<String, Signature>,
+}
 
 impl SemanticAnalyzer {
     pub fn new() -> Self {
-        SemanticAnalyzer
+        let functions = BUILTINS
+            .iter()
+            .map(|builtin| (builtin.name.to_string(), Signature { params: builtin.params.to_vec(), ret: builtin.ret }))
+            .collect();
+        SemanticAnalyzer { globals: Vec::new(), functions }
+    }
+
+    /// Treats `names` as top-level variables that are already assigned.
+    pub fn with_globals(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.globals.extend(names);
+        self
+    }
+
+    /// Treats `functions`, given as name and parameter count, as already defined.
+    pub fn with_functions(mut self, functions: impl IntoIterator<Item = (String, usize)>) -> Self {
+        for (name, arity) in functions {
+            self.functions.insert(name, Signature { params: vec![None; arity], ret: None });
+        }
+        self
+    }
+
+    /// Resolves every identifier in `program` and checks the types of its
+    /// expressions. A read of a name that is never assigned in its scope, a
+    /// call to an unknown function or with the wrong number of arguments, and
+    /// a type mismatch are errors; all of them are reported, in source order.
+    pub fn analyze(&self, program: &Program) -> Result<Analysis, Vec<SemanticError>> {
+        let (analysis, errors) = self.inspect(program);
+        if errors.is_empty() {
+            return Ok(analysis);
+        }
+        Err(errors)
+    }
+
+    /// Like `analyze`, but also returns what could be resolved when there are
+    /// errors, for tools that work on programs being edited.
+    pub fn inspect(&self, program: &Program) -> (Analysis, Vec<SemanticError>) {
+        let mut functions = self.functions.clone();
+        let mut bodies = vec![("<main>", None, program.stmts.as_slice())];
+        for stmt in &program.stmts {
+            if let StmtKind::Def { name, params, ret, body } = &stmt.kind {
+                let params = params.iter().map(|param| param.ty).collect();
+                functions.insert(name.clone(), Signature { params, ret: *ret });
+                bodies.push((name, Some(stmt), body));
+            }
+        }
+        let mut resolver = Resolver {
+            functions: &functions,
+            analysis: Analysis { scopes: Vec::new(), resolutions: Vec::new(), types: HashMap::new() },
+            errors: Vec::new(),
+        };
+        for &(name, def, body) in &bodies {
+            let symbols = match def.map(|def| &def.kind) {
+                Some(StmtKind::Def { params, .. }) => params
+                    .iter()
+                    .map(|param| Symbol { name: param.name.clone(), kind: SymbolKind::Param, declared: Some(param.span), ty: param.ty })
+                    .collect(),
+                _ => self
+                    .globals
+                    .iter()
+                    .map(|name| Symbol { name: name.clone(), kind: SymbolKind::Predefined, declared: None, ty: None })
+                    .collect(),
+            };
+            resolver.scope(name, symbols, body);
+        }
+
+        let mut errors = resolver.errors;
+        let mut types = HashMap::new();
+        for (scope, &(name, def, body)) in resolver.analysis.scopes.iter().zip(&bodies) {
+            let ret = def.and_then(|_| functions[name].ret);
+            let mut checker = Checker {
+                functions: &functions,
+                declared: scope.symbols.iter().filter_map(|symbol| Some((symbol.name.clone(), symbol.ty?))).collect(),
+                function: def.map(|_| (name, ret)),
+                types: HashMap::new(),
+                errors: Vec::new(),
+            };
+            let mut env = Env::new();
+            for symbol in &scope.symbols {
+                if symbol.kind == SymbolKind::Param {
+                    env.insert(symbol.name.clone(), symbol.ty.map_or(Type::Any, Type::from));
+                }
+            }
+            checker.block(body, &mut env);
+            errors.extend(checker.errors);
+            types.extend(checker.types);
+        }
+        errors.sort_by_key(|error| error.span.start);
+        (Analysis { types, ..resolver.analysis }, errors)
+    }
+}
+
+impl Default for SemanticAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Walks one scope at a time, recording resolutions and errors.
+struct Resolver<'a> {
+    functions: &'a HashMap<String, Signature>,
+    analysis: Analysis,
+    errors: Vec<SemanticError>,
+}
+
+impl Resolver<'_> {
+    /// Resolves a scope whose slots start with `symbols`, followed by every
+    /// variable assigned in `stmts`. Nested `def`s belong to their own scope.
+    fn scope(&mut self, name: &str, mut symbols: Vec<Symbol>, stmts: &[Stmt]) {
+        self.declare_assigned(stmts, &mut symbols);
+        self.analysis.scopes.push(Scope { name: name.to_string(), symbols });
+        self.block(stmts);
     }
 
-    pub fn analyze(&self, tokens: &[Token]) {
-        // For demonstration, just print the tokens as a stub for real semantic analysis
+    /// Adds a `Variable` slot for every name assigned in `stmts` that has none
+    /// yet, and records `let` annotations. `x++` does not count as an
+    /// assignment: it reads `x` before writing it.
+    fn declare_assigned(&mut self, stmts: &[Stmt], symbols: &mut Vec<Symbol>) {
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Assign { name, .. } | StmtKind::Let { name, .. } => {
+                    let ty = match &stmt.kind {
+                        StmtKind::Let { ty, .. } => *ty,
+                        _ => None,
+                    };
+                    match symbols.iter_mut().find(|symbol| symbol.name == *name) {
+                        None => symbols.push(Symbol { name: name.clone(), kind: SymbolKind::Variable, declared: Some(stmt.span), ty }),
+                        Some(symbol) => match (symbol.ty, ty) {
+                            (Some(old), Some(new)) if old != new => self.error(
+                                format!("TypeError: '{}' is already declared as {}", name, old.name()),
+                                stmt.span,
+                            ),
+                            (None, Some(_)) => symbol.ty = ty,
+                            _ => {}
+                        },
+                    }
+                }
+                StmtKind::If { then_block, else_block, .. } => {
+                    self.declare_assigned(then_block, symbols);
+                    if let Some(else_block) = else_block {
+                        self.declare_assigned(else_block, symbols);
+                    }
+                }
+                StmtKind::While { body, .. } => self.declare_assigned(body, symbols),
+                StmtKind::For { init, update, body, .. } => {
+                    if let Some(init) = init {
+                        self.declare_assigned(std::slice::from_ref(&**init), symbols);
+                    }
+                    self.declare_assigned(body, symbols);
+                    if let Some(update) = update {
+                        self.declare_assigned(std::slice::from_ref(&**update), symbols);
+                    }
+                }
+                StmtKind::Increment { .. } | StmtKind::Print(_) | StmtKind::Return(_) | StmtKind::Expr(_) | StmtKind::Def { .. } => {}
+            }
+        }
+    }
 
-        for _token in tokens {
+    fn block(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
 
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Assign { name, value } | StmtKind::Let { name, value, .. } => {
+                self.expr(value);
+                self.variable(name, stmt.span, Access::Write);
+            }
+            StmtKind::Increment { name, .. } => self.variable(name, stmt.span, Access::ReadWrite),
+            StmtKind::Print(args) => args.iter().for_each(|arg| self.expr(arg)),
+            StmtKind::If { cond, then_block, else_block } => {
+                self.expr(cond);
+                self.block(then_block);
+                if let Some(else_block) = else_block {
+                    self.block(else_block);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.block(body);
+            }
+            StmtKind::For { init, cond, update, body } => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = cond {
+                    self.expr(cond);
+                }
+                self.block(body);
+                if let Some(update) = update {
+                    self.stmt(update);
+                }
+            }
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Def { .. } => {}
         }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Number(_) | ExprKind::Str(_) => {}
+            ExprKind::Var(name) => self.variable(name, expr.span, Access::Read),
+            ExprKind::Unary { expr, .. } => self.expr(expr),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Call { name, args } => {
+                match self.functions.get(name) {
+                    None => self.error(format!("NameError: name '{}' is not defined", name), expr.span),
+                    Some(signature) if signature.params.len() != args.len() => self.error(
+                        format!(
+                            "TypeError: {}() takes {} arguments but {} were given",
+                            name,
+                            signature.params.len(),
+                            args.len()
+                        ),
+                        expr.span,
+                    ),
+                    Some(_) => {}
+                }
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+        }
+    }
+
+    /// Resolves a use of `name` in the current scope.
+    fn variable(&mut self, name: &str, span: Span, access: Access) {
+        let scope = self.analysis.scopes.len() - 1;
+        match self.analysis.scopes[scope].symbols.iter().position(|symbol| symbol.name == name) {
+            Some(slot) => self.analysis.resolutions.push(Resolution { span, scope, slot, access }),
+            None => self.error(format!("NameError: name '{}' is not defined", name), span),
+        }
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.errors.push(SemanticError { message, span });
+    }
+}
+
+/// A static type. Unannotated code whose type the checker cannot tell gets
+/// `Any`, which is compatible with every type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Float,
+    Str,
+    Any,
+}
+
+impl Type {
+    /// The type of a variable that may hold a value of either type.
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            _ if self == other => self,
+            (Type::Int, Type::Float) | (Type::Float, Type::Int) => Type::Float,
+            _ => Type::Any,
+        }
+    }
+
+    /// Whether a value of this type may be stored where `target` is expected.
+    /// An `int` is also a valid `float`.
+    fn fits(self, target: TypeName) -> bool {
+        matches!(
+            (self, target),
+            (Type::Any, _) | (Type::Int, TypeName::Int | TypeName::Float) | (Type::Float, TypeName::Float) | (Type::Str, TypeName::Str)
+        )
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Str => "str",
+            Type::Any => "any",
+        }
+    }
+}
+
+impl From<TypeName> for Type {
+    fn from(ty: TypeName) -> Type {
+        match ty {
+            TypeName::Int => Type::Int,
+            TypeName::Float => Type::Float,
+            TypeName::Str => Type::Str,
+        }
+    }
+}
+
+/// Types of the variables assigned so far on the current path.
+type Env = HashMap<String, Type>;
+
+/// Infers the types of one scope's variables in program order and reports
+/// operations the runtime would reject.
+struct Checker<'a> {
+    functions: &'a HashMap<String, Signature>,
+    /// Variables with a type annotation, which every assignment must respect.
+    declared: HashMap<String, TypeName>,
+    /// Name and return annotation of the function being checked.
+    function: Option<(&'a str, Option<TypeName>)>,
+    /// Types of the variable uses seen so far, as in `Analysis::types`.
+    types: HashMap<usize, Type>,
+    errors: Vec<SemanticError>,
+}
+
+impl Checker<'_> {
+    fn block(&mut self, stmts: &[Stmt], env: &mut Env) {
+        for stmt in stmts {
+            self.stmt(stmt, env);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, env: &mut Env) {
+        match &stmt.kind {
+            StmtKind::Assign { name, value } | StmtKind::Let { name, value, .. } => {
+                let ty = self.expr(value, env);
+                let ty = match self.declared.get(name) {
+                    Some(&declared) => {
+                        if !ty.fits(declared) {
+                            let message = format!("TypeError: cannot assign {} to '{}' of type {}", ty.name(), name, declared.name());
+                            self.error(message, value.span);
+                        }
+                        declared.into()
+                    }
+                    None => ty,
+                };
+                env.insert(name.clone(), ty);
+                self.record(stmt.span, ty);
+            }
+            StmtKind::Increment { name, amount } => {
+                let ty = self.variable(name, env);
+                self.record(stmt.span, ty);
+                if ty == Type::Str {
+                    let op = if *amount > 0.0 { BinOp::Add } else { BinOp::Sub };
+                    self.error(format!("TypeError: unsupported operand types for {}: str and int", op.symbol()), stmt.span);
+                }
+            }
+            StmtKind::Print(args) => {
+                for arg in args {
+                    self.expr(arg, env);
+                }
+            }
+            StmtKind::If { cond, then_block, else_block } => {
+                self.expr(cond, env);
+                let mut then_env = env.clone();
+                self.block(then_block, &mut then_env);
+                if let Some(else_block) = else_block {
+                    self.block(else_block, env);
+                }
+                *env = join(env, then_env);
+            }
+            StmtKind::While { cond, body } => self.repeat(Some(cond), body, None, env),
+            StmtKind::For { init, cond, update, body } => {
+                if let Some(init) = init {
+                    self.stmt(init, env);
+                }
+                self.repeat(cond.as_ref(), body, update.as_deref(), env);
+            }
+            StmtKind::Return(value) => {
+                let ty = value.as_ref().map_or(Type::Int, |value| self.expr(value, env));
+                if let Some((name, Some(ret))) = self.function
+                    && !ty.fits(ret)
+                {
+                    let span = value.as_ref().map_or(stmt.span, |value| value.span);
+                    self.error(format!("TypeError: {}() must return {}, found {}", name, ret.name(), ty.name()), span);
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr, env);
+            }
+            StmtKind::Def { .. } => {}
+        }
+    }
+
+    /// Checks a loop. Types assigned in the body flow back to its start, so
+    /// the body is walked again with the joined types until they stop changing.
+    /// Errors found on any walk are reported: the first walk sees the types
+    /// on entry, which later walks may have widened to `any`.
+    fn repeat(&mut self, cond: Option<&Expr>, body: &[Stmt], update: Option<&Stmt>, env: &mut Env) {
+        loop {
+            let mut after = env.clone();
+            if let Some(cond) = cond {
+                self.expr(cond, &mut after);
+            }
+            self.block(body, &mut after);
+            if let Some(update) = update {
+                self.stmt(update, &mut after);
+            }
+            let joined = join(env, after);
+            if joined == *env {
+                break;
+            }
+            *env = joined;
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, env: &mut Env) -> Type {
+        match &expr.kind {
+            ExprKind::Number(n) if n.fract() == 0.0 => Type::Int,
+            ExprKind::Number(_) => Type::Float,
+            ExprKind::Str(_) => Type::Str,
+            ExprKind::Var(name) => {
+                let ty = self.variable(name, env);
+                self.record(expr.span, ty);
+                ty
+            }
+            ExprKind::Unary { expr: operand, .. } => match self.expr(operand, env) {
+                Type::Str => {
+                    self.error("TypeError: bad operand type for unary -: str".to_string(), expr.span);
+                    Type::Any
+                }
+                ty => ty,
+            },
+            ExprKind::Binary { op, lhs, rhs } => {
+                let (lhs, rhs) = (self.expr(lhs, env), self.expr(rhs, env));
+                match (op, lhs, rhs) {
+                    (BinOp::Eq | BinOp::Ne, _, _) => Type::Int,
+                    (_, Type::Str, _) | (_, _, Type::Str) => {
+                        let message = format!("TypeError: unsupported operand types for {}: {} and {}", op.symbol(), lhs.name(), rhs.name());
+                        self.error(message, expr.span);
+                        Type::Any
+                    }
+                    (BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge, _, _) => Type::Int,
+                    (_, Type::Any, _) | (_, _, Type::Any) => Type::Any,
+                    (BinOp::Div, _, _) => Type::Float,
+                    (_, Type::Int, Type::Int) => Type::Int,
+                    _ => Type::Float,
+                }
+            }
+            ExprKind::Call { name, args } => {
+                let args: Vec<(Type, Span)> = args.iter().map(|arg| (self.expr(arg, env), arg.span)).collect();
+                let Some(signature) = self.functions.get(name) else {
+                    return Type::Any;
+                };
+                for (idx, (&(ty, span), param)) in args.iter().zip(&signature.params).enumerate() {
+                    if let Some(param) = *param
+                        && !ty.fits(param)
+                    {
+                        let message = format!("TypeError: argument {} of {}() must be {}, found {}", idx + 1, name, param.name(), ty.name());
+                        self.error(message, span);
+                    }
+                }
+                signature.ret.map_or(Type::Any, Type::from)
+            }
+        }
+    }
+
+    /// The type of a variable read: its annotation, else what it was last assigned.
+    fn variable(&self, name: &str, env: &Env) -> Type {
+        match self.declared.get(name) {
+            Some(&declared) => declared.into(),
+            None => env.get(name).copied().unwrap_or(Type::Any),
+        }
+    }
+
+    /// Notes the type of a variable use, joined with what earlier walks of
+    /// an enclosing loop found.
+    fn record(&mut self, span: Span, ty: Type) {
+        let ty = self.types.get(&span.start).map_or(ty, |&old| old.join(ty));
+        self.types.insert(span.start, ty);
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        let error = SemanticError { message, span };
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+}
 
+/// Types after control flow from two paths meets.
+fn join(a: &Env, b: Env) -> Env {
+    let mut joined = a.clone();
+    for (name, ty) in b {
+        let ty = match a.get(&name) {
+            Some(&other) => other.join(ty),
+            None => ty,
+        };
+        joined.insert(name, ty);
     }
+    joined
 }
@@ -0,0 +1,243 @@
+//! Conversion of control-flow graphs into and out of static single assignment form.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::cfg::{BlockId, Cfg, Dominators, Phi, Terminator};
+use crate::ir::{Instr, InstrKind, Operand, Var};
+
+/// Rewrites the graph so every variable has exactly one definition, inserting
+/// phi nodes where definitions from different paths meet. Unreachable blocks
+/// are removed first. Reads of a variable before any definition keep the
+/// unversioned name, which stands for the value on entry to the function.
+pub fn into_ssa(cfg: &mut Cfg) {
+    cfg.remove_unreachable();
+    let dominators = Dominators::compute(cfg);
+    insert_phis(cfg, &dominators);
+    let mut renamer = Renamer { counters: HashMap::new(), stacks: HashMap::new() };
+    renamer.rename(cfg, 0, &dominators.tree());
+}
+
+/// Replaces phi nodes with copies on the incoming edges. Critical edges are
+/// split first so the copies only run on the edge they belong to. Copies of a
+/// variable's entry value are dropped unless it is a parameter: nothing
+/// assigns it, so the destination is left unassigned just the same.
+pub fn out_of_ssa(cfg: &mut Cfg) {
+    for block in 0..cfg.blocks.len() {
+        if let Terminator::Branch { then_block, else_block, .. } = cfg.blocks[block].terminator
+            && then_block == else_block
+        {
+            cfg.blocks[block].terminator = Terminator::Jump(then_block);
+        }
+    }
+    for block in 0..cfg.blocks.len() {
+        if cfg.blocks[block].phis.is_empty() || cfg.blocks[block].preds.len() < 2 {
+            continue;
+        }
+        for pred in cfg.blocks[block].preds.clone() {
+            if cfg.successors(pred).len() > 1 {
+                cfg.split_edge(pred, block);
+            }
+        }
+    }
+    let mut next_temp = max_temp(cfg).map_or(0, |t| t + 1);
+    for block in 0..cfg.blocks.len() {
+        let phis = std::mem::take(&mut cfg.blocks[block].phis);
+        if phis.is_empty() {
+            continue;
+        }
+        for pred in cfg.blocks[block].preds.clone() {
+            let copies: Vec<(Var, Operand)> = phis
+                .iter()
+                .filter_map(|phi| {
+                    let (_, value) = phi.args.iter().find(|(p, _)| *p == pred)?;
+                    Some((phi.dest.clone(), value.clone()))
+                })
+                .collect();
+            let line = cfg.blocks[pred].terminator_line;
+            for (dest, src) in sequentialize(copies, &mut next_temp) {
+                cfg.blocks[pred].instrs.push(Instr { kind: InstrKind::Copy { dest, src }, line });
+            }
+        }
+    }
+    let params = cfg.params.clone();
+    for block in &mut cfg.blocks {
+        block.instrs.retain(|instr| {
+            !matches!(&instr.kind, InstrKind::Copy { src: Operand::Var(Var::Named(name)), .. } if !params.contains(name))
+        });
+    }
+}
+
+/// Orders a set of simultaneous copies so no source is overwritten before it is
+/// read, breaking cycles through a fresh temporary.
+fn sequentialize(mut pending: Vec<(Var, Operand)>, next_temp: &mut u32) -> Vec<(Var, Operand)> {
+    pending.retain(|(dest, src)| *src != Operand::Var(dest.clone()));
+    let mut ordered = Vec::new();
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|(dest, _)| {
+            !pending.iter().any(|(_, src)| *src == Operand::Var(dest.clone()))
+        });
+        match ready {
+            Some(idx) => ordered.push(pending.remove(idx)),
+            None => {
+                // Every destination is still needed as a source: save one in a temporary
+                let saved = pending[0].0.clone();
+                let temp = Var::Temp(*next_temp);
+                *next_temp += 1;
+                ordered.push((temp.clone(), Operand::Var(saved.clone())));
+                for (_, src) in &mut pending {
+                    if *src == Operand::Var(saved.clone()) {
+                        *src = Operand::Var(temp.clone());
+                    }
+                }
+            }
+        }
+    }
+    ordered
+}
+
+/// Highest temporary number used anywhere in the graph.
+pub fn max_temp(cfg: &Cfg) -> Option<u32> {
+    let mut max = None;
+    let mut visit = |var: &Var| {
+        if let Var::Temp(n) = var.base() {
+            max = max.max(Some(*n));
+        }
+    };
+    for block in &cfg.blocks {
+        for phi in &block.phis {
+            visit(&phi.dest);
+        }
+        for instr in &block.instrs {
+            if let Some(dest) = instr.kind.dest() {
+                visit(dest);
+            }
+            for operand in instr.kind.operands() {
+                if let Operand::Var(var) = operand {
+                    visit(var);
+                }
+            }
+        }
+    }
+    max
+}
+
+/// Places phi nodes at the iterated dominance frontier of each variable's
+/// definitions. Only variables read in a block other than the one that
+/// defines them are considered, which avoids most dead phis.
+fn insert_phis(cfg: &mut Cfg, dominators: &Dominators) {
+    let mut def_blocks: BTreeMap<Var, BTreeSet<BlockId>> = BTreeMap::new();
+    let mut live_across = BTreeSet::new();
+    for (id, block) in cfg.blocks.iter().enumerate() {
+        let mut defined = BTreeSet::new();
+        let mut note_use = |operand: &Operand, defined: &BTreeSet<Var>| {
+            if let Operand::Var(var) = operand
+                && !defined.contains(var)
+            {
+                live_across.insert(var.clone());
+            }
+        };
+        for instr in &block.instrs {
+            for operand in instr.kind.operands() {
+                note_use(operand, &defined);
+            }
+            if let Some(dest) = instr.kind.dest() {
+                defined.insert(dest.clone());
+                def_blocks.entry(dest.clone()).or_default().insert(id);
+            }
+        }
+        if let Some(operand) = block.terminator.operand() {
+            note_use(operand, &defined);
+        }
+    }
+    let frontiers = dominators.frontiers(cfg);
+    for (var, blocks) in def_blocks {
+        if !live_across.contains(&var) {
+            continue;
+        }
+        let mut has_phi = BTreeSet::new();
+        let mut worklist: Vec<BlockId> = blocks.iter().copied().collect();
+        while let Some(block) = worklist.pop() {
+            for &frontier in &frontiers[block] {
+                if has_phi.insert(frontier) {
+                    let args = cfg.blocks[frontier].preds.iter().map(|&p| (p, Operand::Var(var.clone()))).collect();
+                    cfg.blocks[frontier].phis.push(Phi { dest: var.clone(), args });
+                    if !blocks.contains(&frontier) {
+                        worklist.push(frontier);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renaming state: the next version number and the stack of live versions of each variable.
+struct Renamer {
+    counters: HashMap<Var, u32>,
+    stacks: HashMap<Var, Vec<Var>>,
+}
+
+impl Renamer {
+    fn define(&mut self, var: &mut Var, pushed: &mut Vec<Var>) {
+        let base = var.base().clone();
+        let counter = self.counters.entry(base.clone()).or_insert(0);
+        *counter += 1;
+        let version = Var::Version(Box::new(base.clone()), *counter);
+        self.stacks.entry(base.clone()).or_default().push(version.clone());
+        pushed.push(base);
+        *var = version;
+    }
+
+    fn current(&self, var: &Var) -> Var {
+        self.stacks
+            .get(var.base())
+            .and_then(|stack| stack.last())
+            .cloned()
+            .unwrap_or_else(|| var.base().clone())
+    }
+
+    fn rewrite_use(&self, operand: &mut Operand) {
+        if let Operand::Var(var) = operand {
+            *var = self.current(var);
+        }
+    }
+
+    /// Renames definitions and uses in `block`, then in the blocks it dominates.
+    fn rename(&mut self, cfg: &mut Cfg, block: BlockId, tree: &[Vec<BlockId>]) {
+        let mut pushed = Vec::new();
+        let mut phis = std::mem::take(&mut cfg.blocks[block].phis);
+        for phi in &mut phis {
+            self.define(&mut phi.dest, &mut pushed);
+        }
+        cfg.blocks[block].phis = phis;
+        let mut instrs = std::mem::take(&mut cfg.blocks[block].instrs);
+        for instr in &mut instrs {
+            for operand in instr.kind.operands_mut() {
+                self.rewrite_use(operand);
+            }
+            if let Some(dest) = instr.kind.dest_mut() {
+                self.define(dest, &mut pushed);
+            }
+        }
+        cfg.blocks[block].instrs = instrs;
+        if let Some(operand) = cfg.blocks[block].terminator.operand_mut() {
+            let mut renamed = operand.clone();
+            self.rewrite_use(&mut renamed);
+            *operand = renamed;
+        }
+        for succ in cfg.successors(block) {
+            let mut phis = std::mem::take(&mut cfg.blocks[succ].phis);
+            for phi in &mut phis {
+                for (pred, value) in &mut phi.args {
+                    if *pred == block {
+                        self.rewrite_use(value);
+                    }
+                }
+            }
+            cfg.blocks[succ].phis = phis;
+        }
+        for &child in &tree[block] {
+            self.rename(cfg, child, tree);
+        }
+        for base in pushed {
+            self.stacks.get_mut(&base).unwrap().pop();
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! `fork test`: finds the `test_` functions of `*_test.fork` files, runs each
+//! in its own interpreter, and reports the results as text or JUnit XML.
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::ast::{Program, StmtKind};
+use crate::coverage::{escape, FileCoverage};
+use crate::debugger::show;
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::semantic::SemanticAnalyzer;
+use crate::value::Value;
+
+/// Files whose names end with this are test files.
+pub const SUFFIX: &str = "_test.fork";
+
+/// Functions whose names start with this are tests.
+pub const PREFIX: &str = "test_";
+
+/// Stack of each thread running tests: as much as the main thread has, which
+/// deep recursion needs.
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// The test files under `paths`, which may be files or directories; the
+/// current directory if there are none. Directories are searched
+/// recursively, skipping hidden ones and `target`.
+pub fn discover(paths: &[String]) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let default = [".".to_string()];
+    for path in if paths.is_empty() { &default[..] } else { paths } {
+        if Path::new(path).is_dir() {
+            let mut found = Vec::new();
+            search(Path::new(path), &mut found)?;
+            found.sort();
+            files.extend(found);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn search(dir: &Path, found: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                search(&path, found)?;
+            }
+        } else if name.ends_with(SUFFIX) {
+            let path = path.to_string_lossy().into_owned();
+            found.push(path.strip_prefix("./").map_or(path.clone(), str::to_string));
+        }
+    }
+    Ok(())
+}
+
+/// Makes the assertions available to a test:
+/// - `assert_true(cond)` fails unless `cond` is true
+/// - `assert_eq(actual, expected)` fails unless the values are equal
+pub fn define_assertions(interpreter: &mut Interpreter) {
+    interpreter.define_native("assert_true", 1, |args| {
+        if args[0].is_truthy() {
+            return Ok(Value::Number(0.0));
+        }
+        Err("AssertionError: assertion failed".to_string())
+    });
+    interpreter.define_native("assert_eq", 2, |args| {
+        if args[0] == args[1] {
+            return Ok(Value::Number(0.0));
+        }
+        Err(format!("AssertionError: {} != {}", show(Some(&args[0])), show(Some(&args[1]))))
+    });
+}
+
+/// A test file.
+pub struct Suite {
+    pub path: String,
+    source: String,
+    /// The checked program, or why it could not be read.
+    program: Result<Program, String>,
+}
+
+impl Suite {
+    pub fn load(path: &str) -> Suite {
+        let (source, program) = match fs::read_to_string(path) {
+            Ok(source) => {
+                let program = Self::check(&source);
+                (source, program)
+            }
+            Err(e) => (String::new(), Err(format!("Error reading file {}: {}", path, e))),
+        };
+        Suite { path: path.to_string(), source, program }
+    }
+
+    fn check(source: &str) -> Result<Program, String> {
+        let tokens = Lexer::new().tokenize_with_spans(source);
+        let program = Parser::new(tokens).parse().map_err(|e| e.to_string())?;
+        let mut interpreter = Interpreter::new();
+        define_assertions(&mut interpreter);
+        SemanticAnalyzer::new()
+            .with_functions(interpreter.function_arities())
+            .analyze(&program)
+            .map_err(|errors| errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))?;
+        Ok(program)
+    }
+
+    /// The names of its `test_` functions that take no arguments. A file
+    /// without any is a single test, named after the file.
+    pub fn tests(&self) -> Vec<Option<String>> {
+        let Ok(program) = &self.program else {
+            return vec![None];
+        };
+        let names: Vec<Option<String>> = program
+            .stmts
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StmtKind::Def { name, params, .. } if name.starts_with(PREFIX) && params.is_empty() => Some(Some(name.clone())),
+                _ => None,
+            })
+            .collect();
+        if names.is_empty() { vec![None] } else { names }
+    }
+}
+
+/// A test to run: a function of a suite, or the whole suite.
+pub struct Test {
+    pub suite: usize,
+    pub name: Option<String>,
+}
+
+impl Test {
+    /// `path::name`, or the path for a whole file.
+    pub fn full_name(&self, suites: &[Suite]) -> String {
+        let path = &suites[self.suite].path;
+        match &self.name {
+            Some(name) => format!("{}::{}", path, name),
+            None => path.clone(),
+        }
+    }
+}
+
+/// Why a test failed, and where.
+pub struct Failure {
+    pub message: String,
+    /// Line of the failing statement and its source text.
+    pub location: Option<(usize, String)>,
+}
+
+pub struct Outcome {
+    pub result: Result<(), Failure>,
+    pub time: Duration,
+    pub coverage: Option<FileCoverage>,
+}
+
+/// Runs the top level of the suite, then the test function if there is one,
+/// in a fresh interpreter.
+fn run(suite: &Suite, name: Option<&str>, coverage: bool) -> Outcome {
+    let start = Instant::now();
+    let program = match &suite.program {
+        Ok(program) => program,
+        Err(message) => {
+            let result = Err(Failure { message: message.clone(), location: None });
+            return Outcome { result, time: start.elapsed(), coverage: None };
+        }
+    };
+    let mut interpreter = Interpreter::new();
+    define_assertions(&mut interpreter);
+    let recorder = coverage.then(|| Rc::new(RefCell::new(FileCoverage::new(&suite.path, &suite.source, program))));
+    if let Some(recorder) = &recorder {
+        interpreter.attach(Box::new(Rc::clone(recorder)));
+    }
+    let mut result = interpreter.interpret(program);
+    if let (Ok(()), Some(name)) = (&result, name) {
+        let line = program.stmts.iter().find(|stmt| matches!(&stmt.kind, StmtKind::Def { name: def, .. } if def == name)).map_or(0, |stmt| stmt.span.line);
+        result = interpreter.call_function(name, Vec::new(), line).map(drop);
+    }
+    drop(interpreter);
+    let result = result.map_err(|e| Failure {
+        location: e.line.map(|line| (line, suite.source.lines().nth(line - 1).unwrap_or_default().trim().to_string())),
+        message: e.message,
+    });
+    let coverage = recorder.and_then(Rc::into_inner).map(RefCell::into_inner);
+    Outcome { result, time: start.elapsed(), coverage }
+}
+
+/// Runs the tests on up to `jobs` threads. The outcomes are in the order of
+/// the tests.
+pub fn run_all(suites: &[Suite], tests: &[Test], jobs: usize, coverage: bool) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let outcomes: Mutex<Vec<Option<Outcome>>> = Mutex::new(tests.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, tests.len().max(1)) {
+            let worker = || {
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(test) = tests.get(idx) else {
+                        break;
+                    };
+                    let outcome = run(&suites[test.suite], test.name.as_deref(), coverage);
+                    outcomes.lock().expect("a test thread panicked")[idx] = Some(outcome);
+                }
+            };
+            thread::Builder::new().stack_size(STACK_SIZE).spawn_scoped(scope, worker).expect("failed to start a test thread");
+        }
+    });
+    outcomes.into_inner().expect("a test thread panicked").into_iter().map(|outcome| outcome.expect("every test runs")).collect()
+}
+
+/// The results in the JUnit XML format read by CI servers, with a
+/// `testsuite` per file.
+pub fn junit(suites: &[Suite], tests: &[Test], outcomes: &[Outcome]) -> String {
+    let failures = outcomes.iter().filter(|outcome| outcome.result.is_err()).count();
+    let total: Duration = outcomes.iter().map(|outcome| outcome.time).sum();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(out, "<testsuites name=\"fork\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">", tests.len(), failures, total.as_secs_f64());
+    for (idx, suite) in suites.iter().enumerate() {
+        let cases: Vec<(&Test, &Outcome)> = tests.iter().zip(outcomes).filter(|(test, _)| test.suite == idx).collect();
+        if cases.is_empty() {
+            continue;
+        }
+        let failures = cases.iter().filter(|(_, outcome)| outcome.result.is_err()).count();
+        let time: Duration = cases.iter().map(|(_, outcome)| outcome.time).sum();
+        let _ = writeln!(
+            out,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+            escape(&suite.path),
+            cases.len(),
+            failures,
+            time.as_secs_f64()
+        );
+        for (test, outcome) in cases {
+            let name = test.name.as_deref().unwrap_or(&suite.path);
+            let head = format!("    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"", escape(name), escape(&suite.path), outcome.time.as_secs_f64());
+            match &outcome.result {
+                Ok(()) => {
+                    let _ = writeln!(out, "{}/>", head);
+                }
+                Err(failure) => {
+                    let detail = match &failure.location {
+                        Some((line, text)) => format!("{}:{}: {}", suite.path, line, text),
+                        None => failure.message.clone(),
+                    };
+                    let _ = writeln!(out, "{}>", head);
+                    let _ = writeln!(out, "      <failure message=\"{}\">{}</failure>", escape(&failure.message), escape(&detail));
+                    let _ = writeln!(out, "    </testcase>");
+                }
+            }
+        }
+        let _ = writeln!(out, "  </testsuite>");
+    }
+    let _ = writeln!(out, "</testsuites>");
+    out
+}
@@ -13,6 +13,21 @@ pub enum Token {
     Symbol(char),
     /// Whitespace (for token stream separation)
     Whitespace,
+    /// A `//` comment running to the end of the line, without the `//`
+    Comment(String),
     /// Unknown or invalid tokens
     Unknown(String),
 }
+
+/// Location of a token in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    /// Byte offset of the first character.
+    pub start: usize,
+    /// Byte offset one past the last character.
+    pub end: usize,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number.
+    pub col: usize,
+}
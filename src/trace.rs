@@ -0,0 +1,85 @@
+//! `--trace`: logs every statement the interpreter runs and every variable
+//! it assigns, as text or as JSON Lines.
+use std::io::Write;
+use std::ops::RangeInclusive;
+use crate::ast::Stmt;
+use crate::debugger::show;
+use crate::interpreter::debug::{Debugger, Event};
+use crate::interpreter::Interpreter;
+use crate::json::Json;
+use crate::value::{RuntimeError, Value};
+
+/// Which events are logged.
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Only events on these lines.
+    pub lines: Option<RangeInclusive<usize>>,
+    /// Only writes to these variables. Statements are not logged when set.
+    pub names: Vec<String>,
+}
+
+impl Filter {
+    fn line(&self, line: usize) -> bool {
+        self.lines.as_ref().is_none_or(|lines| lines.contains(&line))
+    }
+}
+
+pub struct Tracer {
+    out: Box<dyn Write>,
+    /// Whether to write JSON Lines rather than text.
+    json: bool,
+    filter: Filter,
+    source: Vec<String>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, json: bool, filter: Filter, source: &str) -> Self {
+        Tracer { out, json, filter, source: source.lines().map(|line| line.trim().to_string()).collect() }
+    }
+
+    fn emit(&mut self, text: String, record: Json) -> Result<(), RuntimeError> {
+        let result = if self.json { writeln!(self.out, "{}", record) } else { writeln!(self.out, "{}", text) };
+        result.map_err(|e| RuntimeError { message: format!("Error writing trace: {}", e), line: None })
+    }
+}
+
+impl Debugger for Tracer {
+    fn on_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt, event: Event) -> Result<(), RuntimeError> {
+        let line = stmt.span.line;
+        if event == Event::After || !self.filter.names.is_empty() || !self.filter.line(line) {
+            return Ok(());
+        }
+        let text = self.source.get(line - 1).cloned().unwrap_or_default();
+        let record = Json::object([
+            ("event", "statement".into()),
+            ("line", line.into()),
+            ("function", interpreter.function().into()),
+            ("source", text.as_str().into()),
+        ]);
+        self.emit(format!("[line {}] {}", line, text), record)
+    }
+
+    fn write(&mut self, name: &str, old: Option<&Value>, new: &Value, line: usize) -> Result<(), RuntimeError> {
+        let listed = self.filter.names.is_empty() || self.filter.names.iter().any(|listed| listed == name);
+        if !listed || !self.filter.line(line) {
+            return Ok(());
+        }
+        let record = Json::object([
+            ("event", "write".into()),
+            ("line", line.into()),
+            ("name", name.into()),
+            ("old", old.map(json_value).into()),
+            ("new", json_value(new)),
+        ]);
+        self.emit(format!("[line {}] {}: {} -> {}", line, name, show(old), show(Some(new))), record)
+    }
+}
+
+fn json_value(value: &Value) -> Json {
+    match value {
+        Value::Number(n) => Json::Number(*n),
+        Value::Str(s) => Json::Str(s.clone()),
+        Value::List(items) => Json::Array(items.iter().map(json_value).collect()),
+        Value::Dict(entries) => Json::Object(entries.iter().map(|(key, value)| (key.clone(), json_value(value))).collect()),
+    }
+}
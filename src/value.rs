@@ -0,0 +1,138 @@
+//! Runtime values and errors shared by the interpreter and the virtual machine.
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::ast::BinOp;
+
+/// A runtime value. Scripts have no syntax for lists and dicts; they are
+/// made by native functions and passed around like other values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Str(String),
+    List(Vec<Value>),
+    /// Entries in key order.
+    Dict(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Numbers are truthy unless zero; strings, lists and dicts unless empty.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Number(n) => *n != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::List(items) => !items.is_empty(),
+            Value::Dict(entries) => !entries.is_empty(),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Dict(_) => "dict",
+        }
+    }
+
+    /// Bytes the value holds on the heap: the text of a string, or the items
+    /// of a list or dict with what they hold in turn.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Value::Number(_) => 0,
+            Value::Str(s) => s.len(),
+            Value::List(items) => items.iter().map(|item| size_of::<Value>() + item.heap_size()).sum(),
+            Value::Dict(entries) => entries
+                .iter()
+                .map(|(key, value)| size_of::<String>() + key.len() + size_of::<Value>() + value.heap_size())
+                .sum(),
+        }
+    }
+
+    /// The value as an item of a list or dict: strings are quoted.
+    fn fmt_item(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_item(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Dict(entries) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: ", key)?;
+                    value.fmt_item(f)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Applies a binary operator. Comparisons produce `1` or `0`; only `==` and
+/// `!=` accept strings.
+pub fn binary(op: BinOp, lhs: &Value, rhs: &Value) -> Result<Value, String> {
+    let truth = |b: bool| Value::Number(if b { 1.0 } else { 0.0 });
+    match (op, lhs, rhs) {
+        (BinOp::Eq, _, _) => Ok(truth(lhs == rhs)),
+        (BinOp::Ne, _, _) => Ok(truth(lhs != rhs)),
+        (_, Value::Number(a), Value::Number(b)) => Ok(match op {
+            BinOp::Add => Value::Number(a + b),
+            BinOp::Sub => Value::Number(a - b),
+            BinOp::Mul => Value::Number(a * b),
+            BinOp::Div => Value::Number(a / b),
+            BinOp::Lt => truth(a < b),
+            BinOp::Gt => truth(a > b),
+            BinOp::Le => truth(a <= b),
+            _ => truth(a >= b),
+        }),
+        _ => Err(format!(
+            "TypeError: unsupported operand types for {}: {} and {}",
+            op.symbol(),
+            lhs.type_name(),
+            rhs.type_name()
+        )),
+    }
+}
+
+/// Applies unary minus.
+pub fn negate(value: &Value) -> Result<Value, String> {
+    match value {
+        Value::Number(n) => Ok(Value::Number(-n)),
+        _ => Err(format!("TypeError: bad operand type for unary -: {}", value.type_name())),
+    }
+}
+
+/// Error that stops execution, with the source line it happened on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {})", self.message, line),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
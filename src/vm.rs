@@ -0,0 +1,190 @@
+//! Stack virtual machine that executes compiled bytecode.
+use crate::ast::BinOp;
+use crate::bytecode::{Constant, Function, Module, Op};
+use crate::value::{self, RuntimeError, Value};
+
+/// Calls nested deeper than this raise a `RecursionError`.
+const MAX_CALL_DEPTH: usize = 1000;
+
+pub struct Vm;
+
+/// An active function call.
+struct Frame<'m> {
+    function: &'m Function,
+    pc: usize,
+    locals: Vec<Option<Value>>,
+}
+
+impl<'m> Frame<'m> {
+    fn new(function: &'m Function) -> Self {
+        Frame { function, pc: 0, locals: vec![None; function.locals.len()] }
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm
+    }
+
+    /// Runs the module's `<main>` function to completion.
+    pub fn run(&self, module: &Module) -> Result<(), RuntimeError> {
+        match module.functions.first() {
+            Some(main) => self.execute(module, main),
+            None => Ok(()),
+        }
+    }
+
+    fn execute(&self, module: &Module, main: &Function) -> Result<(), RuntimeError> {
+        let mut frames = vec![Frame::new(main)];
+        let mut stack: Vec<Value> = Vec::new();
+        while let Some(frame) = frames.last_mut() {
+            let function = frame.function;
+            let code = &function.code;
+            let offset = frame.pc;
+            if offset >= code.len() {
+                // Falling off the end of a function returns `0`
+                stack.push(Value::Number(0.0));
+                if return_from(&mut frames, &mut stack) {
+                    return Ok(());
+                }
+                continue;
+            }
+            let fail = |message: String| RuntimeError { message, line: function.line_at(offset) };
+            let op = Op::from_byte(code[offset]).ok_or_else(|| fail(format!("BytecodeError: unknown opcode {}", code[offset])))?;
+            let width = op.operand_width();
+            let operand_bytes = code
+                .get(offset + 1..offset + 1 + width)
+                .ok_or_else(|| fail("BytecodeError: truncated instruction".to_string()))?;
+            let operand = operand_bytes.iter().rev().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+            frame.pc += 1 + width;
+            let pop = |stack: &mut Vec<Value>| stack.pop().ok_or_else(|| fail("BytecodeError: stack underflow".to_string()));
+            match op {
+                Op::Const => {
+                    let constant = function
+                        .constants
+                        .get(operand)
+                        .ok_or_else(|| fail(format!("BytecodeError: no constant {}", operand)))?;
+                    stack.push(match constant {
+                        Constant::Number(n) => Value::Number(*n),
+                        Constant::Str(s) => Value::Str(s.clone()),
+                    });
+                }
+                Op::Load => {
+                    let value = frame.locals.get(operand).cloned().flatten().ok_or_else(|| {
+                        let name = function.locals.get(operand).map_or("?", |name| source_name(name));
+                        fail(format!("NameError: name '{}' is not defined", name))
+                    })?;
+                    stack.push(value);
+                }
+                Op::Store => {
+                    let value = pop(&mut stack)?;
+                    let slot = frame
+                        .locals
+                        .get_mut(operand)
+                        .ok_or_else(|| fail(format!("BytecodeError: no local slot {}", operand)))?;
+                    *slot = Some(value);
+                }
+                Op::Neg => {
+                    let result = value::negate(&pop(&mut stack)?).map_err(fail)?;
+                    stack.push(result);
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Lt | Op::Gt | Op::Le | Op::Ge | Op::Eq | Op::Ne => {
+                    let rhs = pop(&mut stack)?;
+                    let lhs = pop(&mut stack)?;
+                    let result = value::binary(binary_op(op), &lhs, &rhs).map_err(fail)?;
+                    stack.push(result);
+                }
+                Op::Jump => frame.pc = operand,
+                Op::JumpIfFalse => {
+                    if !pop(&mut stack)?.is_truthy() {
+                        frame.pc = operand;
+                    }
+                }
+                Op::Print => {
+                    let mut args = Vec::with_capacity(operand);
+                    for _ in 0..operand {
+                        args.push(pop(&mut stack)?.to_string());
+                    }
+                    args.reverse();
+                    println!("{}", args.join(" "));
+                }
+                Op::Call => {
+                    let callee_idx = operand & 0xffff;
+                    let argc = operand >> 16;
+                    let callee = module
+                        .functions
+                        .get(callee_idx)
+                        .ok_or_else(|| fail(format!("BytecodeError: no function {}", callee_idx)))?;
+                    if argc != callee.arity as usize {
+                        return Err(fail(format!(
+                            "TypeError: {}() takes {} arguments but {} were given",
+                            callee.name, callee.arity, argc
+                        )));
+                    }
+                    if frames.len() > MAX_CALL_DEPTH {
+                        return Err(fail("RecursionError: maximum recursion depth exceeded".to_string()));
+                    }
+                    let mut callee_frame = Frame::new(callee);
+                    // Parameters occupy the first local slots
+                    for slot in (0..argc).rev() {
+                        let arg = pop(&mut stack)?;
+                        let local = callee_frame
+                            .locals
+                            .get_mut(slot)
+                            .ok_or_else(|| fail(format!("BytecodeError: no local slot {}", slot)))?;
+                        *local = Some(arg);
+                    }
+                    frames.push(callee_frame);
+                }
+                Op::Pop => {
+                    pop(&mut stack)?;
+                }
+                Op::Return => {
+                    if return_from(&mut frames, &mut stack) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pops the current frame, leaving its return value on the stack for the caller.
+/// Returns `true` once `<main>` itself has returned.
+fn return_from(frames: &mut Vec<Frame>, stack: &mut Vec<Value>) -> bool {
+    frames.pop();
+    if frames.is_empty() {
+        stack.clear();
+        return true;
+    }
+    false
+}
+
+/// The operator an arithmetic or comparison opcode applies.
+fn binary_op(op: Op) -> BinOp {
+    match op {
+        Op::Add => BinOp::Add,
+        Op::Sub => BinOp::Sub,
+        Op::Mul => BinOp::Mul,
+        Op::Div => BinOp::Div,
+        Op::Lt => BinOp::Lt,
+        Op::Gt => BinOp::Gt,
+        Op::Le => BinOp::Le,
+        Op::Ge => BinOp::Ge,
+        Op::Eq => BinOp::Eq,
+        Op::Ne => BinOp::Ne,
+        _ => unreachable!("{} is not a binary operator", op.name()),
+    }
+}
+
+/// The source variable a local slot belongs to: `x.2` is a version of `x`.
+fn source_name(local: &str) -> &str {
+    local.split('.').next().unwrap_or(local)
+}
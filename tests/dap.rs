@@ -0,0 +1,84 @@
+//! Recorded Debug Adapter Protocol sessions with `fork dap`.
+//!
+//! Each transcript in `tests/dap/` lists the requests a client sends, as
+//! lines starting with `->`, and the messages the adapter answers with, as
+//! lines starting with `<-`. The requests are replayed and the answers must
+//! match exactly. Run with `RECORD_DAP=1` to rewrite the answers instead.
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Sends `requests` to an adapter, framed, and returns the bodies of the
+/// messages it sends back.
+fn session(requests: &[&str]) -> Vec<String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fork"))
+        .arg("dap")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run fork dap");
+    let mut stdin = child.stdin.take().unwrap();
+    for request in requests {
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", request.len(), request).unwrap();
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let mut rest = String::from_utf8(output.stdout).unwrap();
+    let mut messages = Vec::new();
+    while let Some(header_end) = rest.find("\r\n\r\n") {
+        let length: usize = rest[..header_end].trim_start_matches("Content-Length: ").parse().unwrap();
+        let body_start = header_end + 4;
+        messages.push(rest[body_start..body_start + length].to_string());
+        rest = rest[body_start + length..].to_string();
+    }
+    messages
+}
+
+fn replay(name: &str) {
+    let path = format!("tests/dap/{}.txt", name);
+    let transcript = fs::read_to_string(&path).unwrap();
+    let requests: Vec<&str> = transcript.lines().filter_map(|line| line.strip_prefix("-> ")).collect();
+    let expected: Vec<&str> = transcript.lines().filter_map(|line| line.strip_prefix("<- ")).collect();
+    let actual = session(&requests);
+    if std::env::var_os("RECORD_DAP").is_some() {
+        let mut recorded: Vec<String> = transcript.lines().filter(|line| !line.starts_with("<- ")).map(str::to_string).collect();
+        recorded.extend(actual.iter().map(|message| format!("<- {}", message)));
+        fs::write(&path, recorded.join("\n") + "\n").unwrap();
+        return;
+    }
+    for (idx, (actual, expected)) in actual.iter().zip(&expected).enumerate() {
+        assert_eq!(actual, expected, "message {} of {}", idx + 1, path);
+    }
+    assert_eq!(actual.len(), expected.len(), "number of messages in {}", path);
+}
+
+#[test]
+fn breakpoints_and_stepping() {
+    replay("breakpoints");
+}
+
+#[test]
+fn stepping_into_calls() {
+    replay("step_in");
+}
+
+#[test]
+fn evaluate_in_frames() {
+    replay("evaluate");
+}
+
+#[test]
+fn terminate_from_a_pause() {
+    replay("terminate");
+}
+
+#[test]
+fn runtime_errors_end_the_session() {
+    replay("runtime_error");
+}
+
+#[test]
+fn invalid_requests_fail() {
+    replay("invalid");
+}
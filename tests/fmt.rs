@@ -0,0 +1,137 @@
+//! Tests of `fork fmt`, run against the built binary.
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn fork(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fork")).args(args).output().expect("failed to run fork")
+}
+
+/// Writes `source` to a fresh `.fork` file in the temporary directory.
+fn temp_file(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fork-fmt-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.fork", name));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// Formats `source` through a file and returns the result.
+fn format(name: &str, source: &str) -> String {
+    let path = temp_file(name, source);
+    let output = fork(&["fmt", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    fs::read_to_string(&path).unwrap()
+}
+
+/// Formatting twice gives the same text as formatting once.
+fn assert_idempotent(name: &str, source: &str) {
+    let once = format(name, source);
+    let twice = format(name, &once);
+    assert_eq!(once, twice, "formatting {} again changed it", name);
+}
+
+/// The ```fork code blocks of the README.
+fn readme_examples() -> Vec<String> {
+    let readme = fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/README.md")).unwrap();
+    let mut examples = Vec::new();
+    let mut current: Option<String> = None;
+    for line in readme.lines() {
+        match current.as_mut() {
+            None if line.trim() == "```fork" => current = Some(String::new()),
+            Some(_) if line.trim() == "```" => examples.extend(current.take()),
+            Some(example) => {
+                example.push_str(line);
+                example.push('\n');
+            }
+            None => {}
+        }
+    }
+    examples
+}
+
+#[test]
+fn example_program_is_formatted() {
+    let output = fork(&["fmt", "--check", concat!(env!("CARGO_MANIFEST_DIR"), "/src/test.fork")]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn example_program_is_idempotent() {
+    let source = fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/src/test.fork")).unwrap();
+    assert_idempotent("test", &source);
+}
+
+#[test]
+fn readme_examples_are_idempotent() {
+    let examples = readme_examples();
+    assert!(!examples.is_empty());
+    for (idx, example) in examples.iter().enumerate() {
+        assert_idempotent(&format!("readme-{}", idx), example);
+    }
+}
+
+#[test]
+fn canonical_style() {
+    let source = "x=1+2*3;\nif(x>1){print(x ,\"big\");}\nelse if(x==2){ y = -(x+1); }\nelse{x--;}\n";
+    let expected = "\
+x = 1 + 2 * 3;
+if (x > 1) {
+    print(x, \"big\");
+} else if (x == 2) {
+    y = -(x + 1);
+} else {
+    x--;
+}
+";
+    assert_eq!(format("style", source), expected);
+}
+
+#[test]
+fn keeps_comments() {
+    let source = "\
+// header
+
+
+x = 1;   // trailing
+def f(a) {
+  // inside
+  return (a - (a - 1)) / 2;
+}
+for (int i = 0; i < 3; i++) { x = f(i); } // after loop
+// last
+";
+    let expected = "\
+// header
+
+x = 1;  // trailing
+def f(a) {
+    // inside
+    return (a - (a - 1)) / 2;
+}
+for (int i = 0; i < 3; i++) {
+    x = f(i);
+}  // after loop
+// last
+";
+    assert_eq!(format("comments", source), expected);
+    assert_idempotent("comments", source);
+}
+
+#[test]
+fn check_reports_without_writing() {
+    let source = "x=1;\n";
+    let path = temp_file("check", source);
+    let output = fork(&["fmt", "--check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("not formatted"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), source);
+}
+
+#[test]
+fn syntax_errors_are_reported() {
+    let path = temp_file("error", "x = ;\n");
+    let output = fork(&["fmt", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("SyntaxError"));
+}
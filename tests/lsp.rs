@@ -0,0 +1,197 @@
+//! Scripted JSON-RPC sessions with `fork lsp`.
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const URI: &str = "file:///test.fork";
+
+const SOURCE: &str = "x = 1;\ndef add(a: int, b) -> int {\n    c = a + b;\n    return c;\n}\ny = add(x, 2);\nprint(y, x);\n";
+
+/// Sends `messages` to a server, framed, and returns the bodies of its
+/// replies together with its exit status.
+fn session(messages: &[String]) -> (Vec<String>, Option<i32>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fork"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run fork lsp");
+    let mut stdin = child.stdin.take().unwrap();
+    for message in messages {
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", message.len(), message).unwrap();
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    let mut rest = String::from_utf8(output.stdout).unwrap();
+    let mut replies = Vec::new();
+    while let Some(header_end) = rest.find("\r\n\r\n") {
+        let length: usize = rest[..header_end].trim_start_matches("Content-Length: ").parse().unwrap();
+        let body_start = header_end + 4;
+        replies.push(rest[body_start..body_start + length].to_string());
+        rest = rest[body_start + length..].to_string();
+    }
+    (replies, output.status.code())
+}
+
+fn request(id: usize, method: &str, params: &str) -> String {
+    format!(r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#, id, method, params)
+}
+
+fn notification(method: &str, params: &str) -> String {
+    format!(r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#, method, params)
+}
+
+fn open(text: &str) -> String {
+    let text = text.replace('\n', "\\n");
+    notification("textDocument/didOpen", &format!(r#"{{"textDocument":{{"uri":"{}","languageId":"fork","version":1,"text":"{}"}}}}"#, URI, text))
+}
+
+fn at(line: usize, character: usize) -> String {
+    format!(r#"{{"textDocument":{{"uri":"{}"}},"position":{{"line":{},"character":{}}}}}"#, URI, line, character)
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> String {
+    format!(r#""range":{{"start":{{"line":{},"character":{}}},"end":{{"line":{},"character":{}}}}}"#, start.0, start.1, end.0, end.1)
+}
+
+/// Runs `requests` on `SOURCE` between the start and end of a session, and
+/// returns the replies to them.
+fn query(requests: &[String]) -> Vec<String> {
+    let mut messages = vec![request(0, "initialize", "{}"), notification("initialized", "{}"), open(SOURCE)];
+    messages.extend_from_slice(requests);
+    messages.push(request(99, "shutdown", "null"));
+    messages.push(notification("exit", "null"));
+    let (replies, status) = session(&messages);
+    assert_eq!(status, Some(0));
+    replies.into_iter().filter(|reply| reply.contains(r#""id":"#) && !reply.contains(r#""id":0,"#) && !reply.contains(r#""id":99,"#)).collect()
+}
+
+#[test]
+fn initialize_announces_capabilities() {
+    let (replies, _) = session(&[request(1, "initialize", "{}")]);
+    assert!(replies[0].starts_with(r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"textDocumentSync":1,"#));
+    for capability in ["definitionProvider", "referencesProvider", "hoverProvider", "documentSymbolProvider", "completionProvider", "documentFormattingProvider"] {
+        assert!(replies[0].contains(capability), "missing {}", capability);
+    }
+}
+
+#[test]
+fn exit_status_follows_shutdown() {
+    let (_, status) = session(&[request(1, "initialize", "{}"), request(2, "shutdown", "null"), notification("exit", "null")]);
+    assert_eq!(status, Some(0));
+    let (_, status) = session(&[request(1, "initialize", "{}"), notification("exit", "null")]);
+    assert_eq!(status, Some(1));
+}
+
+#[test]
+fn requests_before_initialize_fail() {
+    let (replies, _) = session(&[request(1, "textDocument/hover", &at(0, 0))]);
+    assert!(replies[0].contains(r#""error":{"code":-32002,"#));
+}
+
+#[test]
+fn unknown_methods_fail() {
+    let replies = query(&[request(1, "workspace/unknown", "{}")]);
+    assert!(replies[0].contains(r#""error":{"code":-32601,"#));
+}
+
+#[test]
+fn diagnostics_follow_edits() {
+    let change = |version: usize, text: &str| {
+        let params = format!(r#"{{"textDocument":{{"uri":"{}","version":{}}},"contentChanges":[{{"text":"{}"}}]}}"#, URI, version, text);
+        notification("textDocument/didChange", &params)
+    };
+    let messages = [
+        request(1, "initialize", "{}"),
+        open(SOURCE),
+        change(2, "print(z);"),
+        change(3, "x = ;"),
+        change(4, "x = 1;"),
+        notification("textDocument/didClose", &format!(r#"{{"textDocument":{{"uri":"{}"}}}}"#, URI)),
+    ];
+    let (replies, _) = session(&messages);
+    let diagnostics: Vec<&String> = replies.iter().filter(|reply| reply.contains("publishDiagnostics")).collect();
+    assert_eq!(diagnostics.len(), 5);
+    assert!(diagnostics[0].contains(r#""diagnostics":[]"#));
+    let undefined = format!(r#"{{{},"severity":1,"source":"fork","message":"NameError: name 'z' is not defined"}}"#, range((0, 6), (0, 7)));
+    assert!(diagnostics[1].contains(&undefined), "{}", diagnostics[1]);
+    assert!(diagnostics[2].contains("SyntaxError: expected an expression, found symbol ';'"));
+    assert!(diagnostics[3].contains(r#""diagnostics":[]"#));
+    assert!(diagnostics[4].contains(r#""diagnostics":[]"#));
+}
+
+#[test]
+fn definition_of_variables_and_functions() {
+    let replies = query(&[request(1, "textDocument/definition", &at(6, 9)), request(2, "textDocument/definition", &at(2, 8)), request(3, "textDocument/definition", &at(5, 5))]);
+    // `x` in the print statement is first assigned on line 0
+    assert!(replies[0].contains(&range((0, 0), (0, 1))), "{}", replies[0]);
+    // `a` in the body is the parameter
+    assert!(replies[1].contains(&range((1, 8), (1, 9))), "{}", replies[1]);
+    // `add` is the name after `def`
+    assert!(replies[2].contains(&range((1, 4), (1, 7))), "{}", replies[2]);
+}
+
+#[test]
+fn references_of_a_variable() {
+    let params = at(0, 0).replace("}}", r#"},"context":{"includeDeclaration":false}}"#);
+    let replies = query(&[request(1, "textDocument/references", &at(0, 0)), request(2, "textDocument/references", &params)]);
+    for expected in [range((0, 0), (0, 1)), range((5, 8), (5, 9)), range((6, 9), (6, 10))] {
+        assert!(replies[0].contains(&expected), "{}", replies[0]);
+    }
+    assert!(!replies[1].contains(&range((0, 0), (0, 1))), "{}", replies[1]);
+    assert!(replies[1].contains(&range((5, 8), (5, 9))), "{}", replies[1]);
+}
+
+#[test]
+fn references_of_a_function() {
+    let replies = query(&[request(1, "textDocument/references", &at(5, 4))]);
+    assert_eq!(replies[0].matches(r#""uri":"#).count(), 2, "{}", replies[0]);
+    assert!(replies[0].contains(&range((1, 4), (1, 7))));
+    assert!(replies[0].contains(&range((5, 4), (5, 7))));
+}
+
+#[test]
+fn hover_shows_inferred_types() {
+    let replies = query(&[
+        request(1, "textDocument/hover", &at(5, 0)),
+        request(2, "textDocument/hover", &at(2, 4)),
+        request(3, "textDocument/hover", &at(5, 5)),
+        request(4, "textDocument/hover", &at(3, 0)),
+    ]);
+    assert!(replies[0].contains(r#"(variable) y: int"#), "{}", replies[0]);
+    assert!(replies[1].contains(r#"(variable) c: any"#), "{}", replies[1]);
+    assert!(replies[2].contains(r#"def add(a: int, b) -> int"#), "{}", replies[2]);
+    assert!(replies[3].contains(r#""result":null"#), "{}", replies[3]);
+}
+
+#[test]
+fn document_symbols() {
+    let replies = query(&[request(1, "textDocument/documentSymbol", &format!(r#"{{"textDocument":{{"uri":"{}"}}}}"#, URI))]);
+    let symbols = &replies[0];
+    assert!(symbols.contains(r#"{"name":"add","detail":"def add(a: int, b) -> int","kind":12,"#), "{}", symbols);
+    for name in ["a", "b", "c", "x", "y"] {
+        assert!(symbols.contains(&format!(r#"{{"name":"{}","kind":13,"#, name)), "missing {} in {}", name, symbols);
+    }
+}
+
+#[test]
+fn completion_offers_names_in_scope() {
+    let replies = query(&[request(1, "textDocument/completion", &at(3, 4)), request(2, "textDocument/completion", &at(6, 0))]);
+    for label in ["while", "return", "add", "a", "c"] {
+        assert!(replies[0].contains(&format!(r#""label":"{}""#, label)), "missing {} in {}", label, replies[0]);
+    }
+    assert!(!replies[0].contains(r#""label":"x""#));
+    assert!(replies[1].contains(r#""label":"x""#));
+    assert!(!replies[1].contains(r#""label":"c""#));
+}
+
+#[test]
+fn formatting_replaces_the_document() {
+    let messages = [
+        request(1, "initialize", "{}"),
+        open("x=1;\nif(x){print(x);}\n"),
+        request(2, "textDocument/formatting", &format!(r#"{{"textDocument":{{"uri":"{}"}},"options":{{"tabSize":4,"insertSpaces":true}}}}"#, URI)),
+    ];
+    let (replies, _) = session(&messages);
+    let edit = format!(r#""result":[{{{},"newText":"x = 1;\nif (x) {{\n    print(x);\n}}\n"}}]"#, range((0, 0), (2, 0)));
+    assert!(replies[2].contains(&edit), "{}", replies[2]);
+}